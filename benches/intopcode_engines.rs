@@ -0,0 +1,78 @@
+// Compares IntOpCodeComp against FastIntOpCodeComp on the heaviest Intcode workloads.
+// Run with: cargo bench --bench intopcode_engines
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use adventofcode2019::IntOpCodeComp;
+use adventofcode2019::intopcode_fast::FastIntOpCodeComp;
+
+const REPEATS: usize = 5;
+
+fn load_program(filename: &str) -> Vec<i64> {
+    std::fs::read_to_string(filename).unwrap()
+        .trim()
+        .split(',')
+        .map(|v| v.parse().unwrap())
+        .collect()
+}
+
+// Fastest of a few runs, the closest to the real cost without noise from the rest of the machine
+fn time<F: FnMut() -> i64>(mut f: F) -> (Duration, i64) {
+    let mut best = Duration::MAX;
+    let mut result = 0;
+    for _ in 0..REPEATS {
+        let start = Instant::now();
+        result = f();
+        best = best.min(start.elapsed());
+    }
+    (best, result)
+}
+
+fn report(name: &str, reference: (Duration, i64), fast: (Duration, i64)) {
+    assert_eq!(reference.1, fast.1, "{} gave different results", name);
+    println!("{:<28} reference {:>10.2?}   fast {:>10.2?}   speedup {:>5.2}x",
+        name, reference.0, fast.0, reference.0.as_secs_f64() / fast.0.as_secs_f64());
+}
+
+fn main() {
+    // Day 2 part 2 tries all 10,000 noun/verb pairs
+    let gravity_assist = load_program("aocday2.txt");
+    let reference = time(|| {
+        let mut checksum = 0;
+        let mut program = gravity_assist.clone();
+        for noun in 0..100 {
+            for verb in 0..100 {
+                program[1] = noun;
+                program[2] = verb;
+                let mut comp = IntOpCodeComp::new(&program, &VecDeque::new());
+                if comp.tick_to_completion().is_ok() {
+                    checksum += comp.memory().read(0);
+                }
+            }
+        }
+        checksum
+    });
+    let fast = time(|| {
+        let mut checksum = 0;
+        let base = FastIntOpCodeComp::new(&gravity_assist, &VecDeque::new());
+        for noun in 0..100 {
+            for verb in 0..100 {
+                let mut comp = base.clone();
+                comp.write_memory(1, noun).unwrap();
+                comp.write_memory(2, verb).unwrap();
+                if comp.tick_to_completion().is_ok() {
+                    checksum += comp.memory()[0];
+                }
+            }
+        }
+        checksum
+    });
+    report("day 2 noun/verb search", reference, fast);
+
+    // Day 9 part 2 runs a single long program
+    let boost = load_program("aocday9.txt");
+    let input = VecDeque::from(vec![2]);
+    let reference = time(|| *IntOpCodeComp::new(&boost, &input).tick_to_completion().unwrap().back().unwrap());
+    let fast = time(|| *FastIntOpCodeComp::new(&boost, &input).tick_to_completion().unwrap().back().unwrap());
+    report("day 9 BOOST sensor mode", reference, fast);
+}
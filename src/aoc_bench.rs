@@ -0,0 +1,392 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::io::Write;
+use std::path::Path;
+use std::str::CharIndices;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::aoc_output::json_string;
+use crate::aoc_registry::Registry;
+
+pub const STAGES: [&str; 3] = ["parse", "part1", "part2"];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    pub min: Duration,
+    pub median: Duration,
+    pub max: Duration,
+}
+
+impl Stats {
+    // None without any samples
+    pub fn from_samples(samples: &[Duration]) -> Option<Stats> {
+        let mut sorted = samples.to_vec();
+        sorted.sort();
+        let (&min, &max) = (sorted.first()?, sorted.last()?);
+        let middle = sorted.len() / 2;
+        let median = if sorted.len() % 2 == 0 { (sorted[middle - 1] + sorted[middle]) / 2 } else { sorted[middle] };
+        Some(Stats { min, median, max })
+    }
+}
+
+// Timings of one day, by stage in STAGES order
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DayBenchmark {
+    pub day: u32,
+    pub stages: [Stats; 3],
+}
+
+// One run of the benchmarks as kept in the history
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BenchmarkRun {
+    // Seconds since the Unix epoch
+    pub timestamp: u64,
+    // Short hash of the checked out commit, if there is one
+    pub commit: Option<String>,
+    pub iterations: usize,
+    pub days: Vec<DayBenchmark>,
+}
+
+pub fn current_commit() -> Option<String> {
+    let output = std::process::Command::new("git").args(["rev-parse", "--short", "HEAD"]).output().ok()?;
+    let commit = String::from_utf8(output.stdout).ok()?.trim().to_string();
+    if output.status.success() && !commit.is_empty() { Some(commit) } else { None }
+}
+
+// Solves every given day iterations times with its aocday<day>.txt input from the directory
+pub fn run_benchmarks(registry: &Registry, input_directory: &Path, days: &[u32], iterations: usize) -> Result<BenchmarkRun, Box<dyn Error>> {
+    let mut results = Vec::new();
+    for &day in days {
+        let filename = input_directory.join(format!("aocday{}.txt", day));
+        let input = fs::read_to_string(&filename).map_err(|error| format!("{}: {}", filename.display(), error))?;
+        let mut samples = [Vec::new(), Vec::new(), Vec::new()];
+        for _ in 0..iterations {
+            let times = registry.time(day, &input).map_err(|error| format!("day {}: {}", day, error))?;
+            for (stage, time) in samples.iter_mut().zip([times.parse, times.part1, times.part2]) {
+                stage.push(time);
+            }
+        }
+        let stats = |samples: &[Duration]| Stats::from_samples(samples).ok_or("benchmarks need at least one iteration");
+        results.push(DayBenchmark { day, stages: [stats(&samples[0])?, stats(&samples[1])?, stats(&samples[2])?] });
+    }
+
+    let timestamp = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |time| time.as_secs());
+    Ok(BenchmarkRun { timestamp, commit: current_commit(), iterations, days: results })
+}
+
+// The parts of JSON the history uses
+#[derive(Debug, Clone, PartialEq)]
+enum Json {
+    Null,
+    Number(f64),
+    Text(String),
+    Array(Vec<Json>),
+    Object(Vec<(String, Json)>),
+}
+
+impl Json {
+    fn get(&self, key: &str) -> Result<&Json, String> {
+        match self {
+            Json::Object(fields) => fields.iter().find(|(k, _)| k == key).map(|(_, v)| v).ok_or(format!("missing \"{}\"", key)),
+            _ => Err(format!("expected an object with \"{}\"", key)),
+        }
+    }
+
+    fn as_u64(&self) -> Result<u64, String> {
+        match *self {
+            Json::Number(n) if n >= 0.0 && n.fract() == 0.0 => Ok(n as u64),
+            _ => Err(String::from("expected a whole number")),
+        }
+    }
+}
+
+struct JsonParser<'a> {
+    text: &'a str,
+    position: usize,
+}
+
+impl JsonParser<'_> {
+    fn parse(text: &str) -> Result<Json, String> {
+        let mut parser = JsonParser { text, position: 0 };
+        let value = parser.value()?;
+        parser.skip_whitespace();
+        if parser.position != text.len() {
+            return Err(format!("unexpected text at column {}", parser.position + 1));
+        }
+        Ok(value)
+    }
+
+    fn skip_whitespace(&mut self) {
+        let rest = &self.text[self.position..];
+        self.position += rest.len() - rest.trim_start().len();
+    }
+
+    fn peek(&mut self) -> Option<char> {
+        self.skip_whitespace();
+        self.text[self.position..].chars().next()
+    }
+
+    fn expect(&mut self, c: char) -> Result<(), String> {
+        if self.peek() != Some(c) {
+            return Err(format!("expected '{}' at column {}", c, self.position + 1));
+        }
+        self.position += 1;
+        Ok(())
+    }
+
+    // Comma separated items up to the closing character
+    fn items<T, F: FnMut(&mut Self) -> Result<T, String>>(&mut self, close: char, mut item: F) -> Result<Vec<T>, String> {
+        let mut items = Vec::new();
+        if self.peek() == Some(close) {
+            self.position += 1;
+            return Ok(items);
+        }
+        loop {
+            items.push(item(self)?);
+            if self.peek() == Some(',') {
+                self.position += 1;
+            }
+            else {
+                self.expect(close)?;
+                return Ok(items);
+            }
+        }
+    }
+
+    // Four hex digits of a \u escape
+    fn hex_escape(chars: &mut CharIndices) -> Option<u32> {
+        let digits : String = chars.take(4).map(|(_, c)| c).collect();
+        if digits.len() == 4 && digits.chars().all(|c| c.is_ascii_hexdigit()) { u32::from_str_radix(&digits, 16).ok() } else { None }
+    }
+
+    // A \u escape, which takes two of them for characters outside the basic plane
+    fn unicode_escape(chars: &mut CharIndices) -> Option<char> {
+        let code = JsonParser::hex_escape(chars)?;
+        if !(0xD800..0xDC00).contains(&code) {
+            return char::from_u32(code);
+        }
+        let low = match (chars.next(), chars.next()) {
+            (Some((_, '\\')), Some((_, 'u'))) => JsonParser::hex_escape(chars).filter(|low| (0xDC00..0xE000).contains(low))?,
+            _ => return None,
+        };
+        char::from_u32(0x10000 + ((code - 0xD800) << 10) + (low - 0xDC00))
+    }
+
+    fn string(&mut self) -> Result<String, String> {
+        self.expect('"')?;
+        let start = self.position;
+        let error = |i: usize| format!("invalid escape at column {}", start + i + 1);
+        let mut text = String::new();
+        let mut chars = self.text[start..].char_indices();
+        while let Some((i, c)) = chars.next() {
+            match c {
+                '"' => {
+                    self.position = start + i + 1;
+                    return Ok(text);
+                },
+                '\\' => match chars.next() {
+                    Some((_, c @ ('"' | '\\' | '/'))) => text.push(c),
+                    Some((_, 'n')) => text.push('\n'),
+                    Some((_, 't')) => text.push('\t'),
+                    Some((_, 'r')) => text.push('\r'),
+                    Some((_, 'b')) => text.push('\u{8}'),
+                    Some((_, 'f')) => text.push('\u{c}'),
+                    Some((_, 'u')) => text.push(JsonParser::unicode_escape(&mut chars).ok_or_else(|| error(i))?),
+                    _ => return Err(error(i)),
+                },
+                c => text.push(c),
+            }
+        }
+        Err(String::from("unterminated string"))
+    }
+
+    fn value(&mut self) -> Result<Json, String> {
+        match self.peek() {
+            Some('{') => {
+                self.position += 1;
+                Ok(Json::Object(self.items('}', |parser| {
+                    let key = parser.string()?;
+                    parser.expect(':')?;
+                    Ok((key, parser.value()?))
+                })?))
+            },
+            Some('[') => {
+                self.position += 1;
+                Ok(Json::Array(self.items(']', |parser| parser.value())?))
+            },
+            Some('"') => Ok(Json::Text(self.string()?)),
+            Some('n') if self.text[self.position..].starts_with("null") => {
+                self.position += 4;
+                Ok(Json::Null)
+            },
+            Some(_) => {
+                let rest = &self.text[self.position..];
+                let end = rest.find(|c: char| !(c.is_ascii_digit() || "+-.eE".contains(c))).unwrap_or(rest.len());
+                let number = rest[..end].parse().map_err(|_| format!("expected a value at column {}", self.position + 1))?;
+                self.position += end;
+                Ok(Json::Number(number))
+            },
+            None => Err(String::from("unexpected end of line")),
+        }
+    }
+}
+
+impl BenchmarkRun {
+    // A single line of JSON, times in nanoseconds
+    pub fn to_json(&self) -> String {
+        let days : Vec<String> = self.days.iter().map(|day| {
+            let stages : Vec<String> = STAGES.iter().zip(&day.stages).map(|(name, stats)| {
+                format!("\"{}\":{{\"min_ns\":{},\"median_ns\":{},\"max_ns\":{}}}", name, stats.min.as_nanos(), stats.median.as_nanos(), stats.max.as_nanos())
+            }).collect();
+            format!("{{\"day\":{},{}}}", day.day, stages.join(","))
+        }).collect();
+        format!("{{\"timestamp\":{},\"commit\":{},\"iterations\":{},\"days\":[{}]}}",
+            self.timestamp, self.commit.as_deref().map_or(String::from("null"), json_string), self.iterations, days.join(","))
+    }
+
+    pub fn from_json(line: &str) -> Result<BenchmarkRun, String> {
+        let json = JsonParser::parse(line)?;
+        let commit = match json.get("commit")? {
+            Json::Text(commit) => Some(commit.clone()),
+            Json::Null => None,
+            _ => return Err(String::from("expected the commit to be text or null")),
+        };
+        let days = match json.get("days")? {
+            Json::Array(days) => days,
+            _ => return Err(String::from("expected a list of days")),
+        };
+
+        let nanos = |stats: &Json, key: &str| -> Result<Duration, String> { Ok(Duration::from_nanos(stats.get(key)?.as_u64()?)) };
+        let mut results = Vec::new();
+        for day in days {
+            let mut stages = Vec::new();
+            for name in STAGES.iter() {
+                let stats = day.get(name)?;
+                stages.push(Stats { min: nanos(stats, "min_ns")?, median: nanos(stats, "median_ns")?, max: nanos(stats, "max_ns")? });
+            }
+            results.push(DayBenchmark { day: day.get("day")?.as_u64()? as u32, stages: [stages[0], stages[1], stages[2]] });
+        }
+        Ok(BenchmarkRun { timestamp: json.get("timestamp")?.as_u64()?, commit, iterations: json.get("iterations")?.as_u64()? as usize, days: results })
+    }
+}
+
+// Every run saved in the history file, oldest first. A missing file is an empty history.
+pub fn read_history<P: AsRef<Path>>(path: P) -> Result<Vec<BenchmarkRun>, Box<dyn Error>> {
+    let path = path.as_ref();
+    let text = match fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(error) if error.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(error) => return Err(format!("{}: {}", path.display(), error).into()),
+    };
+    text.lines().enumerate().filter(|(_, line)| !line.trim().is_empty())
+        .map(|(i, line)| BenchmarkRun::from_json(line).map_err(|error| format!("{} line {}: {}", path.display(), i + 1, error).into()))
+        .collect()
+}
+
+pub fn append_history<P: AsRef<Path>>(path: P, run: &BenchmarkRun) -> io::Result<()> {
+    let mut file = fs::OpenOptions::new().create(true).append(true).open(path)?;
+    writeln!(file, "{}", run.to_json())
+}
+
+fn format_duration(duration: Duration) -> String {
+    let seconds = duration.as_secs_f64();
+    if seconds < 0.001 {
+        format!("{:.1} us", seconds * 1e6)
+    }
+    else if seconds < 1.0 {
+        format!("{:.2} ms", seconds * 1e3)
+    }
+    else {
+        format!("{:.3} s", seconds)
+    }
+}
+
+// A run next to the latest earlier run of each of its days
+pub struct BenchmarkReport<'a> {
+    pub run: &'a BenchmarkRun,
+    pub history: &'a [BenchmarkRun],
+}
+
+impl BenchmarkReport<'_> {
+    fn previous(&self, day: u32) -> Option<(&BenchmarkRun, &DayBenchmark)> {
+        self.history.iter().rev().find_map(|run| run.days.iter().find(|d| d.day == day).map(|d| (run, d)))
+    }
+}
+
+impl fmt::Display for BenchmarkReport<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{} iterations, commit {}", self.run.iterations, self.run.commit.as_deref().unwrap_or("unknown"))?;
+        writeln!(f, "{:>3}  {:<5} {:>10} {:>10} {:>10}  {:>10} {:>8}  against", "day", "stage", "min", "median", "max", "previous", "change")?;
+        for day in &self.run.days {
+            let previous = self.previous(day.day);
+            for (stage, (name, stats)) in STAGES.iter().zip(&day.stages).enumerate() {
+                write!(f, "{:>3}  {:<5} {:>10} {:>10} {:>10}", day.day, name, format_duration(stats.min), format_duration(stats.median), format_duration(stats.max))?;
+                if let Some((run, previous)) = previous {
+                    let (before, after) = (previous.stages[stage].median.as_secs_f64(), stats.median.as_secs_f64());
+                    let change = if before > 0.0 { format!("{:+.1}%", (after - before) / before * 100.0) } else { String::from("-") };
+                    write!(f, "  {:>10} {:>8}  {}", format_duration(previous.stages[stage].median), change, run.commit.as_deref().unwrap_or("unknown"))?;
+                }
+                writeln!(f)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(ms: u64) -> Duration {
+        Duration::from_millis(ms)
+    }
+
+    #[test]
+    fn stats_test() {
+        assert_eq!( Stats::from_samples(&[ms(5), ms(1), ms(3)]), Some(Stats { min: ms(1), median: ms(3), max: ms(5) }) );
+        assert_eq!( Stats::from_samples(&[ms(4), ms(1), ms(2), ms(8)]).unwrap().median, ms(3) );
+        assert_eq!( Stats::from_samples(&[]), None );
+    }
+
+    #[test]
+    fn run_benchmarks_test() {
+        let run = run_benchmarks(&Registry::all_days(), Path::new("."), &[1, 2], 3).unwrap();
+        assert_eq!( (run.iterations, run.days.len(), run.days[1].day), (3, 2, 2) );
+        assert!( run.days.iter().all(|day| day.stages.iter().all(|stats| stats.min <= stats.median && stats.median <= stats.max)) );
+        assert!( run_benchmarks(&Registry::all_days(), Path::new("."), &[15], 1).unwrap_err().to_string().starts_with("./aocday15.txt") );
+    }
+
+    #[test]
+    fn history_test() {
+        let stats = |median| Stats { min: ms(1), median: ms(median), max: ms(30) };
+        let day = |day, median| DayBenchmark { day, stages: [stats(2), stats(median), stats(10)] };
+        let old = BenchmarkRun { timestamp: 100, commit: Some(String::from("abc\"1")), iterations: 3, days: vec![day(1, 20), day(2, 4)] };
+        let new = BenchmarkRun { timestamp: 200, commit: None, iterations: 3, days: vec![day(1, 25)] };
+        assert_eq!( BenchmarkRun::from_json(&old.to_json()), Ok(old.clone()) );
+        assert_eq!( BenchmarkRun::from_json(&new.to_json()), Ok(new.clone()) );
+        assert_eq!( BenchmarkRun::from_json("{\"timestamp\":1}"), Err(String::from("missing \"commit\"")) );
+        assert_eq!( BenchmarkRun::from_json("{\"timestamp\":1,"), Err(String::from("expected '\"' at column 16")) );
+
+        // whatever the writer escapes, the reader takes back
+        let odd = BenchmarkRun { commit: Some(String::from("a\\b\"c\nd\te\u{1}\u{7f}é😀")), ..new.clone() };
+        assert_eq!( BenchmarkRun::from_json(&odd.to_json()), Ok(odd) );
+        let commit = |json: &str| BenchmarkRun::from_json(&format!("{{\"timestamp\":1,\"commit\":{},\"iterations\":1,\"days\":[]}}", json)).map(|run| run.commit.unwrap());
+        assert_eq!( commit("\"\\u00e9\\ud83d\\ude00\\/\\r\""), Ok(String::from("é😀/\r")) );
+        assert_eq!( commit("\"\\ud83d\""), Err(String::from("invalid escape at column 26")) );
+        assert_eq!( commit("\"\\x\""), Err(String::from("invalid escape at column 26")) );
+
+        let path = std::env::temp_dir().join(format!("aoc_bench_history_test_{}.jsonl", std::process::id()));
+        let _ = fs::remove_file(&path);
+        assert_eq!( read_history(&path).unwrap(), vec![] );
+        append_history(&path, &old).unwrap();
+        append_history(&path, &new).unwrap();
+        let history = read_history(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!( history, vec![old, new.clone()] );
+
+        let report = BenchmarkReport { run: &new, history: &history[..1] }.to_string();
+        assert!( report.contains("  1  part1    1.00 ms   25.00 ms   30.00 ms    20.00 ms   +25.0%  abc\"1\n") );
+    }
+}
@@ -0,0 +1,123 @@
+use std::str::FromStr;
+
+use crate::aoc_registry::{Answer, PartResult};
+
+// How puzzle results are printed. Text is for people, JSON and CSV are for scripts.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    #[default]
+    Text,
+    // One JSON object per line
+    Json,
+    // A header line, then one row per part
+    Csv,
+}
+
+impl OutputFormat {
+    // Text only prints the answers, so the details are left out
+    pub fn shows_details(self) -> bool {
+        self != OutputFormat::Text
+    }
+}
+
+impl FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(format: &str) -> Result<OutputFormat, String> {
+        match format {
+            "text" => Ok(OutputFormat::Text),
+            "json" => Ok(OutputFormat::Json),
+            "csv" => Ok(OutputFormat::Csv),
+            _ => Err(format!("--format is json, csv or text, not '{}'", format)),
+        }
+    }
+}
+
+pub(crate) fn json_string(text: &str) -> String {
+    let mut quoted = String::from("\"");
+    for c in text.chars() {
+        match c {
+            '"' => quoted += "\\\"",
+            '\\' => quoted += "\\\\",
+            '\n' => quoted += "\\n",
+            '\t' => quoted += "\\t",
+            c if c.is_control() => quoted += &format!("\\u{:04x}", c as u32),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+// Quoted only when it has to be, doubling any quotes inside
+fn csv_field(text: &str) -> String {
+    if text.contains([',', '"', '\n', '\r']) { format!("\"{}\"", text.replace('"', "\"\"")) } else { text.to_string() }
+}
+
+// Printed before the first record, if the format has one
+pub fn header(format: OutputFormat) -> Option<&'static str> {
+    match format {
+        OutputFormat::Csv => Some("day,part,answer,elapsed_ns,details,error"),
+        OutputFormat::Text | OutputFormat::Json => None,
+    }
+}
+
+pub fn format_result(format: OutputFormat, day: u32, result: &PartResult) -> String {
+    match format {
+        OutputFormat::Text => {
+            // pictures start on a line of their own so they line up
+            let separator = if result.answer.to_string().contains('\n') { "\n" } else { " " };
+            format!("Day {} part {}:{}{}", day, result.part, separator, result.answer)
+        },
+        OutputFormat::Json => {
+            let answer = match &result.answer {
+                Answer::Number(n) => n.to_string(),
+                Answer::Text(text) => json_string(text),
+            };
+            let details : Vec<String> = result.details.iter().map(|(name, value)| format!("{}:{}", json_string(name), json_string(value))).collect();
+            format!("{{\"day\":{},\"part\":{},\"answer\":{},\"elapsed_ns\":{},\"details\":{{{}}}}}", day, result.part, answer, result.elapsed.as_nanos(), details.join(","))
+        },
+        OutputFormat::Csv => {
+            let details : Vec<String> = result.details.iter().map(|(name, value)| format!("{}={}", name, value)).collect();
+            format!("{},{},{},{},{},", day, result.part, csv_field(&result.answer.to_string()), result.elapsed.as_nanos(), csv_field(&details.join(";")))
+        },
+    }
+}
+
+// A day that couldn't be solved at all
+pub fn format_error(format: OutputFormat, day: u32, error: &str) -> String {
+    match format {
+        OutputFormat::Text => format!("Day {}: error: {}", day, error),
+        OutputFormat::Json => format!("{{\"day\":{},\"error\":{}}}", day, json_string(error)),
+        OutputFormat::Csv => format!("{},,,,,{}", day, csv_field(error)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn output_format_test() {
+        let station = PartResult { part: 1, answer: Answer::Number(326), elapsed: Duration::from_nanos(1500), details: vec![("station", String::from("11,13"))] };
+        let image = PartResult { part: 2, answer: Answer::Text(String::from("# \"a\"\n #")), elapsed: Duration::from_nanos(7), details: Vec::new() };
+
+        assert_eq!( format_result(OutputFormat::Text, 10, &station), "Day 10 part 1: 326" );
+        assert_eq!( format_result(OutputFormat::Text, 8, &image), "Day 8 part 2:\n# \"a\"\n #" );
+        assert_eq!( format_error(OutputFormat::Text, 5, "no input"), "Day 5: error: no input" );
+        assert!( !OutputFormat::Text.shows_details() && OutputFormat::Json.shows_details() && OutputFormat::Csv.shows_details() );
+
+        assert_eq!( format_result(OutputFormat::Json, 10, &station), "{\"day\":10,\"part\":1,\"answer\":326,\"elapsed_ns\":1500,\"details\":{\"station\":\"11,13\"}}" );
+        assert_eq!( format_result(OutputFormat::Json, 8, &image), "{\"day\":8,\"part\":2,\"answer\":\"# \\\"a\\\"\\n #\",\"elapsed_ns\":7,\"details\":{}}" );
+        assert_eq!( format_error(OutputFormat::Json, 5, "bad\tinput\u{1}"), "{\"day\":5,\"error\":\"bad\\tinput\\u0001\"}" );
+
+        assert_eq!( header(OutputFormat::Csv), Some("day,part,answer,elapsed_ns,details,error") );
+        assert_eq!( format_result(OutputFormat::Csv, 10, &station), "10,1,326,1500,\"station=11,13\"," );
+        assert_eq!( format_result(OutputFormat::Csv, 8, &image), "8,2,\"# \"\"a\"\"\n #\",7,," );
+        assert_eq!( format_error(OutputFormat::Csv, 5, "no input"), "5,,,,,no input" );
+
+        assert_eq!( "csv".parse(), Ok(OutputFormat::Csv) );
+        assert_eq!( "xml".parse::<OutputFormat>(), Err(String::from("--format is json, csv or text, not 'xml'")) );
+    }
+}
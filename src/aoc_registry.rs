@@ -0,0 +1,253 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+// A puzzle answer. Most are numbers, some are text such as letters drawn in a grid.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Answer {
+    Number(i64),
+    Text(String),
+}
+
+impl fmt::Display for Answer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Answer::Number(n) => write!(f, "{}", n),
+            Answer::Text(text) => write!(f, "{}", text),
+        }
+    }
+}
+
+impl From<i64> for Answer {
+    fn from(n: i64) -> Answer {
+        Answer::Number(n)
+    }
+}
+
+impl From<i32> for Answer {
+    fn from(n: i32) -> Answer {
+        Answer::Number(n.into())
+    }
+}
+
+impl From<u32> for Answer {
+    fn from(n: u32) -> Answer {
+        Answer::Number(n.into())
+    }
+}
+
+impl From<usize> for Answer {
+    fn from(n: usize) -> Answer {
+        Answer::Number(n as i64)
+    }
+}
+
+impl From<String> for Answer {
+    fn from(text: String) -> Answer {
+        Answer::Text(text)
+    }
+}
+
+pub type PuzzleResult<T> = Result<T, Box<dyn Error>>;
+
+// Named facts about how an answer was found, such as where the monitoring station is
+pub type Details = Vec<(&'static str, String)>;
+
+// One day's puzzle. The input is parsed once and shared by both parts.
+pub trait Solver {
+    type Input;
+    fn parse(&self, input: &str) -> PuzzleResult<Self::Input>;
+    fn part1(&self, input: &Self::Input) -> PuzzleResult<Answer>;
+    fn part2(&self, input: &Self::Input) -> PuzzleResult<Answer>;
+
+    // A part's answer with the details of how it was found. Days that have details override this
+    // to find both in one go, and have part1 and part2 drop the details.
+    fn solve_part(&self, input: &Self::Input, part: u32) -> PuzzleResult<(Answer, Details)> {
+        let answer = match part {
+            1 => self.part1(input)?,
+            2 => self.part2(input)?,
+            _ => return Err(format!("there is no part {}", part).into()),
+        };
+        Ok((answer, Details::new()))
+    }
+}
+
+// A part's answer with how long solving it took, not counting parsing
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PartResult {
+    pub part: u32,
+    pub answer: Answer,
+    pub elapsed: Duration,
+    pub details: Details,
+}
+
+// How long parsing and each part took on their own
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct StageTimes {
+    pub parse: Duration,
+    pub part1: Duration,
+    pub part2: Duration,
+}
+
+// Solver with the input type hidden, so solvers for different days can share the registry
+pub trait Puzzle {
+    // Answers to the given parts, in the same order
+    fn solve(&self, input: &str, parts: &[u32]) -> PuzzleResult<Vec<Answer>>;
+    // Like solve, timing each part and keeping its details if they are wanted
+    fn solve_detailed(&self, input: &str, parts: &[u32], with_details: bool) -> PuzzleResult<Vec<PartResult>>;
+    // Parses the input and solves both parts, timing each
+    fn time(&self, input: &str) -> PuzzleResult<StageTimes>;
+}
+
+impl<S: Solver> Puzzle for S {
+    fn solve(&self, input: &str, parts: &[u32]) -> PuzzleResult<Vec<Answer>> {
+        let input = self.parse(input)?;
+        parts.iter().map(|&part| match part {
+            1 => self.part1(&input),
+            2 => self.part2(&input),
+            _ => Err(format!("there is no part {}", part).into()),
+        }).collect()
+    }
+
+    fn solve_detailed(&self, input: &str, parts: &[u32], with_details: bool) -> PuzzleResult<Vec<PartResult>> {
+        let input = self.parse(input)?;
+        parts.iter().map(|&part| {
+            let start = Instant::now();
+            let (answer, details) = self.solve_part(&input, part)?;
+            let elapsed = start.elapsed();
+            let details = if with_details { details } else { Details::new() };
+            Ok(PartResult { part, answer, elapsed, details })
+        }).collect()
+    }
+
+    fn time(&self, input: &str) -> PuzzleResult<StageTimes> {
+        let start = Instant::now();
+        let input = black_box(self.parse(input)?);
+        let parse = start.elapsed();
+        let start = Instant::now();
+        black_box(self.part1(&input)?);
+        let part1 = start.elapsed();
+        let start = Instant::now();
+        black_box(self.part2(&input)?);
+        Ok(StageTimes { parse, part1, part2: start.elapsed() })
+    }
+}
+
+// Solvers by day
+#[derive(Default)]
+pub struct Registry {
+    days: BTreeMap<u32, Box<dyn Puzzle>>,
+}
+
+impl Registry {
+    pub fn new() -> Registry {
+        Registry::default()
+    }
+
+    // Every day solved so far
+    pub fn all_days() -> Registry {
+        let mut registry = Registry::new();
+        crate::register_solvers(&mut registry);
+        registry
+    }
+
+    // Adds the solver for a day, replacing any it had
+    pub fn register<S: Solver + 'static>(&mut self, day: u32, solver: S) {
+        self.days.insert(day, Box::new(solver));
+    }
+
+    pub fn days(&self) -> impl Iterator<Item = u32> + '_ {
+        self.days.keys().copied()
+    }
+
+    pub fn contains(&self, day: u32) -> bool {
+        self.days.contains_key(&day)
+    }
+
+    pub fn solve(&self, day: u32, input: &str, parts: &[u32]) -> PuzzleResult<Vec<Answer>> {
+        let puzzle = self.days.get(&day).ok_or_else(|| format!("day {} isn't solved", day))?;
+        puzzle.solve(input, parts)
+    }
+
+    pub fn solve_detailed(&self, day: u32, input: &str, parts: &[u32], with_details: bool) -> PuzzleResult<Vec<PartResult>> {
+        let puzzle = self.days.get(&day).ok_or_else(|| format!("day {} isn't solved", day))?;
+        puzzle.solve_detailed(input, parts, with_details)
+    }
+
+    pub fn time(&self, day: u32, input: &str) -> PuzzleResult<StageTimes> {
+        let puzzle = self.days.get(&day).ok_or_else(|| format!("day {} isn't solved", day))?;
+        puzzle.time(input)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Sums the numbers on a line for part 1, multiplies them for part 2
+    struct Arithmetic;
+
+    impl Solver for Arithmetic {
+        type Input = Vec<i64>;
+
+        fn parse(&self, input: &str) -> PuzzleResult<Vec<i64>> {
+            Ok(input.split_whitespace().map(|n| n.parse()).collect::<Result<_, _>>()?)
+        }
+
+        fn part1(&self, input: &Vec<i64>) -> PuzzleResult<Answer> {
+            Ok(input.iter().sum::<i64>().into())
+        }
+
+        fn part2(&self, input: &Vec<i64>) -> PuzzleResult<Answer> {
+            Ok(input.iter().product::<i64>().into())
+        }
+    }
+
+    // Arithmetic that also says how many numbers there were
+    struct Counted;
+
+    impl Solver for Counted {
+        type Input = Vec<i64>;
+
+        fn parse(&self, input: &str) -> PuzzleResult<Vec<i64>> {
+            Arithmetic.parse(input)
+        }
+
+        fn part1(&self, input: &Vec<i64>) -> PuzzleResult<Answer> {
+            Ok(self.solve_part(input, 1)?.0)
+        }
+
+        fn part2(&self, input: &Vec<i64>) -> PuzzleResult<Answer> {
+            Ok(self.solve_part(input, 2)?.0)
+        }
+
+        fn solve_part(&self, input: &Vec<i64>, part: u32) -> PuzzleResult<(Answer, Details)> {
+            let answer = match part {
+                1 => Arithmetic.part1(input)?,
+                _ => Arithmetic.part2(input)?,
+            };
+            Ok((answer, vec![("count", input.len().to_string())]))
+        }
+    }
+
+    #[test]
+    fn registry_test() {
+        let mut registry = Registry::new();
+        registry.register(3, Arithmetic);
+        assert_eq!( registry.days().collect::<Vec<_>>(), vec![3] );
+        assert_eq!( registry.solve(3, "2 3 4", &[1, 2]).unwrap(), vec![Answer::Number(9), Answer::Number(24)] );
+        assert_eq!( registry.solve(3, "2 x", &[1]).unwrap_err().to_string(), "invalid digit found in string" );
+        assert_eq!( registry.solve(3, "2", &[3]).unwrap_err().to_string(), "there is no part 3" );
+        assert_eq!( registry.solve(4, "2", &[1]).unwrap_err().to_string(), "day 4 isn't solved" );
+        assert_eq!( registry.solve_detailed(3, "2 3 4", &[2], true).unwrap()[0].details, Details::new() );
+        registry.register(5, Counted);
+        let results = registry.solve_detailed(5, "2 3 4", &[2], true).unwrap();
+        assert_eq!( (results[0].part, &results[0].answer, &results[0].details), (2, &Answer::Number(24), &vec![("count", String::from("3"))]) );
+        assert_eq!( registry.solve_detailed(5, "2 3 4", &[2], false).unwrap()[0].details, Details::new() );
+        assert_eq!( registry.solve(5, "2 3 4", &[1]).unwrap(), vec![Answer::Number(9)] );
+        assert_eq!( Answer::from(String::from("AB\nCD")).to_string(), "AB\nCD" );
+        assert_eq!( Registry::all_days().days().count(), 15 );
+    }
+}
@@ -0,0 +1,241 @@
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+
+use crate::aoc_registry::{Answer, Registry};
+
+// Confirmed answers by (day, part)
+pub type KnownAnswers = BTreeMap<(u32, u32), Answer>;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AnswerFileError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AnswerFileError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "answer file line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for AnswerFileError {}
+
+// Numbers are written as they are, text is quoted with \n, \" and \\ escapes so pictures fit
+// on one line and keep their trailing spaces
+fn encode_answer(answer: &Answer) -> String {
+    match answer {
+        Answer::Number(n) => n.to_string(),
+        Answer::Text(text) => format!("\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")),
+    }
+}
+
+fn decode_answer(line: usize, text: &str) -> Result<Answer, AnswerFileError> {
+    let error = |message: &str| AnswerFileError { line, message: message.to_string() };
+    let quoted = match text.strip_prefix('"') {
+        Some(rest) => rest.strip_suffix('"').ok_or_else(|| error("text answer is missing its closing quote"))?,
+        None => return text.parse().map(Answer::Number).map_err(|_| error(&format!("'{}' isn't a number or quoted text", text))),
+    };
+
+    let mut decoded = String::new();
+    let mut chars = quoted.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            decoded.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => decoded.push('\n'),
+            Some(c @ ('"' | '\\')) => decoded.push(c),
+            _ => return Err(error("unknown escape in text answer")),
+        }
+    }
+    Ok(Answer::Text(decoded))
+}
+
+// One answer per line as "<day> <part> <answer>". Blank lines and lines starting with # are skipped.
+pub fn parse_known_answers(text: &str) -> Result<KnownAnswers, AnswerFileError> {
+    let mut answers = KnownAnswers::new();
+    for (i, l) in text.lines().enumerate() {
+        let (line, l) = (i + 1, l.trim());
+        if l.is_empty() || l.starts_with('#') {
+            continue;
+        }
+        let mut fields = l.splitn(3, ' ');
+        let mut number = |what: &str| fields.next().and_then(|f| f.parse::<u32>().ok())
+            .ok_or_else(|| AnswerFileError { line, message: format!("expected '<day> <part> <answer>', the {} isn't a number", what) });
+        let (day, part) = (number("day")?, number("part")?);
+        let answer = decode_answer(line, fields.next().unwrap_or("").trim())?;
+        if answers.insert((day, part), answer).is_some() {
+            return Err(AnswerFileError { line, message: format!("day {} part {} is in the file twice", day, part) });
+        }
+    }
+    Ok(answers)
+}
+
+pub fn format_known_answers(answers: &KnownAnswers) -> String {
+    let mut text = String::from("# <day> <part> <answer>, confirmed answers for the aocday<day>.txt inputs\n");
+    for (&(day, part), answer) in answers {
+        text += &format!("{} {} {}\n", day, part, encode_answer(answer));
+    }
+    text
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RegressionResult {
+    pub day: u32,
+    pub part: u32,
+    pub expected: Option<Answer>,
+    pub actual: Result<Answer, String>,
+    pub duration: Duration,
+}
+
+impl RegressionResult {
+    pub fn status(&self) -> &'static str {
+        match (&self.actual, &self.expected) {
+            (Err(_), _) => "ERROR",
+            (Ok(_), None) => "new",
+            (Ok(actual), Some(expected)) if actual == expected => "ok",
+            (Ok(_), Some(_)) => "MISMATCH",
+        }
+    }
+
+    // New answers that haven't been recorded yet don't count as failures
+    pub fn failed(&self) -> bool {
+        matches!(self.status(), "ERROR" | "MISMATCH")
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct RegressionReport {
+    pub results: Vec<RegressionResult>,
+}
+
+impl RegressionReport {
+    pub fn failures(&self) -> impl Iterator<Item = &RegressionResult> {
+        self.results.iter().filter(|r| r.failed())
+    }
+
+    pub fn all_passed(&self) -> bool {
+        self.failures().next().is_none()
+    }
+}
+
+// Pictures are shortened to keep the table readable, failures are listed in full below it
+fn table_answer(answer: &Result<Answer, String>) -> String {
+    let text = match answer {
+        Ok(answer) => encode_answer(answer),
+        Err(_) => String::new(),
+    };
+    if text.chars().count() > 32 { format!("{}...", text.chars().take(29).collect::<String>()) } else { text }
+}
+
+impl fmt::Display for RegressionReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "{:>3} {:>4}  {:<8} {:>10}  answer", "day", "part", "status", "time")?;
+        for result in &self.results {
+            writeln!(f, "{:>3} {:>4}  {:<8} {:>7.1} ms  {}", result.day, result.part, result.status(), result.duration.as_secs_f64() * 1000.0, table_answer(&result.actual))?;
+        }
+        for result in self.failures() {
+            match (&result.actual, &result.expected) {
+                (Err(error), _) => writeln!(f, "day {} part {}: {}", result.day, result.part, error)?,
+                (Ok(actual), expected) => writeln!(f, "day {} part {}: expected {} but got {}", result.day, result.part,
+                    expected.as_ref().map_or(String::from("nothing"), encode_answer), encode_answer(actual))?,
+            }
+        }
+        let total : Duration = self.results.iter().map(|r| r.duration).sum();
+        let unrecorded = self.results.iter().filter(|r| r.status() == "new").count();
+        write!(f, "{} passed, {} failed, {} not recorded yet in {:.1} ms", self.results.len() - self.failures().count() - unrecorded, self.failures().count(), unrecorded, total.as_secs_f64() * 1000.0)
+    }
+}
+
+// Solves both parts of every day in the registry with its aocday<day>.txt input from the
+// directory, timing each part on its own. Known answers for days without a solver are errors.
+pub fn run_regression(registry: &Registry, input_directory: &Path, known: &KnownAnswers) -> RegressionReport {
+    let mut days : Vec<u32> = registry.days().chain(known.keys().map(|&(day, _)| day)).collect();
+    days.sort_unstable();
+    days.dedup();
+
+    let mut report = RegressionReport::default();
+    for day in days {
+        let filename = input_directory.join(format!("aocday{}.txt", day));
+        let input = fs::read_to_string(&filename).map_err(|error| format!("{}: {}", filename.display(), error));
+        for part in 1..=2 {
+            let start = Instant::now();
+            let actual = match &input {
+                Ok(input) => registry.solve(day, input, &[part]).map(|mut answers| answers.remove(0)).map_err(|error| error.to_string()),
+                Err(error) => Err(error.clone()),
+            };
+            report.results.push(RegressionResult { day, part, expected: known.get(&(day, part)).cloned(), actual, duration: start.elapsed() });
+        }
+    }
+    report
+}
+
+// Takes the answers the report got for the given days as the confirmed ones, returning what
+// changed. Fails without changing anything if one of the days couldn't be solved.
+pub fn record_answers(known: &mut KnownAnswers, report: &RegressionReport, days: &[u32]) -> Result<Vec<(u32, u32)>, String> {
+    let results : Vec<&RegressionResult> = report.results.iter().filter(|r| days.contains(&r.day)).collect();
+    if let Some(&day) = days.iter().find(|&&day| !results.iter().any(|r| r.day == day)) {
+        return Err(format!("day {} wasn't run", day));
+    }
+    if let Some(result) = results.iter().find(|r| r.actual.is_err()) {
+        return Err(format!("day {} part {} has no answer to record", result.day, result.part));
+    }
+
+    let mut changed = Vec::new();
+    for result in results {
+        let actual = result.actual.clone().unwrap();
+        if known.insert((result.day, result.part), actual).as_ref() != result.actual.as_ref().ok() {
+            changed.push((result.day, result.part));
+        }
+    }
+    Ok(changed)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn answer_file_test() {
+        let mut answers = KnownAnswers::new();
+        answers.insert((1, 1), Answer::Number(-42));
+        answers.insert((8, 2), Answer::Text(String::from("# \"a\\b\"  \n  #  ")));
+        let text = format_known_answers(&answers);
+        assert!( text.contains("8 2 \"# \\\"a\\\\b\\\"  \\n  #  \"\n") );
+        assert_eq!( parse_known_answers(&text), Ok(answers) );
+
+        let error = |text: &str| parse_known_answers(text).unwrap_err().to_string();
+        assert_eq!( error("1 1 5\n\n1 x 5"), "answer file line 3: expected '<day> <part> <answer>', the part isn't a number" );
+        assert_eq!( error("1 1 five"), "answer file line 1: 'five' isn't a number or quoted text" );
+        assert_eq!( error("1 1 \"open"), "answer file line 1: text answer is missing its closing quote" );
+        assert_eq!( error("1 1 5\n1 1 6"), "answer file line 2: day 1 part 1 is in the file twice" );
+    }
+
+    #[test]
+    fn checked_in_answers_test() {
+        let known = parse_known_answers(&fs::read_to_string("answers.txt").unwrap()).unwrap();
+        let registry = Registry::all_days();
+        let report = run_regression(&registry, Path::new("."), &known);
+        assert!( report.all_passed(), "{}", report );
+        assert_eq!( known.len(), 2 * registry.days().count() );
+
+        // a wrong answer is caught and can be recorded over once confirmed
+        let mut wrong = known.clone();
+        wrong.insert((4, 2), Answer::Number(0));
+        let report = run_regression(&Registry::new(), Path::new("."), &wrong);
+        assert_eq!( report.failures().count(), report.results.len() );
+        assert!( report.to_string().contains("day 4 part 2: day 4 isn't solved") );
+        let mut four = Registry::new();
+        four.register(4, crate::AocDayFour);
+        let report = run_regression(&four, Path::new("."), &wrong);
+        assert!( report.to_string().contains("day 4 part 2: expected 0 but got 1991") );
+        assert_eq!( record_answers(&mut wrong, &report, &[4]), Ok(vec![(4, 2)]) );
+        assert_eq!( wrong, known );
+        assert_eq!( record_answers(&mut wrong, &report, &[5]), Err(String::from("day 5 part 1 has no answer to record")) );
+    }
+}
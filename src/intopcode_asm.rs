@@ -0,0 +1,386 @@
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt;
+
+use crate::decode_opcode;
+use crate::intopcode_isa::InstructionSet;
+
+// Max number of words printed on a single .data line
+const DATA_WORDS_PER_LINE: usize = 8;
+
+fn format_operand(mode: i64, param: i64) -> String {
+    match mode {
+        0 => format!("[{}]", param),
+        1 => format!("#{}", param),
+        _ => {
+            if param < 0 {
+                format!("[rb-{}]", param.unsigned_abs())
+            }
+            else {
+                format!("[rb+{}]", param)
+            }
+        }
+    }
+}
+
+// Decodes the instruction at address, returning its text and length in words.
+// Returns None if the words there can't be reproduced exactly by an instruction
+// (unknown opcode or mode, stray mode digits, or operands running past the end).
+pub fn disassemble_instruction(intopcodes: &[i64], address: usize) -> Option<(String, usize)> {
+    disassemble_instruction_with(intopcodes, address, &InstructionSet::standard_shared())
+}
+
+// disassemble_instruction for a machine with different opcodes or modes
+pub fn disassemble_instruction_with(intopcodes: &[i64], address: usize, instruction_set: &InstructionSet) -> Option<(String, usize)> {
+    let opcode = *intopcodes.get(address)?;
+    if opcode < 0 {
+        return None;
+    }
+
+    let (two_digit_opcode, modes) = decode_opcode(opcode);
+    let definition = instruction_set.get(two_digit_opcode)?;
+    let (mnemonic, param_count) = (definition.mnemonic, definition.parameters.len());
+    if address + param_count >= intopcodes.len() {
+        return None;
+    }
+
+    // Only the modes of parameters the opcode actually has may be set, and they must be known
+    let mut encoded = two_digit_opcode;
+    let mut operands = Vec::new();
+    for (i, (mode, &kind)) in modes.iter().zip(&definition.parameters).enumerate() {
+        if !instruction_set.allows_mode(*mode, kind) {
+            return None;
+        }
+        encoded += mode * 10i64.pow(i as u32 + 2);
+        operands.push(format_operand(*mode, intopcodes[address + 1 + i]));
+    }
+    if encoded != opcode {
+        return None;
+    }
+
+    let text = if operands.is_empty() {
+        String::from(mnemonic)
+    }
+    else {
+        format!("{} {}", mnemonic, operands.join(", "))
+    };
+    Some((text, param_count + 1))
+}
+
+fn push_line(lines: &mut Vec<String>, text: &str, address: usize) {
+    lines.push(format!("    {:<36} ; {}", text, address));
+}
+
+fn flush_data(lines: &mut Vec<String>, data: &mut Vec<i64>, data_address: usize) {
+    for (chunk_index, chunk) in data.chunks(DATA_WORDS_PER_LINE).enumerate() {
+        let values : Vec<String> = chunk.iter().map(|v| v.to_string()).collect();
+        push_line(lines, &format!(".data {}", values.join(", ")), data_address + chunk_index * DATA_WORDS_PER_LINE);
+    }
+    data.clear();
+}
+
+// Walks the whole program printing one instruction per line, with the address as a trailing comment.
+// Words that don't form a valid instruction are emitted as .data so the output always assembles back
+// to the exact same memory.
+pub fn disassemble(intopcodes: &[i64]) -> String {
+    disassemble_with(intopcodes, &InstructionSet::standard_shared())
+}
+
+// disassemble for a machine with different opcodes or modes, anything it wouldn't run is .data
+pub fn disassemble_with(intopcodes: &[i64], instruction_set: &InstructionSet) -> String {
+    let mut lines = Vec::new();
+    let mut data = Vec::new();
+    let mut data_address = 0;
+    let mut address = 0;
+
+    while address < intopcodes.len() {
+        match disassemble_instruction_with(intopcodes, address, instruction_set) {
+            Some((text, length)) => {
+                flush_data(&mut lines, &mut data, data_address);
+                push_line(&mut lines, &text, address);
+                address += length;
+            },
+            None => {
+                if data.is_empty() {
+                    data_address = address;
+                }
+                data.push(intopcodes[address]);
+                address += 1;
+            }
+        }
+    }
+    flush_data(&mut lines, &mut data, data_address);
+
+    let mut output = lines.join("\n");
+    output.push('\n');
+    output
+}
+
+#[derive(Debug, PartialEq)]
+pub struct AssembleError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for AssembleError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for AssembleError {}
+
+fn is_label(name: &str) -> bool {
+    let mut chars = name.chars();
+    match chars.next() {
+        Some(c) if c.is_ascii_alphabetic() || c == '_' => chars.all(|c| c.is_ascii_alphanumeric() || c == '_'),
+        _ => false
+    }
+}
+
+// Evaluates an expression made of integers and labels joined with + and -, e.g. "loop+2"
+fn evaluate_expression(expr: &str, labels: &HashMap<String, i64>) -> Result<i64, String> {
+    let expr = expr.trim();
+    if expr.is_empty() {
+        return Err(String::from("missing value"));
+    }
+
+    // i128 so the magnitude of i64::MIN can be written out, e.g. [rb-9223372036854775808]
+    let mut total : i128 = 0;
+    let mut sign : i128 = 1;
+    let mut term = String::new();
+    let mut terms = Vec::new();
+    for (i, c) in expr.chars().enumerate() {
+        // a leading sign belongs to the first term, e.g. "-5"
+        if (c == '+' || c == '-') && i > 0 {
+            terms.push((sign, term.trim().to_string()));
+            sign = if c == '-' { -1 } else { 1 };
+            term.clear();
+        }
+        else {
+            term.push(c);
+        }
+    }
+    terms.push((sign, term.trim().to_string()));
+
+    for (sign, term) in terms {
+        let value = if let Ok(val) = term.parse::<i128>() {
+            val
+        }
+        else if is_label(&term) {
+            match labels.get(&term) {
+                Some(&address) => address as i128,
+                None => return Err(format!("unknown label '{}'", term))
+            }
+        }
+        else {
+            return Err(format!("invalid value '{}'", term));
+        };
+        total = sign.checked_mul(value).and_then(|value| total.checked_add(value))
+            .ok_or_else(|| format!("'{}' is out of range", expr))?;
+    }
+
+    i64::try_from(total).map_err(|_| format!("'{}' is out of range", expr))
+}
+
+// Parses an operand into its mode digit and parameter value
+fn parse_operand(operand: &str, labels: &HashMap<String, i64>) -> Result<(i64, i64), String> {
+    let operand = operand.trim();
+    if let Some(value) = operand.strip_prefix('#') {
+        return Ok((1, evaluate_expression(value, labels)?));
+    }
+
+    if operand.starts_with('[') && operand.ends_with(']') {
+        let inner = operand[1..operand.len() - 1].trim();
+        if let Some(offset) = inner.strip_prefix("rb") {
+            let offset = offset.trim();
+            if offset.is_empty() {
+                return Ok((2, 0));
+            }
+            if offset.starts_with('+') || offset.starts_with('-') {
+                return Ok((2, evaluate_expression(&format!("0{}", offset), labels)?));
+            }
+        }
+        return Ok((0, evaluate_expression(inner, labels)?));
+    }
+
+    Err(format!("invalid operand '{}', expected #value, [address] or [rb+offset]", operand))
+}
+
+struct SourceLine<'a> {
+    line: usize,
+    address: usize,
+    mnemonic: &'a str,
+    operands: Vec<&'a str>,
+}
+
+fn split_operands(operands: &str) -> Vec<&str> {
+    if operands.trim().is_empty() {
+        Vec::new()
+    }
+    else {
+        operands.split(',').map(|o| o.trim()).collect()
+    }
+}
+
+// Assembles text in the format produced by disassemble back into a program.
+// Each line holds an optional "label:", then an instruction or a ".data" directive, and ';' starts a comment.
+pub fn assemble(source: &str) -> Result<Vec<i64>, AssembleError> {
+    assemble_with(source, &InstructionSet::standard_shared())
+}
+
+// assemble with the mnemonics and modes of a machine with different opcodes
+pub fn assemble_with(source: &str, instruction_set: &InstructionSet) -> Result<Vec<i64>, AssembleError> {
+    let mut labels : HashMap<String, i64> = HashMap::new();
+    let mut source_lines = Vec::new();
+    let mut address = 0;
+
+    // First pass finds the address of every label
+    for (line_index, raw_line) in source.lines().enumerate() {
+        let line = line_index + 1;
+        let mut text = raw_line.split(';').next().unwrap().trim();
+
+        while let Some(colon) = text.find(':') {
+            let label = text[..colon].trim();
+            if !is_label(label) {
+                return Err(AssembleError { line, message: format!("invalid label '{}'", label) });
+            }
+            if labels.insert(String::from(label), address as i64).is_some() {
+                return Err(AssembleError { line, message: format!("duplicate label '{}'", label) });
+            }
+            text = text[colon + 1..].trim();
+        }
+
+        if text.is_empty() {
+            continue;
+        }
+
+        let (mnemonic, operands) = match text.find(char::is_whitespace) {
+            Some(split) => (&text[..split], split_operands(&text[split..])),
+            None => (text, Vec::new())
+        };
+
+        let length = if mnemonic == ".data" {
+            if operands.is_empty() {
+                return Err(AssembleError { line, message: String::from(".data needs at least one value") });
+            }
+            operands.len()
+        }
+        else {
+            let param_count = instruction_set.find_mnemonic(mnemonic)
+                .map(|definition| definition.parameters.len())
+                .ok_or_else(|| AssembleError { line, message: format!("unknown instruction '{}'", mnemonic) })?;
+            if operands.len() != param_count {
+                return Err(AssembleError { line, message: format!("'{}' takes {} operands, found {}", mnemonic, param_count, operands.len()) });
+            }
+            param_count + 1
+        };
+
+        source_lines.push(SourceLine { line, address, mnemonic, operands });
+        address += length;
+    }
+
+    // Second pass encodes everything now that all labels are known
+    let mut intopcodes = Vec::with_capacity(address);
+    for source_line in source_lines {
+        let line = source_line.line;
+        debug_assert_eq!(intopcodes.len(), source_line.address);
+
+        if source_line.mnemonic == ".data" {
+            for value in source_line.operands {
+                intopcodes.push(evaluate_expression(value, &labels).map_err(|message| AssembleError { line, message })?);
+            }
+        }
+        else {
+            let definition = instruction_set.find_mnemonic(source_line.mnemonic).unwrap();
+            let mut opcode = definition.opcode;
+            let mut params = Vec::new();
+            for (i, (operand, &kind)) in source_line.operands.iter().zip(&definition.parameters).enumerate() {
+                let (mode, param) = parse_operand(operand, &labels).map_err(|message| AssembleError { line, message })?;
+                if !instruction_set.allows_mode(mode, kind) {
+                    return Err(AssembleError { line, message: format!("operand {} of '{}' can't be '{}'", i + 1, source_line.mnemonic, operand) });
+                }
+                opcode += mode * 10i64.pow(i as u32 + 2);
+                params.push(param);
+            }
+            intopcodes.push(opcode);
+            intopcodes.extend(params);
+        }
+    }
+
+    Ok(intopcodes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_intopcodes;
+
+    #[test]
+    fn disassemble_instruction_test() {
+        let program = vec![21101,5,-3,100,204,-1,1105,1,7,99];
+        assert_eq!(disassemble_instruction(&program, 0), Some((String::from("add #5, #-3, [rb+100]"), 4)));
+        assert_eq!(disassemble_instruction(&program, 4), Some((String::from("out [rb-1]"), 2)));
+        assert_eq!(disassemble_instruction(&program, 6), Some((String::from("jnz #1, #7"), 3)));
+        assert_eq!(disassemble_instruction(&program, 9), Some((String::from("hlt"), 1)));
+        assert_eq!(disassemble_instruction(&[10004, 5], 0), None); // stray mode digit
+        assert_eq!(disassemble_instruction(&[1, 5, 6], 0), None); // operands run past the end
+        assert_eq!(disassemble_instruction(&[-1], 0), None);
+    }
+
+    #[test]
+    fn assemble_labels_and_data_test() {
+        let source = "
+            start:  in [value]          ; read a value
+                    eq [value], #8, [rb]
+                    jz [rb], #done
+                    out [value]
+            done:   hlt
+            value:  .data 0, done-start
+        ";
+        assert_eq!(assemble(source), Ok(vec![3,12,21008,12,8,0,1206,0,11,4,12,99,0,11]));
+    }
+
+    #[test]
+    fn assemble_error_test() {
+        assert_eq!(assemble("add #1, #2").unwrap_err().line, 1);
+        assert_eq!(assemble("\nfoo #1").unwrap_err(), AssembleError { line: 2, message: String::from("unknown instruction 'foo'") });
+        assert_eq!(assemble("jz #0, #nowhere").unwrap_err().message, "unknown label 'nowhere'");
+        assert_eq!(assemble("a: hlt\na: hlt").unwrap_err().line, 2);
+        assert_eq!(assemble("out #9223372036854775807+1").unwrap_err().message, "'9223372036854775807+1' is out of range");
+        assert_eq!(assemble("out #-170141183460469231731687303715884105728-1").unwrap_err().message, "'-170141183460469231731687303715884105728-1' is out of range");
+    }
+
+    #[test]
+    fn disassemble_round_trip_test() {
+        let program = vec![109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99];
+        assert_eq!(assemble(&disassemble(&program)), Ok(program));
+
+        let boost = parse_intopcodes(&std::fs::read_to_string("aocday9.txt").unwrap()).unwrap();
+        assert_eq!(assemble(&disassemble(&boost)), Ok(boost));
+
+        let extremes = vec![204,i64::MIN,1101,i64::MIN,i64::MAX,0,99];
+        assert_eq!(disassemble_instruction(&extremes, 0), Some((String::from("out [rb-9223372036854775808]"), 2)));
+        assert_eq!(assemble(&disassemble(&extremes)), Ok(extremes));
+    }
+
+    #[test]
+    fn instruction_set_test() {
+        use crate::intopcode_isa::{OpcodeDefinition, ParameterKind};
+        let mut set = InstructionSet::standard();
+        set.register(OpcodeDefinition { opcode: 10, mnemonic: "div", parameters: vec![ParameterKind::Read, ParameterKind::Read, ParameterKind::Write], day: 0, execute: |_| Ok(()) }).unwrap();
+
+        let program = vec![1110,47,5,5,4,0,99];
+        assert_eq!(disassemble_instruction_with(&program, 0, &set), Some((String::from("div #47, #5, [5]"), 4)));
+        assert_eq!(disassemble_instruction(&program, 0), None);
+        assert_eq!(assemble_with(&disassemble_with(&program, &set), &set), Ok(program));
+        assert_eq!(assemble("div #47, #5, [5]").unwrap_err().message, "unknown instruction 'div'");
+
+        let day2 = InstructionSet::for_day(2);
+        assert_eq!(disassemble_instruction_with(&[1101,1,1,0,99], 0, &day2), None);
+        assert_eq!(disassemble_instruction_with(&[104,1,99], 0, &day2), None);
+        assert_eq!(assemble_with("add [0], #1, [0]", &day2).unwrap_err().message, "operand 2 of 'add' can't be '#1'");
+        assert_eq!(assemble_with("add [0], [1], [0]", &day2), Ok(vec![1,0,1,0]));
+    }
+}
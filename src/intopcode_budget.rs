@@ -0,0 +1,216 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::time::{Duration, Instant};
+
+use crate::{IntOpCodeComp, IntOpCodeError, RunState};
+use crate::intopcode_trace::MemoryWrite;
+
+// Limits on a run. Nothing is limited by default.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct RunBudget {
+    pub max_steps: Option<u64>,
+    pub max_duration: Option<Duration>,
+    // Stop as soon as the program is back in a state it was already in with no input or
+    // output since, as it can only go round the same loop forever
+    pub detect_loops: bool,
+}
+
+// Why a budgeted run stopped, with the number of instructions it executed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunOutcome {
+    Halted { steps: u64 },
+    // Needs input nobody has given it
+    WaitingOnInput { steps: u64 },
+    StepBudgetExhausted { steps: u64 },
+    TimeBudgetExhausted { steps: u64 },
+    // The state at ip came round again after period more instructions
+    NonTerminating { steps: u64, ip: usize, period: u64 },
+}
+
+impl RunOutcome {
+    pub fn steps(&self) -> u64 {
+        match *self {
+            RunOutcome::Halted { steps } | RunOutcome::WaitingOnInput { steps } | RunOutcome::StepBudgetExhausted { steps }
+                | RunOutcome::TimeBudgetExhausted { steps } | RunOutcome::NonTerminating { steps, .. } => steps,
+        }
+    }
+}
+
+impl fmt::Display for RunOutcome {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RunOutcome::Halted { steps } => write!(f, "halted after {} steps", steps),
+            RunOutcome::WaitingOnInput { steps } => write!(f, "waiting on input after {} steps", steps),
+            RunOutcome::StepBudgetExhausted { steps } => write!(f, "stopped after using the budget of {} steps", steps),
+            RunOutcome::TimeBudgetExhausted { steps } => write!(f, "stopped after using the time budget, {} steps", steps),
+            RunOutcome::NonTerminating { steps, ip, period } =>
+                write!(f, "non-terminating: the state at ip {} repeats every {} steps with no input or output, stopped after {} steps", ip, period, steps),
+        }
+    }
+}
+
+// How often the clock is checked, reading it every instruction costs more than the instruction
+const TIME_CHECK_INTERVAL: u64 = 1024;
+
+fn mix(mut x: u64) -> u64 {
+    x = (x ^ (x >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+    x = (x ^ (x >> 27)).wrapping_mul(0x94D049BB133111EB);
+    x ^ (x >> 31)
+}
+
+// Zero cells add nothing, so the hash doesn't change as memory grows
+fn cell_hash(address: usize, value: i64) -> u64 {
+    if value == 0 { 0 } else { mix((address as u64).wrapping_mul(0x9E3779B97F4A7C15) ^ value as u64) }
+}
+
+// Brent's cycle detection over (ip, relative base, memory). Remembers one state, replacing it
+// whenever the number of steps since it was taken reaches a power of two, so any loop is caught
+// within a few times its length without keeping every state.
+struct LoopDetector {
+    // Sum of the hashes of every cell, kept up to date write by write
+    memory_hash: u64,
+    // ip, relative base and memory hash of the remembered state
+    saved: (usize, i64, u64),
+    // Value each cell written since then had in the remembered state, so memory can be compared
+    // with it without copying all of it
+    saved_cells: HashMap<usize, i64>,
+    power: u64,
+    length: u64,
+}
+
+impl LoopDetector {
+    fn new(comp: &IntOpCodeComp) -> LoopDetector {
+        let memory_hash = comp.memory.cells().iter().fold(0u64, |hash, &(a, v)| hash.wrapping_add(cell_hash(a, v)));
+        LoopDetector { memory_hash, saved: (comp.index, comp.relative_base, memory_hash), saved_cells: HashMap::new(), power: 1, length: 0 }
+    }
+
+    fn write(&mut self, write: &MemoryWrite) {
+        self.memory_hash = self.memory_hash.wrapping_sub(cell_hash(write.address, write.old_value)).wrapping_add(cell_hash(write.address, write.new_value));
+        self.saved_cells.entry(write.address).or_insert(write.old_value);
+    }
+
+    fn save(&mut self, comp: &IntOpCodeComp) {
+        self.saved = (comp.index, comp.relative_base, self.memory_hash);
+        self.saved_cells.clear();
+        self.power = 1;
+        self.length = 0;
+    }
+
+    // Returns the loop length once the saved state comes round again
+    fn check(&mut self, comp: &IntOpCodeComp) -> Option<u64> {
+        self.length += 1;
+        // the hash only says where to look, the written cells are compared to make sure
+        if self.saved == (comp.index, comp.relative_base, self.memory_hash) && self.saved_cells.iter().all(|(&a, &v)| comp.memory.read(a) == v) {
+            return Some(self.length);
+        }
+        if self.length == self.power {
+            let power = self.power * 2;
+            self.save(comp);
+            self.power = power;
+        }
+        None
+    }
+}
+
+impl IntOpCodeComp {
+    // Runs until the program halts, needs input or goes over the budget
+    pub fn run_with_budget(&mut self, budget: &RunBudget) -> Result<RunOutcome, IntOpCodeError> {
+        let start = Instant::now();
+        let mut steps = 0;
+        let mut detector = if budget.detect_loops { Some(LoopDetector::new(self)) } else { None };
+        loop {
+            if self.state == RunState::Halted {
+                return Ok(RunOutcome::Halted { steps });
+            }
+            if budget.max_steps.is_some_and(|max_steps| steps >= max_steps) {
+                return Ok(RunOutcome::StepBudgetExhausted { steps });
+            }
+            if steps % TIME_CHECK_INTERVAL == 0 && budget.max_duration.is_some_and(|max_duration| start.elapsed() >= max_duration) {
+                return Ok(RunOutcome::TimeBudgetExhausted { steps });
+            }
+
+            match detector.as_mut() {
+                None => {
+                    self.tick()?;
+                    if self.state == RunState::WaitingOnInput {
+                        return Ok(RunOutcome::WaitingOnInput { steps });
+                    }
+                },
+                Some(detector) => {
+                    let input_count = self.input_values.len();
+                    let record = match self.traced_tick()? {
+                        Some(record) => record,
+                        None => return Ok(RunOutcome::WaitingOnInput { steps }),
+                    };
+                    if let Some(write) = &record.write {
+                        detector.write(write);
+                    }
+                    if record.output.is_some() || self.input_values.len() != input_count {
+                        detector.save(self);
+                    }
+                    else if let Some(period) = detector.check(self) {
+                        return Ok(RunOutcome::NonTerminating { steps: steps + 1, ip: self.index, period });
+                    }
+                },
+            }
+            steps += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+    use crate::parse_intopcodes;
+    use crate::intopcode_asm::assemble;
+
+    fn run(program: &[i64], budget: RunBudget) -> RunOutcome {
+        IntOpCodeComp::new(program, &VecDeque::new()).run_with_budget(&budget).unwrap()
+    }
+
+    #[test]
+    fn loop_detection_test() {
+        let detect = RunBudget { detect_loops: true, ..RunBudget::default() };
+        assert_eq!( run(&[1105,1,0], detect), RunOutcome::NonTerminating { steps: 1, ip: 0, period: 1 } );
+
+        // flips the sign of x forever, so memory only repeats every other time round
+        let flip = assemble("
+            loop:   mul [x], #-1, [x]
+                    jz #0, #loop
+            x:      .data 1
+        ").unwrap();
+        match run(&flip, detect) {
+            RunOutcome::NonTerminating { period, .. } => assert_eq!( period, 4 ),
+            outcome => panic!("{}", outcome),
+        }
+
+        // counting up never repeats and printing is progress, so only a budget stops them
+        let count = assemble("
+            loop:   add [x], #1, [x]
+                    jz #0, #loop
+            x:      .data 0
+        ").unwrap();
+        let steps = RunBudget { max_steps: Some(1000), detect_loops: true, ..RunBudget::default() };
+        assert_eq!( run(&count, steps), RunOutcome::StepBudgetExhausted { steps: 1000 } );
+        assert_eq!( run(&[104,1,1105,1,0], steps), RunOutcome::StepBudgetExhausted { steps: 1000 } );
+        let time = RunBudget { max_duration: Some(Duration::from_millis(10)), ..RunBudget::default() };
+        assert!( matches!(run(&count, time), RunOutcome::TimeBudgetExhausted { .. }) );
+
+        // programs that finish aren't mistaken for loops
+        let mut comp = IntOpCodeComp::new(&parse_intopcodes(&std::fs::read_to_string("aocday9.txt").unwrap()).unwrap(), &VecDeque::from(vec![1]));
+        assert!( matches!(comp.run_with_budget(&detect), Ok(RunOutcome::Halted { .. })) );
+        assert_eq!( comp.output_values, vec![3601950151] );
+    }
+
+    #[test]
+    fn waiting_on_input_test() {
+        assert_eq!( run(&[3,0,99], RunBudget::default()), RunOutcome::WaitingOnInput { steps: 0 } );
+        assert_eq!( run(&[104,7,3,0,99], RunBudget { detect_loops: true, ..RunBudget::default() }), RunOutcome::WaitingOnInput { steps: 1 } );
+
+        // tick_to_completion gives up instead of waiting forever
+        let mut comp = IntOpCodeComp::new(&[104,7,3,0,99], &VecDeque::new());
+        assert_eq!( comp.tick_to_completion().unwrap(), &VecDeque::from(vec![7]) );
+        assert_eq!( comp.state(), RunState::WaitingOnInput );
+    }
+}
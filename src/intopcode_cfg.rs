@@ -0,0 +1,454 @@
+use std::collections::BTreeMap;
+use std::collections::BTreeSet;
+use std::collections::VecDeque;
+
+use crate::decode_opcode;
+use crate::intopcode_asm::disassemble_instruction_with;
+use crate::intopcode_isa::{InstructionSet, ParameterKind};
+
+// An instruction found by following the program from address 0
+#[derive(Debug, Clone)]
+struct Decoded {
+    two_digit_opcode: i64,
+    // Only standard opcodes are known to jump or halt, any others are taken to fall through
+    standard: bool,
+    modes: [i64; 3],
+    kinds: Vec<ParameterKind>,
+    params: Vec<i64>,
+}
+
+impl Decoded {
+    fn length(&self) -> usize {
+        self.params.len() + 1
+    }
+
+    fn is_jump(&self) -> bool {
+        self.standard && (self.two_digit_opcode == 5 || self.two_digit_opcode == 6)
+    }
+
+    fn halts(&self) -> bool {
+        self.standard && self.two_digit_opcode == 99
+    }
+
+    fn ends_block(&self) -> bool {
+        self.is_jump() || self.halts()
+    }
+
+    // (always jumps, never jumps) for a jump whose condition is an immediate value
+    fn constant_condition(&self) -> (bool, bool) {
+        if !self.is_jump() || self.modes[0] != 1 {
+            return (false, false);
+        }
+        let jumps = (self.params[0] != 0) == (self.two_digit_opcode == 5);
+        (jumps, !jumps)
+    }
+
+    // Jump target if it is an immediate value, None when it comes from memory
+    fn direct_target(&self) -> Option<usize> {
+        if self.modes[1] == 1 && self.params[1] >= 0 { Some(self.params[1] as usize) } else { None }
+    }
+
+    // Absolute addresses of the parameters of a kind that are in position mode
+    fn addresses(&self, kind: ParameterKind) -> Vec<usize> {
+        (0..self.params.len()).filter(|&i| self.kinds[i] == kind && self.modes[i] == 0 && self.params[i] >= 0).map(|i| self.params[i] as usize).collect()
+    }
+
+    // Absolute addresses read and written through position mode parameters
+    fn reads(&self) -> Vec<usize> {
+        self.addresses(ParameterKind::Read)
+    }
+
+    fn writes(&self) -> Vec<usize> {
+        self.addresses(ParameterKind::Write)
+    }
+}
+
+fn decode(instruction_set: &InstructionSet, intopcodes: &[i64], ip: usize) -> Option<Decoded> {
+    let opcode = *intopcodes.get(ip)?;
+    let (two_digit_opcode, modes) = decode_opcode(opcode);
+    let definition = instruction_set.get(two_digit_opcode)?;
+    let param_count = definition.parameters.len();
+    if opcode < 0 || ip + param_count >= intopcodes.len() || modes.iter().zip(&definition.parameters).any(|(&m, &k)| !instruction_set.allows_mode(m, k)) {
+        return None;
+    }
+    Some(Decoded {
+        two_digit_opcode,
+        standard: instruction_set.is_standard(two_digit_opcode),
+        modes,
+        kinds: definition.parameters.clone(),
+        params: intopcodes[ip + 1..=ip + param_count].to_vec(),
+    })
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum EdgeKind {
+    Fallthrough,
+    Jump,
+    // Unconditional jump to the entry of a function
+    Call,
+    // From a call to the address it stored as its return address
+    CallReturn,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BasicBlock {
+    pub start: usize,
+    // One past the last word of the last instruction
+    pub end: usize,
+    pub instructions: Vec<usize>,
+    pub successors: Vec<(usize, EdgeKind)>,
+    // Ends in a jump whose target is read from memory, e.g. a function return
+    pub indirect_jump: bool,
+    pub reads: BTreeSet<usize>,
+    pub writes: BTreeSet<usize>,
+}
+
+// Code reached by jumping to an "arb #n" that sets up a stack frame of n words
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Function {
+    pub entry: usize,
+    pub frame_size: i64,
+    pub blocks: BTreeSet<usize>,
+}
+
+#[derive(Debug, Clone)]
+pub struct ControlFlowGraph {
+    pub blocks: BTreeMap<usize, BasicBlock>,
+    pub functions: Vec<Function>,
+    instructions: BTreeMap<usize, Decoded>,
+    program: Vec<i64>,
+    instruction_set: InstructionSet,
+}
+
+fn frame_size(instructions: &BTreeMap<usize, Decoded>, address: usize) -> Option<i64> {
+    match instructions.get(&address) {
+        Some(d) if d.standard && d.two_digit_opcode == 9 && d.modes[0] == 1 && d.params[0] > 0 => Some(d.params[0]),
+        _ => None
+    }
+}
+
+// Follows every path the program can take from address 0 without running it
+fn discover(instruction_set: &InstructionSet, intopcodes: &[i64], roots: &BTreeSet<usize>, instructions: &mut BTreeMap<usize, Decoded>, leaders: &mut BTreeSet<usize>) {
+    let mut work : Vec<usize> = roots.iter().copied().collect();
+    while let Some(ip) = work.pop() {
+        if instructions.contains_key(&ip) {
+            continue;
+        }
+        let decoded = match decode(instruction_set, intopcodes, ip) {
+            Some(decoded) => decoded,
+            None => continue,
+        };
+
+        let next = ip + decoded.length();
+        if decoded.is_jump() {
+            let (always, never) = decoded.constant_condition();
+            if let Some(target) = decoded.direct_target().filter(|_| !never) {
+                leaders.insert(target);
+                work.push(target);
+            }
+            leaders.insert(next);
+            if !always {
+                work.push(next);
+            }
+        }
+        else if !decoded.halts() {
+            work.push(next);
+        }
+        instructions.insert(ip, decoded);
+    }
+}
+
+fn build_blocks(instructions: &BTreeMap<usize, Decoded>, leaders: &BTreeSet<usize>) -> BTreeMap<usize, BasicBlock> {
+    let mut blocks = BTreeMap::new();
+    let mut current : Option<BasicBlock> = None;
+    for (&ip, decoded) in instructions {
+        if let Some(block) = current.take() {
+            if block.end == ip && !leaders.contains(&ip) {
+                current = Some(block);
+            }
+            else {
+                blocks.insert(block.start, block);
+            }
+        }
+
+        let block = current.get_or_insert_with(|| BasicBlock {
+            start: ip,
+            end: ip,
+            instructions: Vec::new(),
+            successors: Vec::new(),
+            indirect_jump: false,
+            reads: BTreeSet::new(),
+            writes: BTreeSet::new(),
+        });
+        block.instructions.push(ip);
+        block.end = ip + decoded.length();
+        block.reads.extend(decoded.reads());
+        block.writes.extend(decoded.writes());
+
+        if decoded.ends_block() {
+            blocks.insert(block.start, current.take().unwrap());
+        }
+    }
+    if let Some(block) = current {
+        blocks.insert(block.start, block);
+    }
+    blocks
+}
+
+// The return address a call block stores before jumping, e.g. "add #ret, #0, [rb+1]"
+fn stored_return_address(instructions: &BTreeMap<usize, Decoded>, block: &BasicBlock) -> Option<usize> {
+    block.instructions.iter().rev().skip(1).find_map(|ip| {
+        let d = &instructions[ip];
+        if !d.standard || d.modes[0] != 1 || d.modes[1] != 1 || d.modes[2] != 2 {
+            return None;
+        }
+        let value = match d.two_digit_opcode {
+            1 => d.params[0].checked_add(d.params[1])?,
+            2 => d.params[0].checked_mul(d.params[1])?,
+            _ => return None,
+        };
+        if value >= 0 { Some(value as usize) } else { None }
+    })
+}
+
+pub fn analyze(intopcodes: &[i64]) -> ControlFlowGraph {
+    analyze_with(intopcodes, &InstructionSet::standard_shared())
+}
+
+// analyze for a machine with different opcodes or modes
+pub fn analyze_with(intopcodes: &[i64], instruction_set: &InstructionSet) -> ControlFlowGraph {
+    let mut instructions = BTreeMap::new();
+    let mut leaders = BTreeSet::new();
+    let mut roots = BTreeSet::new();
+    roots.insert(0);
+    leaders.insert(0);
+
+    // Return addresses of calls aren't reachable by any jump, so keep adding them as new
+    // starting points until no more turn up
+    let mut blocks;
+    loop {
+        discover(instruction_set, intopcodes, &roots, &mut instructions, &mut leaders);
+        blocks = build_blocks(&instructions, &leaders);
+
+        let mut new_roots = Vec::new();
+        for block in blocks.values() {
+            let last = &instructions[block.instructions.last().unwrap()];
+            let calls_function = last.constant_condition().0 && last.direct_target().and_then(|t| frame_size(&instructions, t)).is_some();
+            if let Some(return_address) = stored_return_address(&instructions, block).filter(|_| calls_function) {
+                if roots.insert(return_address) {
+                    leaders.insert(return_address);
+                    new_roots.push(return_address);
+                }
+            }
+        }
+        if new_roots.is_empty() {
+            break;
+        }
+    }
+
+    // Edges
+    let starts : BTreeSet<usize> = blocks.keys().copied().collect();
+    for block in blocks.values_mut() {
+        let last = &instructions[block.instructions.last().unwrap()];
+        if last.halts() {
+            continue;
+        }
+        if last.is_jump() {
+            let (always, never) = last.constant_condition();
+            if !never {
+                match last.direct_target() {
+                    Some(target) if starts.contains(&target) => {
+                        let is_call = always && frame_size(&instructions, target).is_some();
+                        block.successors.push((target, if is_call { EdgeKind::Call } else { EdgeKind::Jump }));
+                        if is_call {
+                            if let Some(return_address) = stored_return_address(&instructions, block).filter(|a| starts.contains(a)) {
+                                block.successors.push((return_address, EdgeKind::CallReturn));
+                            }
+                        }
+                    },
+                    Some(_) => {},
+                    None => block.indirect_jump = true,
+                }
+            }
+            if always {
+                continue;
+            }
+        }
+        if starts.contains(&block.end) {
+            block.successors.push((block.end, EdgeKind::Fallthrough));
+        }
+    }
+
+    // Functions own the blocks reachable from their entry without going through another call
+    let entries : BTreeSet<usize> = blocks.values()
+        .flat_map(|b| b.successors.iter().filter(|(_, kind)| *kind == EdgeKind::Call).map(|&(target, _)| target))
+        .collect();
+    let mut owned = BTreeSet::new();
+    let mut functions = Vec::new();
+    for &entry in &entries {
+        let mut function = Function { entry, frame_size: frame_size(&instructions, entry).unwrap(), blocks: BTreeSet::new() };
+        let mut work = VecDeque::from(vec![entry]);
+        while let Some(start) = work.pop_front() {
+            if owned.contains(&start) || (start != entry && entries.contains(&start)) {
+                continue;
+            }
+            owned.insert(start);
+            function.blocks.insert(start);
+            for &(successor, kind) in &blocks[&start].successors {
+                if kind != EdgeKind::Call {
+                    work.push_back(successor);
+                }
+            }
+        }
+        functions.push(function);
+    }
+
+    ControlFlowGraph { blocks, functions, instructions, program: intopcodes.to_vec(), instruction_set: instruction_set.clone() }
+}
+
+impl ControlFlowGraph {
+    pub fn block_containing(&self, address: usize) -> Option<&BasicBlock> {
+        self.blocks.range(..=address).next_back().map(|(_, block)| block).filter(|block| address < block.end)
+    }
+
+    // Addresses of the instructions that write directly to address, e.g. to find what updates a score
+    pub fn writers_of(&self, address: usize) -> Vec<usize> {
+        self.instructions.iter().filter(|(_, d)| d.writes().contains(&address)).map(|(&ip, _)| ip).collect()
+    }
+
+    // Addresses of the instructions that read address through a position mode parameter
+    pub fn readers_of(&self, address: usize) -> Vec<usize> {
+        self.instructions.iter().filter(|(_, d)| d.reads().contains(&address)).map(|(&ip, _)| ip).collect()
+    }
+
+    fn block_label(&self, block: &BasicBlock) -> String {
+        let mut label = String::new();
+        for &ip in &block.instructions {
+            let text = disassemble_instruction_with(&self.program, ip, &self.instruction_set).map_or_else(|| format!(".data {}", self.program[ip]), |(text, _)| text);
+            label.push_str(&format!("{}: {}\\l", ip, text));
+        }
+        if !block.writes.is_empty() {
+            let writes : Vec<String> = block.writes.iter().map(|a| a.to_string()).collect();
+            label.push_str(&format!("writes {}\\l", writes.join(", ")));
+        }
+        label
+    }
+
+    // Graphviz DOT, with each function in its own cluster
+    pub fn to_dot(&self) -> String {
+        let mut lines = vec![
+            String::from("digraph intcode {"),
+            String::from("    node [shape=box, fontname=\"monospace\"];"),
+        ];
+
+        let mut in_function = BTreeSet::new();
+        for function in &self.functions {
+            lines.push(format!("    subgraph cluster_{} {{", function.entry));
+            lines.push(format!("        label=\"function {} (frame {})\";", function.entry, function.frame_size));
+            for start in &function.blocks {
+                lines.push(format!("        b{} [label=\"{}\"];", start, self.block_label(&self.blocks[start])));
+                in_function.insert(*start);
+            }
+            lines.push(String::from("    }"));
+        }
+        for block in self.blocks.values().filter(|b| !in_function.contains(&b.start)) {
+            lines.push(format!("    b{} [label=\"{}\"];", block.start, self.block_label(block)));
+        }
+
+        let mut has_indirect = false;
+        for block in self.blocks.values() {
+            for (successor, kind) in &block.successors {
+                let style = match kind {
+                    EdgeKind::Fallthrough => "",
+                    EdgeKind::Jump => " [label=\"jump\"]",
+                    EdgeKind::Call => " [label=\"call\", style=bold]",
+                    EdgeKind::CallReturn => " [label=\"return\", style=dashed]",
+                };
+                lines.push(format!("    b{} -> b{}{};", block.start, successor, style));
+            }
+            if block.indirect_jump {
+                has_indirect = true;
+                lines.push(format!("    b{} -> indirect [style=dotted];", block.start));
+            }
+        }
+        if has_indirect {
+            lines.push(String::from("    indirect [shape=ellipse, label=\"indirect jump\"];"));
+        }
+
+        lines.push(String::from("}"));
+        let mut dot = lines.join("\n");
+        dot.push('\n');
+        dot
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intopcode_asm::assemble;
+    use crate::process_simple_intopcode;
+
+    #[test]
+    fn control_flow_graph_test() {
+        let program = assemble("
+                    arb #stack
+                    add #ret, #0, [rb+0]
+                    jz #0, #double
+            ret:    out [value]
+                    hlt
+            double: arb #1
+                    mul [value], #2, [value]
+                    arb #-1
+                    jz #0, [rb+0]
+            value:  .data 21
+            stack:  .data 0
+        ").unwrap();
+        assert!( process_simple_intopcode(&program).is_ok() );
+
+        let cfg = analyze(&program);
+        assert_eq!( cfg.blocks.keys().copied().collect::<Vec<usize>>(), vec![0, 9, 12] );
+        assert_eq!( cfg.blocks[&0].successors, vec![(12, EdgeKind::Call), (9, EdgeKind::CallReturn)] );
+        assert_eq!( cfg.blocks[&9].successors, vec![] );
+        assert!( cfg.blocks[&12].indirect_jump );
+        assert_eq!( cfg.blocks[&12].writes, vec![23].into_iter().collect() );
+        assert_eq!( cfg.functions, vec![Function { entry: 12, frame_size: 1, blocks: vec![12].into_iter().collect() }] );
+
+        assert_eq!( cfg.writers_of(23), vec![14] );
+        assert_eq!( cfg.readers_of(23), vec![9, 14] );
+        assert_eq!( cfg.block_containing(16).map(|b| b.start), Some(12) );
+        assert_eq!( cfg.block_containing(23), None );
+
+        let dot = cfg.to_dot();
+        assert!( dot.starts_with("digraph intcode {\n") );
+        assert!( dot.contains("    subgraph cluster_12 {\n        label=\"function 12 (frame 1)\";\n") );
+        assert!( dot.contains("    b0 -> b12 [label=\"call\", style=bold];\n    b0 -> b9 [label=\"return\", style=dashed];\n") );
+        assert!( dot.contains("    b12 -> indirect [style=dotted];\n") );
+    }
+
+    #[test]
+    fn conditional_jumps_test() {
+        // Loop counting [10] down to 0 then halting, data after the halt is never decoded
+        let program = vec![1001,10,-1,10,1005,10,0,99,42,42,3];
+        let cfg = analyze(&program);
+        assert_eq!( cfg.blocks.keys().copied().collect::<Vec<usize>>(), vec![0, 7] );
+        assert_eq!( cfg.blocks[&0].successors, vec![(0, EdgeKind::Jump), (7, EdgeKind::Fallthrough)] );
+        assert_eq!( cfg.blocks[&0].reads, vec![10].into_iter().collect() );
+        assert!( cfg.functions.is_empty() );
+    }
+
+    #[test]
+    fn instruction_set_test() {
+        use crate::intopcode_isa::OpcodeDefinition;
+        let mut set = InstructionSet::standard();
+        set.register(OpcodeDefinition { opcode: 10, mnemonic: "div", parameters: vec![ParameterKind::Read, ParameterKind::Read, ParameterKind::Write], day: 0, execute: |_| Ok(()) }).unwrap();
+
+        // [9] = [9] / 2 until it reaches 0
+        let program = vec![1010,9,2,9,1005,9,0,99,0,40];
+        assert_eq!( analyze(&program).blocks.keys().copied().collect::<Vec<usize>>(), Vec::<usize>::new() );
+        let cfg = analyze_with(&program, &set);
+        assert_eq!( cfg.blocks.keys().copied().collect::<Vec<usize>>(), vec![0, 7] );
+        assert_eq!( cfg.blocks[&0].successors, vec![(0, EdgeKind::Jump), (7, EdgeKind::Fallthrough)] );
+        assert_eq!( (cfg.readers_of(9), cfg.writers_of(9)), (vec![0, 4], vec![0]) );
+        assert!( cfg.to_dot().contains("0: div [9], #2, [9]\\l4: jnz [9], #0\\l") );
+    }
+}
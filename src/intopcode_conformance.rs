@@ -0,0 +1,261 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::{amplifier_network, IntOpCodeComp};
+use crate::intopcode_budget::{RunBudget, RunOutcome};
+use crate::intopcode_network::NetworkStatus;
+
+// Cases that run longer than this are failed rather than left to hang the suite
+const CASE_STEP_LIMIT: u64 = 10_000_000;
+
+// One program run with what it should produce. Amplifier cases chain a copy of the program
+// per phase instead of taking inputs, and expect the last amplifier's final output.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TestCase {
+    pub name: String,
+    // Line of the case header, for finding it again when it fails
+    pub line: usize,
+    pub program: Vec<i64>,
+    pub inputs: Vec<i64>,
+    pub phases: Option<Vec<i64>>,
+    pub feedback: bool,
+    pub expected_output: Option<Vec<i64>>,
+    pub expected_memory: Option<Vec<i64>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaseError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for CaseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "test case line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for CaseError {}
+
+fn parse_values(line: usize, text: &str) -> Result<Vec<i64>, CaseError> {
+    if text.is_empty() {
+        return Ok(Vec::new());
+    }
+    text.split(',').map(|v| v.trim().parse().map_err(|_| CaseError { line, message: format!("invalid number '{}'", v.trim()) })).collect()
+}
+
+fn finish_case(case: TestCase) -> Result<TestCase, CaseError> {
+    let error = |message: &str| Err(CaseError { line: case.line, message: format!("case '{}' {}", case.name, message) });
+    if case.program.is_empty() {
+        return error("has no program");
+    }
+    if case.expected_output.is_none() && case.expected_memory.is_none() {
+        return error("has no output or memory to check");
+    }
+    if case.phases.is_some() && (!case.inputs.is_empty() || case.expected_memory.is_some()) {
+        return error("runs amplifiers, which take no input and have no single memory to check");
+    }
+    if case.feedback && case.phases.is_none() {
+        return error("has feedback without phases");
+    }
+    Ok(case)
+}
+
+// Text format, one case after another:
+//   case <name>
+//   program <comma separated values>
+//   input <comma separated values>       optional
+//   phases <comma separated values>      optional, runs amplifiers instead
+//   feedback                             optional, loops the last amplifier back to the first
+//   output <comma separated values>      optional, checked if given
+//   memory <comma separated values>      optional, the whole memory after the halt
+// Blank lines and lines starting with # are skipped.
+pub fn parse_cases(text: &str) -> Result<Vec<TestCase>, CaseError> {
+    let mut cases = Vec::new();
+    let mut current : Option<TestCase> = None;
+    for (i, l) in text.lines().enumerate() {
+        let (line, l) = (i + 1, l.trim());
+        if l.is_empty() || l.starts_with('#') {
+            continue;
+        }
+        let (key, value) = match l.find(' ') {
+            Some(space) => (&l[..space], l[space + 1..].trim()),
+            None => (l, ""),
+        };
+
+        if key == "case" {
+            if value.is_empty() {
+                return Err(CaseError { line, message: String::from("expected 'case <name>'") });
+            }
+            if let Some(case) = current.take() {
+                cases.push(finish_case(case)?);
+            }
+            current = Some(TestCase {
+                name: value.to_string(),
+                line,
+                program: Vec::new(),
+                inputs: Vec::new(),
+                phases: None,
+                feedback: false,
+                expected_output: None,
+                expected_memory: None,
+            });
+            continue;
+        }
+
+        let case = current.as_mut().ok_or(CaseError { line, message: String::from("expected 'case <name>' first") })?;
+        match key {
+            "program" => case.program = parse_values(line, value)?,
+            "input" => case.inputs = parse_values(line, value)?,
+            "phases" => case.phases = Some(parse_values(line, value)?),
+            "feedback" => case.feedback = true,
+            "output" => case.expected_output = Some(parse_values(line, value)?),
+            "memory" => case.expected_memory = Some(parse_values(line, value)?),
+            _ => return Err(CaseError { line, message: format!("unknown key '{}'", key) }),
+        }
+    }
+    if let Some(case) = current {
+        cases.push(finish_case(case)?);
+    }
+    Ok(cases)
+}
+
+impl TestCase {
+    // Runs the case on IntOpCodeComp, describing the first thing that didn't match
+    pub fn run(&self) -> Result<(), String> {
+        self.run_with_limit(CASE_STEP_LIMIT)
+    }
+
+    fn run_with_limit(&self, step_limit: u64) -> Result<(), String> {
+        let budget = RunBudget { max_steps: Some(step_limit), detect_loops: true, ..RunBudget::default() };
+        if let Some(phases) = &self.phases {
+            // the budget limits each amplifier's turn, the steps of every turn together are limited too
+            let mut amps = amplifier_network(&self.program, phases, self.feedback);
+            amps.set_budget(budget);
+            loop {
+                match amps.tick().map_err(|error| error.to_string())? {
+                    NetworkStatus::Running if amps.steps() >= step_limit => return Err(format!("didn't halt after {} steps", amps.steps())),
+                    NetworkStatus::Running => {},
+                    NetworkStatus::OverBudget { machine, outcome } => return Err(format!("amplifier {} didn't halt, {}", machine, outcome)),
+                    _ => break,
+                }
+            }
+            let output = amps.last_output(phases.len() - 1).unwrap_or(0);
+            return self.check("output", &self.expected_output, &[output]);
+        }
+
+        let mut comp = IntOpCodeComp::new(&self.program, &self.inputs.iter().copied().collect());
+        match comp.run_with_budget(&budget).map_err(|error| error.to_string())? {
+            RunOutcome::Halted { .. } => {},
+            outcome => return Err(format!("didn't halt, {}", outcome)),
+        }
+        let outputs : Vec<i64> = comp.output_values.iter().copied().collect();
+        self.check("output", &self.expected_output, &outputs)?;
+        self.check("memory", &self.expected_memory, &comp.memory().to_vec().map_err(|error| error.to_string())?)
+    }
+
+    fn check(&self, what: &str, expected: &Option<Vec<i64>>, actual: &[i64]) -> Result<(), String> {
+        match expected {
+            Some(expected) if expected[..] != *actual => Err(format!("expected {} {:?} but got {:?}", what, expected, actual)),
+            _ => Ok(())
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CaseResult {
+    pub file: String,
+    pub name: String,
+    pub line: usize,
+    pub failure: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConformanceReport {
+    pub results: Vec<CaseResult>,
+}
+
+impl ConformanceReport {
+    pub fn failures(&self) -> impl Iterator<Item = &CaseResult> {
+        self.results.iter().filter(|r| r.failure.is_some())
+    }
+
+    pub fn all_passed(&self) -> bool {
+        self.failures().next().is_none()
+    }
+}
+
+impl fmt::Display for ConformanceReport {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for result in self.failures() {
+            writeln!(f, "FAIL {}:{} {}: {}", result.file, result.line, result.name, result.failure.as_ref().unwrap())?;
+        }
+        write!(f, "{} passed, {} failed", self.results.len() - self.failures().count(), self.failures().count())
+    }
+}
+
+// Runs every case in every .cases file in the directory, in file name order
+pub fn run_directory<P: AsRef<Path>>(path: P) -> Result<ConformanceReport, Box<dyn Error>> {
+    let mut files : Vec<_> = fs::read_dir(path)?
+        .map(|entry| entry.map(|e| e.path()))
+        .collect::<Result<Vec<_>, _>>()?;
+    files.retain(|file| file.extension().is_some_and(|e| e == "cases"));
+    files.sort();
+
+    let mut report = ConformanceReport::default();
+    for file in files {
+        let name = file.display().to_string();
+        let cases = parse_cases(&fs::read_to_string(&file)?).map_err(|error| format!("{}: {}", name, error))?;
+        for case in cases {
+            report.results.push(CaseResult { file: name.clone(), name: case.name.clone(), line: case.line, failure: case.run().err() });
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn conformance_suite_test() {
+        let report = run_directory("conformance").unwrap();
+        assert!( report.all_passed(), "{}", report );
+        assert!( report.results.len() >= 20 );
+    }
+
+    #[test]
+    fn parse_cases_test() {
+        let cases = parse_cases("
+            # adds two numbers
+            case add
+            program 1101,2,3,5,99,0
+            memory 1101,2,3,5,99,5
+
+            case echo
+            program 3,0,4,0,99
+            input 7
+            output 7
+        ").unwrap();
+        assert_eq!( cases.len(), 2 );
+        assert_eq!( (cases[1].name.as_str(), cases[1].line, &cases[1].inputs), ("echo", 7, &vec![7]) );
+        assert!( cases.iter().all(|case| case.run().is_ok()) );
+
+        let wrong = parse_cases("case wrong\nprogram 104,1,99\noutput 2").unwrap();
+        assert_eq!( wrong[0].run(), Err(String::from("expected output [2] but got [1]")) );
+        let stuck = parse_cases("case stuck\nprogram 3,0,99\noutput").unwrap();
+        assert_eq!( stuck[0].run(), Err(String::from("didn't halt, waiting on input after 0 steps")) );
+        // passes the signal round forever, adding one each time
+        let endless = parse_cases("case endless\nprogram 3,9,1001,9,1,9,4,9,1105,1,0\nphases 0,1\nfeedback\noutput 1").unwrap();
+        assert_eq!( endless[0].run_with_limit(1000), Err(String::from("didn't halt after 1004 steps")) );
+        let spinning = parse_cases("case spinning\nprogram 3,7,4,7,1105,1,4,0\nphases 0,1\noutput 1").unwrap();
+        assert_eq!( spinning[0].run(), Err(String::from("amplifier 0 didn't halt, non-terminating: the state at ip 4 repeats every 1 steps with no input or output, stopped after 3 steps")) );
+
+        assert_eq!( parse_cases("program 99"), Err(CaseError { line: 1, message: String::from("expected 'case <name>' first") }) );
+        assert_eq!( parse_cases("case a\nprogram 1,x").unwrap_err().message, "invalid number 'x'" );
+        assert_eq!( parse_cases("case a\nprogram 99").unwrap_err().message, "case 'a' has no output or memory to check" );
+        assert_eq!( parse_cases("case a\nprograms 99").unwrap_err().message, "unknown key 'programs'" );
+    }
+}
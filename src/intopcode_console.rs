@@ -0,0 +1,175 @@
+use std::error::Error;
+use std::io;
+use std::io::{BufRead, Write};
+
+use crate::{IntOpCodeComp, IntOpCodeError, RunState};
+use crate::intopcode_io::{AsciiInput, OutputDevice};
+
+// Writes outputs as text as soon as they arrive. Values that aren't ASCII are written as a
+// number on a line of their own, since they are usually the puzzle answer.
+pub struct AsciiStream<W: Write> {
+    writer: W,
+    at_line_start: bool,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> AsciiStream<W> {
+    pub fn new(writer: W) -> AsciiStream<W> {
+        AsciiStream { writer, at_line_start: true, error: None }
+    }
+
+    fn record(&mut self, result: io::Result<()>) {
+        if let (None, Err(error)) = (&self.error, result) {
+            self.error = Some(error);
+        }
+    }
+
+    // Writes a line that didn't come from the program, e.g. a scripted command
+    pub fn echo(&mut self, line: &str) {
+        let prefix = if self.at_line_start { "" } else { "\n" };
+        let result = writeln!(self.writer, "{}{}", prefix, line);
+        self.record(result);
+        self.at_line_start = true;
+    }
+
+    pub fn flush(&mut self) {
+        let result = self.writer.flush();
+        self.record(result);
+    }
+
+    // Flushes the stream, returning the first error hit while writing it
+    pub fn finish(mut self) -> io::Result<W> {
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write> OutputDevice for AsciiStream<W> {
+    fn write(&mut self, value: i64) {
+        if (0..=127).contains(&value) {
+            let result = self.writer.write_all(&[value as u8]);
+            self.record(result);
+            self.at_line_start = value == i64::from(b'\n');
+        }
+        else {
+            self.echo(&value.to_string());
+        }
+    }
+}
+
+// Plays a program that talks in lines of text, e.g. a text adventure
+pub struct Console<W: Write> {
+    comp: IntOpCodeComp,
+    output: AsciiStream<W>,
+}
+
+impl<W: Write> Console<W> {
+    pub fn new(comp: IntOpCodeComp, writer: W) -> Console<W> {
+        Console { comp, output: AsciiStream::new(writer) }
+    }
+
+    pub fn comp(&self) -> &IntOpCodeComp {
+        &self.comp
+    }
+
+    // Runs until the program wants more input than it has or halts
+    pub fn run(&mut self) -> Result<RunState, IntOpCodeError> {
+        self.send(AsciiInput::default())
+    }
+
+    // Sends one line of text, adding the newline
+    pub fn send_line(&mut self, line: &str) -> Result<RunState, IntOpCodeError> {
+        let mut input = AsciiInput::default();
+        input.push_line(line);
+        self.send(input)
+    }
+
+    fn send(&mut self, mut input: AsciiInput) -> Result<RunState, IntOpCodeError> {
+        let state = self.comp.run_with_devices(&mut input, &mut self.output)?;
+        self.output.flush();
+        Ok(state)
+    }
+
+    // Sends every line of the script in turn, echoing each one so the output reads like a
+    // played session. Lines starting with # are comments. Stops early if the program halts.
+    pub fn run_script(&mut self, script: &str) -> Result<RunState, IntOpCodeError> {
+        let mut state = self.run()?;
+        for line in script.lines().filter(|line| !line.starts_with('#')) {
+            if state == RunState::Halted {
+                break;
+            }
+            self.output.echo(line);
+            state = self.send_line(line)?;
+        }
+        Ok(state)
+    }
+
+    // Sends lines read from the reader, e.g. stdin, until the program halts or the reader ends.
+    // Fails on the first error from either the program or the reader.
+    pub fn run_interactive<R: BufRead>(&mut self, mut reader: R) -> Result<RunState, Box<dyn Error>> {
+        let mut state = self.run()?;
+        while state == RunState::WaitingOnInput {
+            let mut line = String::new();
+            if reader.read_line(&mut line)? == 0 {
+                break;
+            }
+            state = self.send_line(line.trim_end_matches(['\n', '\r']))?;
+        }
+        Ok(state)
+    }
+
+    pub fn finish(self) -> io::Result<W> {
+        self.output.finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+    use crate::intopcode_asm::assemble;
+
+    // Echoes every character until it reads a '!', then outputs 1000 and halts
+    fn echo_program() -> Vec<i64> {
+        assemble("
+            loop:   in [c]
+                    eq [c], #33, [bang]
+                    jnz [bang], #done
+                    out [c]
+                    jz #0, #loop
+            done:   out #1000
+                    hlt
+            c:      .data 0
+            bang:   .data 0
+        ").unwrap()
+    }
+
+    #[test]
+    fn ascii_stream_test() {
+        let mut stream = AsciiStream::new(Vec::new());
+        for &value in &[72, 105, 200, 10, -5] {
+            stream.write(value);
+        }
+        assert_eq!( String::from_utf8(stream.finish().unwrap()).unwrap(), "Hi\n200\n\n-5\n" );
+    }
+
+    #[test]
+    fn scripted_and_interactive_test() {
+        let mut console = Console::new(IntOpCodeComp::new(&echo_program(), &VecDeque::new()), Vec::new());
+        assert_eq!( console.run_script("hi\n# not sent\nyo\n!\nnever sent"), Ok(RunState::Halted) );
+        assert_eq!( String::from_utf8(console.finish().unwrap()).unwrap(), "hi\nhi\nyo\nyo\n!\n1000\n" );
+
+        let mut console = Console::new(IntOpCodeComp::new(&echo_program(), &VecDeque::new()), Vec::new());
+        assert_eq!( console.run_interactive(io::Cursor::new("ab\r\ncd\n")).unwrap(), RunState::WaitingOnInput );
+        assert_eq!( console.run_interactive(io::Cursor::new("!\n")).unwrap(), RunState::Halted );
+        assert_eq!( String::from_utf8(console.finish().unwrap()).unwrap(), "ab\ncd\n1000\n" );
+
+        // a line that isn't UTF-8 can't be read, which is passed on rather than taken as the end
+        let mut console = Console::new(IntOpCodeComp::new(&echo_program(), &VecDeque::new()), Vec::new());
+        assert_eq!( console.run_interactive(io::Cursor::new(b"ab\n\xff\n".to_vec())).unwrap_err().to_string(), "stream did not contain valid UTF-8" );
+        assert_eq!( String::from_utf8(console.finish().unwrap()).unwrap(), "ab\n" );
+    }
+}
@@ -0,0 +1,404 @@
+use std::collections::BTreeSet;
+use std::collections::VecDeque;
+use std::io::BufRead;
+use std::io::Write;
+
+use crate::IntOpCodeComp;
+use crate::RunState;
+use crate::intopcode_asm::disassemble_instruction_with;
+
+const HELP: &str = "\
+commands:
+  s, step [n]         execute n instructions (default 1)
+  c, continue [n]     run until a breakpoint, watchpoint, input request or halt, giving up
+                      after n instructions (default 1000000) in case the program loops forever
+  rs, rstep [n]       undo the last n instructions (default 1), the last 100000 are kept
+  rc, rcontinue       undo instructions until a breakpoint, watchpoint or the oldest kept
+  l, lastwrite <addr> show which instruction last wrote to addr
+  b, break <ip>       stop when the instruction pointer reaches ip
+  w, watch <addr>     stop after an instruction changes memory at addr
+  d, delete <ip>      remove the breakpoint at ip
+  u, unwatch <addr>   remove the watchpoint at addr
+  i, input <v>...     queue input values
+  m, mem <addr> [n]   print n memory values starting at addr (default 1, at most 1000)
+  r, regs             print the machine state
+  h, help             print this message
+  q, quit             leave the debugger";
+
+// Most instructions a continue runs when it isn't told how many
+const CONTINUE_STEP_LIMIT: u64 = 1_000_000;
+
+// Most values a mem command prints
+const MEM_VALUE_LIMIT: usize = 1000;
+
+// Most instructions that can be undone, older ones are forgotten so a long run doesn't
+// fill up memory with its history
+const HISTORY_STEP_LIMIT: usize = 100_000;
+
+// Wraps a single IntOpCodeComp and drives it one tick at a time so its state can be inspected in between
+pub struct Debugger {
+    comp: IntOpCodeComp,
+    breakpoints: BTreeSet<usize>,
+    watchpoints: BTreeSet<usize>,
+    steps: u64,
+}
+
+impl Debugger {
+    pub fn new(intopcodes: &[i64], input_values: &VecDeque<i64>) -> Debugger {
+        // recorded so it can step backwards too
+        let mut comp = IntOpCodeComp::new(intopcodes, input_values);
+        comp.start_recording_with_limit(HISTORY_STEP_LIMIT);
+        Debugger {
+            comp,
+            breakpoints: BTreeSet::new(),
+            watchpoints: BTreeSet::new(),
+            steps: 0,
+        }
+    }
+
+    fn read_memory(&self, address: usize) -> i64 {
+        self.comp.memory.read(address)
+    }
+
+    fn current_instruction(&self) -> String {
+        // the longest instruction is 4 words
+        let words : Vec<i64> = (self.comp.index..self.comp.index + 4).map(|a| self.read_memory(a)).collect();
+        match disassemble_instruction_with(&words, 0, self.comp.instruction_set()) {
+            Some((text, _)) => text,
+            None => format!(".data {}", self.read_memory(self.comp.index))
+        }
+    }
+
+    pub fn state(&self) -> String {
+        let status = match self.comp.state {
+            RunState::Running => "running",
+            RunState::WaitingOnInput => "waiting on input",
+            RunState::Halted => "halted",
+        };
+
+        let mut lines = vec![
+            format!("ip {}  relative_base {}  steps {}  status {}", self.comp.index, self.comp.relative_base, self.steps, status),
+            format!("input  {:?}", self.comp.input_values),
+            format!("output {:?}", self.comp.output_values),
+        ];
+        if self.comp.state != RunState::Halted {
+            lines.push(format!("next   {}: {}", self.comp.index, self.current_instruction()));
+        }
+        lines.join("\n")
+    }
+
+    // Executes a single instruction, returning why execution should stop if it should
+    fn step(&mut self) -> Option<String> {
+        if self.comp.state == RunState::Halted {
+            return Some(String::from("program has halted"));
+        }
+
+        let ip = self.comp.index;
+        let watched : Vec<(usize, i64)> = self.watchpoints.iter().map(|&a| (a, self.read_memory(a))).collect();
+        if let Err(error) = self.comp.tick() {
+            return Some(format!("error: {}", error));
+        }
+
+        if self.comp.state == RunState::WaitingOnInput {
+            return Some(String::from("waiting on input, queue some with 'input'"));
+        }
+        self.steps += 1;
+
+        let mut hits = Vec::new();
+        for (address, old_value) in watched {
+            let new_value = self.read_memory(address);
+            if new_value != old_value {
+                hits.push(format!("watchpoint [{}]: {} -> {} by ip {}", address, old_value, new_value, ip));
+            }
+        }
+
+        if self.comp.state == RunState::Halted {
+            hits.push(String::from("program halted"));
+        }
+        else if self.breakpoints.contains(&self.comp.index) {
+            hits.push(format!("breakpoint at ip {}", self.comp.index));
+        }
+
+        if hits.is_empty() { None } else { Some(hits.join("\n")) }
+    }
+
+    // Executes up to count instructions. A continue that gets that far says so, since it
+    // was meant to stop somewhere else.
+    fn run_steps(&mut self, count: u64, continuing: bool) -> String {
+        let mut executed = 0;
+        let reason = loop {
+            if executed == count {
+                break if continuing { Some(format!("no stop after {} steps, 'continue' to keep going", count)) } else { None };
+            }
+            executed += 1;
+            if let Some(reason) = self.step() {
+                break Some(reason);
+            }
+        };
+
+        match reason {
+            Some(reason) => format!("{}\n{}", reason, self.state()),
+            None => self.state()
+        }
+    }
+
+    // Undoes a single instruction, returning why rewinding should stop if it should
+    fn step_back(&mut self) -> Option<String> {
+        let watched : Vec<(usize, i64)> = self.watchpoints.iter().map(|&a| (a, self.read_memory(a))).collect();
+        if self.comp.rewind(1) == 0 {
+            if self.forgotten_steps() > 0 {
+                return Some(format!("at the oldest step kept, only the last {} are", HISTORY_STEP_LIMIT));
+            }
+            return Some(String::from("at the start of the recording"));
+        }
+        self.steps -= 1;
+
+        let mut hits = Vec::new();
+        for (address, new_value) in watched {
+            let old_value = self.read_memory(address);
+            if new_value != old_value {
+                hits.push(format!("watchpoint [{}]: {} <- {} by ip {}", address, old_value, new_value, self.comp.index));
+            }
+        }
+        if self.breakpoints.contains(&self.comp.index) {
+            hits.push(format!("breakpoint at ip {}", self.comp.index));
+        }
+
+        if hits.is_empty() { None } else { Some(hits.join("\n")) }
+    }
+
+    fn rewind_steps(&mut self, count: Option<u64>) -> String {
+        let mut rewound = 0;
+        let reason = loop {
+            if count == Some(rewound) {
+                break None;
+            }
+            rewound += 1;
+            if let Some(reason) = self.step_back() {
+                break Some(reason);
+            }
+        };
+
+        match reason {
+            Some(reason) => format!("{}\n{}", reason, self.state()),
+            None => self.state()
+        }
+    }
+
+    fn last_write(&self, address: usize) -> String {
+        match self.comp.last_write(address) {
+            Some(event) => format!("[{}] last written by ip {} at step {}: {} -> {}", address, event.ip, event.step + 1, event.write.old_value, event.write.new_value),
+            None if self.forgotten_steps() > 0 => format!("[{}] hasn't been written to in the last {} steps", address, HISTORY_STEP_LIMIT),
+            None => format!("[{}] hasn't been written to", address)
+        }
+    }
+
+    fn forgotten_steps(&self) -> usize {
+        self.comp.history().map_or(0, |history| history.first_step())
+    }
+
+    // Runs a single debugger command and returns the text to show for it
+    pub fn execute(&mut self, command_line: &str) -> String {
+        let mut words = command_line.split_whitespace();
+        let command = match words.next() {
+            Some(command) => command,
+            None => return String::new()
+        };
+        let args : Result<Vec<i64>, _> = words.map(|w| w.parse::<i64>()).collect();
+        let args = match args {
+            Ok(args) => args,
+            Err(error) => return format!("error: {}", error)
+        };
+        let address = |i: usize| -> Result<usize, String> {
+            match args.get(i) {
+                Some(&val) if val >= 0 => Ok(val as usize),
+                Some(val) => Err(format!("error: {} is not a valid address", val)),
+                None => Err(format!("error: '{}' needs an address", command))
+            }
+        };
+
+        match command {
+            "s" | "step" => {
+                let count = args.first().copied().unwrap_or(1);
+                if count < 1 {
+                    return String::from("error: step count must be positive");
+                }
+                self.run_steps(count as u64, false)
+            },
+            "c" | "continue" => {
+                let count = args.first().copied().unwrap_or(CONTINUE_STEP_LIMIT as i64);
+                if count < 1 {
+                    return String::from("error: step count must be positive");
+                }
+                self.run_steps(count as u64, true)
+            },
+            "rs" | "rstep" => {
+                let count = args.first().copied().unwrap_or(1);
+                if count < 1 {
+                    return String::from("error: step count must be positive");
+                }
+                self.rewind_steps(Some(count as u64))
+            },
+            "rc" | "rcontinue" => self.rewind_steps(None),
+            "l" | "lastwrite" => match address(0) {
+                Ok(addr) => self.last_write(addr),
+                Err(error) => error
+            },
+            "b" | "break" => match address(0) {
+                Ok(ip) => {
+                    self.breakpoints.insert(ip);
+                    format!("breakpoint set at ip {}", ip)
+                },
+                Err(error) => error
+            },
+            "w" | "watch" => match address(0) {
+                Ok(addr) => {
+                    self.watchpoints.insert(addr);
+                    format!("watching [{}] = {}", addr, self.read_memory(addr))
+                },
+                Err(error) => error
+            },
+            "d" | "delete" => match address(0) {
+                Ok(ip) if self.breakpoints.remove(&ip) => format!("removed the breakpoint at ip {}", ip),
+                Ok(ip) => format!("error: no breakpoint at ip {}", ip),
+                Err(error) => error
+            },
+            "u" | "unwatch" => match address(0) {
+                Ok(addr) if self.watchpoints.remove(&addr) => format!("stopped watching [{}]", addr),
+                Ok(addr) => format!("error: not watching [{}]", addr),
+                Err(error) => error
+            },
+            "i" | "input" => {
+                self.comp.input_values.extend(args.iter());
+                format!("input  {:?}", self.comp.input_values)
+            },
+            "m" | "mem" => match address(0) {
+                Ok(addr) => {
+                    let count = args.get(1).copied().unwrap_or(1).clamp(1, MEM_VALUE_LIMIT as i64) as usize;
+                    let end = match addr.checked_add(count) {
+                        Some(end) => end,
+                        None => return format!("error: [{}] is too close to the end of memory for {} values", addr, count)
+                    };
+                    let values : Vec<String> = (addr..end).map(|a| format!("[{}] = {}", a, self.read_memory(a))).collect();
+                    values.join("\n")
+                },
+                Err(error) => error
+            },
+            "r" | "regs" => self.state(),
+            "h" | "help" => String::from(HELP),
+            _ => format!("error: unknown command '{}', try 'help'", command)
+        }
+    }
+}
+
+// Interactive debugger session reading commands from stdin
+pub fn run_debugger(intopcodes: &[i64], input_values: &VecDeque<i64>) {
+    let mut debugger = Debugger::new(intopcodes, input_values);
+    println!("{}", debugger.state());
+
+    let stdin = std::io::stdin();
+    loop {
+        print!("(intdbg) ");
+        std::io::stdout().flush().unwrap();
+
+        let mut line = String::new();
+        match stdin.lock().read_line(&mut line) {
+            Ok(0) => break,
+            Ok(_) => {
+                let trimmed = line.trim();
+                if trimmed == "q" || trimmed == "quit" {
+                    break;
+                }
+                let output = debugger.execute(trimmed);
+                if !output.is_empty() {
+                    println!("{}", output);
+                }
+            },
+            Err(error) => {
+                println!("error: {}", error);
+                break;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn step_and_breakpoint_test() {
+        let test = vec![3,9,8,9,10,9,4,9,99,-1,8];
+        let mut debugger = Debugger::new(&test, &VecDeque::new());
+        assert!(debugger.execute("step").starts_with("waiting on input"));
+        assert_eq!(debugger.comp.index, 0);
+
+        debugger.execute("input 8");
+        debugger.execute("break 6");
+        assert!(debugger.execute("continue").starts_with("breakpoint at ip 6"));
+        assert_eq!(debugger.steps, 2);
+        assert!(debugger.state().contains("next   6: out [9]"));
+
+        assert!(debugger.execute("c").starts_with("program halted"));
+        assert_eq!(debugger.comp.output_values, vec![1]);
+    }
+
+    #[test]
+    fn watchpoint_test() {
+        let test = vec![109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99];
+        let mut debugger = Debugger::new(&test, &VecDeque::new());
+        assert_eq!(debugger.execute("watch 100"), "watching [100] = 0");
+        assert!(debugger.execute("c").starts_with("watchpoint [100]: 0 -> 1 by ip 4"));
+        assert!(debugger.execute("c").starts_with("watchpoint [100]: 1 -> 2 by ip 4"));
+        assert!(debugger.state().contains("relative_base 2"));
+        assert_eq!(debugger.execute("mem 100 2"), "[100] = 2\n[101] = 0");
+
+        assert_eq!(debugger.execute("delete 100"), "error: no breakpoint at ip 100");
+        assert_eq!(debugger.execute("unwatch 100"), "stopped watching [100]");
+        assert_eq!(debugger.execute("unwatch 100"), "error: not watching [100]");
+        assert!(debugger.execute("c").starts_with("program halted"));
+        assert_eq!(debugger.comp.output_values, test);
+    }
+
+    #[test]
+    fn limits_test() {
+        // jumps to itself forever
+        let mut debugger = Debugger::new(&[1105,1,0], &VecDeque::new());
+        assert!(debugger.execute("c 50").starts_with("no stop after 50 steps"));
+        assert!(debugger.execute("c").starts_with("no stop after 1000000 steps"));
+        assert_eq!(debugger.steps, 1_000_050);
+        assert_eq!(debugger.comp.history().unwrap().len(), HISTORY_STEP_LIMIT);
+        assert_eq!(debugger.execute("lastwrite 0"), "[0] hasn't been written to in the last 100000 steps");
+        assert!(debugger.execute(&format!("rs {}", HISTORY_STEP_LIMIT + 1)).starts_with("at the oldest step kept, only the last 100000 are"));
+        assert_eq!(debugger.steps, 1_000_050 - HISTORY_STEP_LIMIT as u64);
+        assert_eq!(debugger.execute("c 0"), "error: step count must be positive");
+
+        assert_eq!(debugger.execute("mem 0 5000").lines().count(), 1000);
+        assert_eq!(debugger.execute("mem 1 -3"), "[1] = 1");
+        assert_eq!(debugger.execute(&format!("mem {} 2", i64::MAX)), format!("[{}] = 0\n[{}] = 0", i64::MAX, i64::MAX as u64 + 1));
+    }
+
+    #[test]
+    fn reverse_step_test() {
+        let test = vec![109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99];
+        let mut debugger = Debugger::new(&test, &VecDeque::new());
+        debugger.execute("c");
+        assert_eq!( debugger.execute("lastwrite 100"), "[100] last written by ip 4 at step 78: 15 -> 16" );
+        assert_eq!( debugger.execute("lastwrite 0"), "[0] hasn't been written to" );
+
+        assert!( debugger.execute("rs 3").starts_with("ip 8") );
+        assert_eq!( debugger.steps, 78 );
+        debugger.execute("watch 100");
+        assert!( debugger.execute("rc").starts_with("watchpoint [100]: 15 <- 16 by ip 4") );
+        debugger.execute("break 2");
+        assert!( debugger.execute("rc").starts_with("breakpoint at ip 2") );
+        debugger.execute("unwatch 100");
+        debugger.execute("delete 2");
+        assert!( debugger.execute("rc").starts_with("at the start of the recording") );
+        assert_eq!( (debugger.steps, debugger.comp.index), (0, 0) );
+
+        // the output taken back is made again going forwards
+        assert!( debugger.execute("c").starts_with("program halted") );
+        assert_eq!( debugger.comp.output_values, test );
+    }
+}
@@ -0,0 +1,460 @@
+use std::collections::VecDeque;
+use std::sync::Arc;
+
+use crate::{decode_opcode, IntOpCodeError, ParameterMode, RunEvent, RunState};
+use crate::intopcode_isa::{execute_on, ExecuteFn, InstructionSet, Machine, Next};
+use crate::intopcode_memory::DEFAULT_MEMORY_LIMIT;
+
+#[derive(Debug, Clone, Copy)]
+enum Operand {
+    Position(i64),
+    Immediate(i64),
+    Relative(i64),
+}
+
+// An instruction with its modes already applied to its parameters. The relative base is
+// only added when it runs since it can change between runs of the same instruction.
+#[derive(Debug, Clone, Copy)]
+struct Instruction {
+    opcode: i64,
+    two_digit_opcode: i64,
+    operands: [Operand; 3],
+    parameter_count: usize,
+    // Set for opcodes that aren't standard, which run through their definition instead
+    execute: Option<ExecuteFn>,
+}
+
+// Alternative to IntOpCodeComp for brute force searches. Every instruction is decoded once and
+// cached by address, and a write anywhere inside a cached instruction throws it away so
+// self-modifying programs still behave exactly like they do on IntOpCodeComp.
+#[derive(Debug, Clone)]
+pub struct FastIntOpCodeComp {
+    memory: Vec<i64>,
+    // decoded[i] is the instruction starting at address i, if it has been run since it last changed
+    decoded: Vec<Option<Instruction>>,
+    input_values: VecDeque<i64>,
+    output_values: VecDeque<i64>,
+    index: usize,
+    relative_base: i64,
+    state: RunState,
+    instruction_set: Arc<InstructionSet>,
+}
+
+impl FastIntOpCodeComp {
+    pub fn new(intopcodes: &[i64], input_values: &VecDeque<i64>) -> FastIntOpCodeComp {
+        FastIntOpCodeComp {
+            memory: intopcodes.to_vec(),
+            decoded: vec![None; intopcodes.len()],
+            input_values: input_values.clone(),
+            output_values: VecDeque::new(),
+            index: 0,
+            relative_base: 0,
+            state: if intopcodes.is_empty() { RunState::Halted } else { RunState::Running },
+            instruction_set: InstructionSet::standard_shared(),
+        }
+    }
+
+    pub fn instruction_set(&self) -> &InstructionSet {
+        &self.instruction_set
+    }
+
+    // Runs the program with different opcodes, like IntOpCodeComp::set_instruction_set
+    pub fn set_instruction_set(&mut self, instruction_set: Arc<InstructionSet>) {
+        self.instruction_set = instruction_set;
+        self.decoded.iter_mut().for_each(|cached| *cached = None);
+    }
+
+    pub fn state(&self) -> RunState {
+        self.state
+    }
+
+    pub fn memory(&self) -> &[i64] {
+        &self.memory
+    }
+
+    pub fn ip(&self) -> usize {
+        self.index
+    }
+
+    pub fn relative_base(&self) -> i64 {
+        self.relative_base
+    }
+
+    pub fn output_values(&self) -> &VecDeque<i64> {
+        &self.output_values
+    }
+
+    pub fn push_input(&mut self, value: i64) {
+        self.input_values.push_back(value);
+    }
+
+    // Changes memory from outside, e.g. to patch in the noun and verb of a day 2 attempt
+    pub fn write_memory(&mut self, address: usize, value: i64) -> Result<(), IntOpCodeError> {
+        self.store(address, value)
+    }
+
+    fn read(&self, address: usize) -> i64 {
+        self.memory.get(address).copied().unwrap_or(0)
+    }
+
+    fn store(&mut self, address: usize, value: i64) -> Result<(), IntOpCodeError> {
+        if address >= self.memory.len() {
+            if address >= DEFAULT_MEMORY_LIMIT {
+                return Err(IntOpCodeError::MemoryLimitExceeded { ip: self.index, address, limit: DEFAULT_MEMORY_LIMIT });
+            }
+            self.memory.resize(address + 1, 0);
+            self.decoded.resize(address + 1, None);
+        }
+        self.memory[address] = value;
+
+        // Instructions are at most 4 words long, so only the last 4 decoded addresses can cover this one
+        for cached in &mut self.decoded[address.saturating_sub(3)..=address] {
+            *cached = None;
+        }
+        Ok(())
+    }
+
+    fn decode(&self, ip: usize) -> Result<Instruction, IntOpCodeError> {
+        let opcode = self.read(ip);
+        let (two_digit_opcode, modes) = decode_opcode(opcode);
+        let definition = self.instruction_set.get(two_digit_opcode)
+            .ok_or(IntOpCodeError::UnknownOpcode { ip, opcode })?;
+
+        let mut operands = [Operand::Immediate(0); 3];
+        for (i, (&mode, &kind)) in modes.iter().zip(&definition.parameters).enumerate() {
+            let param = self.read(ip + i + 1);
+            operands[i] = match mode {
+                _ if !self.instruction_set.allows_mode(mode, kind) => return Err(IntOpCodeError::UnknownParameterMode { ip, opcode, parameter: i + 1, mode }),
+                0 => Operand::Position(param),
+                1 => Operand::Immediate(param),
+                _ => Operand::Relative(param),
+            };
+        }
+        let execute = if self.instruction_set.is_standard(two_digit_opcode) { None } else { Some(definition.execute) };
+        Ok(Instruction { opcode, two_digit_opcode, operands, parameter_count: definition.parameters.len(), execute })
+    }
+
+    fn negative_address(&self, instruction: &Instruction, parameter: usize, address: i64) -> IntOpCodeError {
+        IntOpCodeError::NegativeAddress { ip: self.index, opcode: instruction.opcode, parameter, address }
+    }
+
+    fn address(&self, instruction: &Instruction, parameter: usize) -> Result<usize, IntOpCodeError> {
+        let address = match instruction.operands[parameter - 1] {
+            Operand::Relative(offset) => self.relative_base.saturating_add(offset),
+            Operand::Position(address) | Operand::Immediate(address) => address,
+        };
+        if address < 0 {
+            return Err(self.negative_address(instruction, parameter, address));
+        }
+        Ok(address as usize)
+    }
+
+    fn value(&self, instruction: &Instruction, parameter: usize) -> Result<i64, IntOpCodeError> {
+        match instruction.operands[parameter - 1] {
+            Operand::Immediate(value) => Ok(value),
+            _ => Ok(self.read(self.address(instruction, parameter)?))
+        }
+    }
+
+    fn overflow(&self, instruction: &Instruction) -> IntOpCodeError {
+        IntOpCodeError::Overflow { ip: self.index, opcode: instruction.opcode }
+    }
+
+    fn jump(&mut self, instruction: &Instruction, target: i64) -> Result<(), IntOpCodeError> {
+        if target < 0 {
+            return Err(self.negative_address(instruction, 2, target));
+        }
+        self.index = target as usize;
+        Ok(())
+    }
+
+    // Runs an opcode that isn't standard the way IntOpCodeComp would, through its definition
+    fn execute(&mut self, instruction: &Instruction, execute: ExecuteFn) -> Result<(), IntOpCodeError> {
+        let mut modes = [ParameterMode::Position; 3];
+        for (mode, operand) in modes.iter_mut().zip(&instruction.operands) {
+            *mode = match operand {
+                Operand::Position(_) => ParameterMode::Position,
+                Operand::Immediate(_) => ParameterMode::ImmediateMode,
+                Operand::Relative(_) => ParameterMode::RelativeMode(self.relative_base),
+            };
+        }
+        match execute_on(self, execute, modes, instruction.parameter_count)? {
+            Next::Advance => self.index += instruction.parameter_count + 1,
+            Next::Jump(target) => self.index = target,
+            Next::Stay => {},
+        }
+        Ok(())
+    }
+
+    pub fn tick(&mut self) -> Result<(), IntOpCodeError> {
+        if self.state == RunState::Halted {
+            return Ok(());
+        }
+
+        let ip = self.index;
+        let instruction = match self.decoded.get(ip) {
+            Some(Some(instruction)) => *instruction,
+            _ => {
+                let instruction = self.decode(ip)?;
+                if ip < self.decoded.len() {
+                    self.decoded[ip] = Some(instruction);
+                }
+                instruction
+            }
+        };
+
+        if let Some(execute) = instruction.execute {
+            return self.execute(&instruction, execute);
+        }
+
+        match instruction.two_digit_opcode {
+            1 | 2 | 7 | 8 => {
+                let a = self.value(&instruction, 1)?;
+                let b = self.value(&instruction, 2)?;
+                let store_index = self.address(&instruction, 3)?;
+                let result = match instruction.two_digit_opcode {
+                    1 => a.checked_add(b).ok_or_else(|| self.overflow(&instruction))?,
+                    2 => a.checked_mul(b).ok_or_else(|| self.overflow(&instruction))?,
+                    7 => (a < b) as i64,
+                    _ => (a == b) as i64,
+                };
+                self.store(store_index, result)?;
+                self.index += 4;
+            },
+            3 => {
+                let store_index = self.address(&instruction, 1)?;
+                match self.input_values.pop_front() {
+                    Some(val) => {
+                        self.store(store_index, val)?;
+                        self.index += 2;
+                        self.state = RunState::Running;
+                    },
+                    None => self.state = RunState::WaitingOnInput
+                };
+            },
+            4 => {
+                let output = self.value(&instruction, 1)?;
+                self.output_values.push_back(output);
+                self.index += 2;
+            },
+            5 | 6 => {
+                let a = self.value(&instruction, 1)?;
+                if (a != 0) == (instruction.two_digit_opcode == 5) {
+                    let b = self.value(&instruction, 2)?;
+                    self.jump(&instruction, b)?;
+                }
+                else {
+                    self.index += 3;
+                }
+            },
+            9 => {
+                let a = self.value(&instruction, 1)?;
+                self.relative_base = self.relative_base.checked_add(a).ok_or_else(|| self.overflow(&instruction))?;
+                self.index += 2;
+            },
+            _ => {
+                self.index = self.memory.len();
+                self.state = RunState::Halted;
+            },
+        }
+
+        Ok(())
+    }
+
+    // Like IntOpCodeComp::tick_to_completion this also stops when the program needs input
+    pub fn tick_to_completion(&mut self) -> Result<&VecDeque<i64>, IntOpCodeError> {
+        while self.state == RunState::Running {
+            self.tick()?;
+        }
+
+        Ok(&self.output_values)
+    }
+
+    // Executes instructions until the program needs input, produces an output or halts
+    pub fn run_until_event(&mut self) -> Result<RunEvent, IntOpCodeError> {
+        loop {
+            if self.state == RunState::Halted {
+                return Ok(RunEvent::Halted);
+            }
+
+            let output_count = self.output_values.len();
+            self.tick()?;
+
+            if self.state == RunState::WaitingOnInput {
+                return Ok(RunEvent::InputNeeded);
+            }
+            if self.output_values.len() > output_count {
+                return Ok(RunEvent::Output(self.output_values.pop_back().unwrap()));
+            }
+        }
+    }
+}
+
+impl Machine for FastIntOpCodeComp {
+    fn read_param(&self, modes: &[ParameterMode], n: usize) -> Result<i64, IntOpCodeError> {
+        let param = self.read(self.index + n);
+        let address = match modes[n - 1] {
+            ParameterMode::ImmediateMode => return Ok(param),
+            ParameterMode::Position => param,
+            ParameterMode::RelativeMode(relative_base) => relative_base.saturating_add(param),
+        };
+        if address < 0 {
+            return Err(Machine::negative_address(self, n, address));
+        }
+        Ok(self.read(address as usize))
+    }
+
+    fn write_param_index(&self, modes: &[ParameterMode], n: usize) -> Result<usize, IntOpCodeError> {
+        let param = self.read(self.index + n);
+        let address = match modes[n - 1] {
+            ParameterMode::RelativeMode(relative_base) => relative_base.saturating_add(param),
+            _ => param,
+        };
+        if address < 0 {
+            return Err(Machine::negative_address(self, n, address));
+        }
+        Ok(address as usize)
+    }
+
+    fn store(&mut self, address: usize, value: i64) -> Result<(), IntOpCodeError> {
+        FastIntOpCodeComp::store(self, address, value)
+    }
+
+    fn negative_address(&self, parameter: usize, address: i64) -> IntOpCodeError {
+        IntOpCodeError::NegativeAddress { ip: self.index, opcode: self.read(self.index), parameter, address }
+    }
+
+    fn overflow(&self) -> IntOpCodeError {
+        IntOpCodeError::Overflow { ip: self.index, opcode: self.read(self.index) }
+    }
+
+    fn take_input(&mut self) -> Option<i64> {
+        let value = self.input_values.pop_front();
+        self.state = if value.is_some() { RunState::Running } else { RunState::WaitingOnInput };
+        value
+    }
+
+    fn output(&mut self, value: i64) {
+        self.output_values.push_back(value);
+    }
+
+    fn relative_base(&self) -> i64 {
+        self.relative_base
+    }
+
+    fn set_relative_base(&mut self, relative_base: i64) {
+        self.relative_base = relative_base;
+    }
+
+    fn halt(&mut self) -> usize {
+        self.state = RunState::Halted;
+        self.memory.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{parse_intopcodes, IntOpCodeComp};
+    use crate::intopcode_isa::{OpcodeDefinition, ParameterKind};
+
+    // Runs both engines to completion and checks they end up in exactly the same place
+    fn assert_same_as_reference(intopcodes: &[i64], input_values: &[i64]) {
+        assert_same_as_reference_with(intopcodes, input_values, &InstructionSet::standard_shared());
+    }
+
+    fn assert_same_as_reference_with(intopcodes: &[i64], input_values: &[i64], instruction_set: &Arc<InstructionSet>) {
+        let input_values : VecDeque<i64> = input_values.iter().copied().collect();
+        let mut reference = IntOpCodeComp::new(intopcodes, &input_values);
+        reference.set_instruction_set(Arc::clone(instruction_set));
+        let mut fast = FastIntOpCodeComp::new(intopcodes, &input_values);
+        fast.set_instruction_set(Arc::clone(instruction_set));
+        let reference_result = reference.tick_to_completion().cloned();
+        let fast_result = fast.tick_to_completion().cloned();
+        assert_eq!( fast_result, reference_result );
+        if reference_result.is_ok() {
+            assert_eq!( fast.memory(), &reference.memory().to_vec().unwrap()[..] );
+            assert_eq!( (fast.index, fast.relative_base), (reference.index, reference.relative_base) );
+        }
+    }
+
+    #[test]
+    fn matches_reference_test() {
+        assert_same_as_reference(&parse_intopcodes(&std::fs::read_to_string("aocday2.txt").unwrap()).unwrap(), &[]);
+        let diagnostics = parse_intopcodes(&std::fs::read_to_string("aocday5.txt").unwrap()).unwrap();
+        assert_same_as_reference(&diagnostics, &[1]);
+        assert_same_as_reference(&diagnostics, &[5]);
+        assert_same_as_reference(&parse_intopcodes(&std::fs::read_to_string("aocday9.txt").unwrap()).unwrap(), &[1]);
+        assert_same_as_reference(&parse_intopcodes(&std::fs::read_to_string("aocday13.txt").unwrap()).unwrap(), &[]);
+        assert_same_as_reference(&[109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99], &[]);
+
+        // errors come out the same way too
+        assert_same_as_reference(&[1,0,0,0,42], &[]);
+        assert_same_as_reference(&[3001,0,0,0,99], &[]);
+        assert_same_as_reference(&[109,-3,21101,1,1,0,99], &[]);
+        assert_same_as_reference(&[1105,1,-1], &[]);
+        assert_same_as_reference(&[1102,i64::MAX,2,0,99], &[]);
+        assert_same_as_reference(&[1101,1,1,1000000000000,99], &[]);
+    }
+
+    #[test]
+    fn self_modifying_test() {
+        // Loops twice, adding 100 to its first instruction each time so "out [15]" becomes "out #15"
+        let test = vec![4,15,1001,0,100,0,1001,16,-1,16,1005,16,0,99,0,7,2];
+        assert_same_as_reference(&test, &[]);
+
+        let mut fast = FastIntOpCodeComp::new(&test, &VecDeque::new());
+        assert_eq!( fast.tick_to_completion().unwrap(), &VecDeque::from(vec![7, 15]) );
+        assert_eq!( fast.memory()[0], 204 );
+
+        // Patching memory from outside after a run also invalidates the cache
+        let mut fast = FastIntOpCodeComp::new(&[4,3,99,7], &VecDeque::new());
+        fast.tick().unwrap();
+        fast.index = 0;
+        fast.write_memory(0, 104).unwrap();
+        fast.tick().unwrap();
+        assert_eq!( fast.output_values, vec![7, 3] );
+    }
+
+    #[test]
+    fn instruction_set_test() {
+        use ParameterKind::{Read, Write};
+        let mut set = InstructionSet::standard();
+        set.register(OpcodeDefinition { opcode: 10, mnemonic: "div", parameters: vec![Read, Read, Write], day: 0, execute: |instruction| {
+            let quotient = instruction.read(1)?.checked_div(instruction.read(2)?).ok_or_else(|| instruction.overflow())?;
+            instruction.write(3, quotient)
+        } }).unwrap();
+        // stores an input and goes back to the start unless it was 0
+        set.register(OpcodeDefinition { opcode: 11, mnemonic: "inz", parameters: vec![Write], day: 0, execute: |instruction| {
+            let address = instruction.write_address(1)?;
+            if let Some(value) = instruction.input() {
+                instruction.store(address, value)?;
+                if value != 0 {
+                    instruction.jump(1, 0)?;
+                }
+            }
+            Ok(())
+        } }).unwrap();
+        // out prints everything twice
+        set.register(OpcodeDefinition { opcode: 4, mnemonic: "out2", parameters: vec![Read], day: 0, execute: |instruction| {
+            let value = instruction.read(1)?;
+            instruction.output(value);
+            instruction.output(value);
+            Ok(())
+        } }).unwrap();
+        let set = Arc::new(set);
+
+        // divides the second to last input by 3, printing it twice
+        let program = [109,20,211,0,21210,-20,3,1,204,1,99];
+        assert_same_as_reference_with(&program, &[12, 0], &set);
+        assert_same_as_reference_with(&program, &[12, 5, 0], &set);
+        assert_same_as_reference_with(&program, &[12], &set);
+        let mut fast = FastIntOpCodeComp::new(&program, &VecDeque::from(vec![12, 0]));
+        fast.set_instruction_set(Arc::clone(&set));
+        assert_eq!( fast.tick_to_completion().unwrap(), &VecDeque::from(vec![4, 4]) );
+
+        assert_same_as_reference_with(&[1110,1,0,0,99], &[], &set);
+        assert_same_as_reference_with(&[1110,1,1,0,99], &[], &Arc::new(InstructionSet::for_day(9)));
+        assert_same_as_reference_with(&[11101,1,1,0,99], &[], &Arc::new(InstructionSet::for_day(5)));
+    }
+}
@@ -0,0 +1,440 @@
+use std::collections::VecDeque;
+use std::panic;
+
+use crate::{decode_opcode, IntOpCodeComp, IntOpCodeError, RunState};
+use crate::intopcode_fast::FastIntOpCodeComp;
+
+// Anything that can run an intopcode program one instruction at a time
+pub trait Engine {
+    // Executes one instruction, returning the state after it
+    fn step(&mut self) -> Result<RunState, IntOpCodeError>;
+    fn ip(&self) -> usize;
+    fn relative_base(&self) -> i64;
+    fn outputs(&self) -> Vec<i64>;
+    // Every non-zero word as (address, value), in address order
+    fn memory_cells(&self) -> Vec<(usize, i64)>;
+}
+
+pub type EngineFactory = fn(&[i64], &VecDeque<i64>) -> Box<dyn Engine>;
+
+impl Engine for IntOpCodeComp {
+    fn step(&mut self) -> Result<RunState, IntOpCodeError> {
+        self.tick()?;
+        Ok(self.state)
+    }
+
+    fn ip(&self) -> usize {
+        self.index
+    }
+
+    fn relative_base(&self) -> i64 {
+        self.relative_base
+    }
+
+    fn outputs(&self) -> Vec<i64> {
+        self.output_values.iter().copied().collect()
+    }
+
+    fn memory_cells(&self) -> Vec<(usize, i64)> {
+        self.memory.cells()
+    }
+}
+
+impl Engine for FastIntOpCodeComp {
+    fn step(&mut self) -> Result<RunState, IntOpCodeError> {
+        self.tick()?;
+        Ok(self.state())
+    }
+
+    fn ip(&self) -> usize {
+        FastIntOpCodeComp::ip(self)
+    }
+
+    fn relative_base(&self) -> i64 {
+        FastIntOpCodeComp::relative_base(self)
+    }
+
+    fn outputs(&self) -> Vec<i64> {
+        self.output_values().iter().copied().collect()
+    }
+
+    fn memory_cells(&self) -> Vec<(usize, i64)> {
+        self.memory().iter().copied().enumerate().filter(|&(_, value)| value != 0).collect()
+    }
+}
+
+// Every engine in the crate, the reference implementation first
+pub fn engines() -> Vec<(&'static str, EngineFactory)> {
+    vec![
+        ("reference", |intopcodes, input_values| Box::new(IntOpCodeComp::new(intopcodes, input_values))),
+        ("fast", |intopcodes, input_values| Box::new(FastIntOpCodeComp::new(intopcodes, input_values))),
+    ]
+}
+
+// Where a run ended up. A result of Ok(RunState::Running) means the step limit was hit.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Outcome {
+    pub result: Result<RunState, IntOpCodeError>,
+    pub steps: usize,
+    pub ip: usize,
+    pub relative_base: i64,
+    pub outputs: Vec<i64>,
+    pub memory: Vec<(usize, i64)>,
+}
+
+pub fn run_engine(engine: &mut dyn Engine, step_limit: usize) -> Outcome {
+    let mut result = Ok(RunState::Running);
+    let mut steps = 0;
+    while steps < step_limit {
+        steps += 1;
+        result = engine.step();
+        if result != Ok(RunState::Running) {
+            break;
+        }
+    }
+    Outcome { result, steps, ip: engine.ip(), relative_base: engine.relative_base(), outputs: engine.outputs(), memory: engine.memory_cells() }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FailureKind {
+    Panic,
+    // The relative base changed by something other than what opcode 9 asked for
+    RelativeBase,
+    // Two runs of the same program ended differently
+    Nondeterministic,
+    // The named engine disagreed with the first one
+    Mismatch(&'static str),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Failure {
+    pub kind: FailureKind,
+    pub program: Vec<i64>,
+    pub inputs: Vec<i64>,
+    pub message: String,
+}
+
+fn catch_panic<T, F: FnOnce() -> T>(f: F) -> Result<T, String> {
+    panic::catch_unwind(panic::AssertUnwindSafe(f)).map_err(|payload| {
+        payload.downcast_ref::<&str>().map(|s| s.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| String::from("panic"))
+    })
+}
+
+// Steps the reference engine, checking every relative base change against the opcode 9 that made it
+fn check_relative_base(program: &[i64], inputs: &VecDeque<i64>, step_limit: usize) -> Result<(), String> {
+    let mut comp = IntOpCodeComp::new(program, inputs);
+    for _ in 0..step_limit {
+        let before = comp.relative_base;
+        let opcode = comp.read_memory(comp.index);
+        let (two_digit_opcode, modes) = decode_opcode(opcode);
+        let operand = comp.read_memory(comp.index + 1);
+        let expected = if two_digit_opcode != 9 {
+            Some(before)
+        }
+        else {
+            let value = match modes[0] {
+                0 if operand >= 0 => Some(comp.read_memory(operand as usize)),
+                1 => Some(operand),
+                2 => before.checked_add(operand).filter(|&a| a >= 0).map(|a| comp.read_memory(a as usize)),
+                _ => None,
+            };
+            value.and_then(|value| before.checked_add(value))
+        };
+
+        let ip = comp.index;
+        match comp.traced_tick() {
+            Ok(Some(record)) => {
+                if expected.is_some_and(|expected| expected != record.relative_base) {
+                    return Err(format!("ip {} opcode {} moved the relative base from {} to {}, expected {}", ip, opcode, before, record.relative_base, expected.unwrap()));
+                }
+            },
+            Ok(None) | Err(_) => return Ok(()),
+        }
+    }
+    Ok(())
+}
+
+// Runs every invariant against one program, returning the first one broken
+pub fn check_program(program: &[i64], inputs: &[i64], engines: &[(&'static str, EngineFactory)], step_limit: usize) -> Option<Failure> {
+    let input_values : VecDeque<i64> = inputs.iter().copied().collect();
+    let failure = |kind, message| Some(Failure { kind, program: program.to_vec(), inputs: inputs.to_vec(), message });
+
+    let mut reference = None;
+    for &(name, factory) in engines {
+        let run = || run_engine(factory(program, &input_values).as_mut(), step_limit);
+        let outcome = match catch_panic(run) {
+            Ok(outcome) => outcome,
+            Err(message) => return failure(FailureKind::Panic, format!("{} engine panicked: {}", name, message)),
+        };
+        match catch_panic(run) {
+            Ok(again) if again == outcome => {},
+            Ok(again) => return failure(FailureKind::Nondeterministic, format!("{} engine ended with {:?} then {:?}", name, outcome.result, again.result)),
+            Err(message) => return failure(FailureKind::Panic, format!("{} engine panicked: {}", name, message)),
+        }
+        match &reference {
+            None => reference = Some(outcome),
+            Some(expected) if *expected != outcome => {
+                return failure(FailureKind::Mismatch(name), format!("expected {:?}\nbut {} engine gave {:?}", expected, name, outcome));
+            },
+            Some(_) => {},
+        }
+    }
+
+    match catch_panic(|| check_relative_base(program, &input_values, step_limit)) {
+        Ok(Ok(())) => None,
+        Ok(Err(message)) => failure(FailureKind::RelativeBase, message),
+        Err(message) => failure(FailureKind::Panic, format!("relative base check panicked: {}", message)),
+    }
+}
+
+// Smaller values first, then anything closer to zero
+fn simpler_values(value: i64) -> Vec<i64> {
+    let mut candidates = vec![0, 1, value / 2, value / 10, value.signum() * 99];
+    candidates.retain(|&c| c.unsigned_abs() < value.unsigned_abs());
+    candidates.dedup();
+    candidates
+}
+
+// Cuts a failing program and its inputs down while still_fails holds, by removing runs of
+// words and inputs and making what's left closer to zero
+pub fn shrink<F: Fn(&[i64], &[i64]) -> bool>(program: &[i64], inputs: &[i64], still_fails: F) -> (Vec<i64>, Vec<i64>) {
+    let mut program = program.to_vec();
+    let mut inputs = inputs.to_vec();
+    loop {
+        let mut changed = false;
+
+        let mut chunk = std::cmp::max(program.len() / 2, 1);
+        while chunk > 0 {
+            let mut start = 0;
+            while start + chunk <= program.len() {
+                let mut candidate = program.clone();
+                candidate.drain(start..start + chunk);
+                if still_fails(&candidate, &inputs) {
+                    program = candidate;
+                    changed = true;
+                }
+                else {
+                    start += chunk;
+                }
+            }
+            chunk /= 2;
+        }
+
+        let mut i = 0;
+        while i < inputs.len() {
+            let mut candidate = inputs.clone();
+            candidate.remove(i);
+            if still_fails(&program, &candidate) {
+                inputs = candidate;
+                changed = true;
+            }
+            else {
+                i += 1;
+            }
+        }
+
+        for i in 0..program.len() {
+            for value in simpler_values(program[i]) {
+                let mut candidate = program.clone();
+                candidate[i] = value;
+                if still_fails(&candidate, &inputs) {
+                    program = candidate;
+                    changed = true;
+                    break;
+                }
+            }
+        }
+        for i in 0..inputs.len() {
+            for value in simpler_values(inputs[i]) {
+                let mut candidate = inputs.clone();
+                candidate[i] = value;
+                if still_fails(&program, &candidate) {
+                    inputs = candidate;
+                    changed = true;
+                    break;
+                }
+            }
+        }
+
+        if !changed {
+            return (program, inputs);
+        }
+    }
+}
+
+// xorshift64*, plenty for picking instructions and repeatable from a seed
+#[derive(Debug, Clone)]
+struct Rng(u64);
+
+impl Rng {
+    fn new(seed: u64) -> Rng {
+        Rng(seed.wrapping_mul(0x9E3779B97F4A7C15) | 1)
+    }
+
+    fn next(&mut self) -> u64 {
+        self.0 ^= self.0 >> 12;
+        self.0 ^= self.0 << 25;
+        self.0 ^= self.0 >> 27;
+        self.0.wrapping_mul(0x2545F4914F6CDD1D)
+    }
+
+    // Uniform in min..=max
+    fn range(&mut self, min: i64, max: i64) -> i64 {
+        min + (self.next() % (max - min + 1) as u64) as i64
+    }
+
+    fn chance(&mut self, one_in: u64) -> bool {
+        self.next() % one_in == 0
+    }
+}
+
+const DATA_SIZE: usize = 16;
+
+// A random program of valid instructions with valid modes, followed by a data area that relative
+// mode points at. Jumps mostly land on instructions and writes mostly land in the data, but now
+// and then an operand is anything at all so error paths and self-modification get exercised too.
+fn generate_program(rng: &mut Rng, instructions: usize) -> Vec<i64> {
+    let mut opcodes = vec![9];
+    opcodes.extend((0..instructions).map(|_| rng.range(1, 9)));
+    opcodes.push(99);
+    let lengths : Vec<usize> = opcodes.iter().map(|&op| match op { 1 | 2 | 7 | 8 => 4, 5 | 6 => 3, 99 => 1, _ => 2 }).collect();
+    let starts : Vec<usize> = lengths.iter().scan(0, |address, &length| { *address += length; Some(*address - length) }).collect();
+    let code_length = lengths.iter().sum::<usize>();
+    let size = (code_length + DATA_SIZE) as i64;
+
+    let mut program = Vec::with_capacity(code_length + DATA_SIZE);
+    for (n, &op) in opcodes.iter().enumerate() {
+        if n == 0 {
+            // point the relative base at the data area
+            program.extend(&[109, code_length as i64]);
+            continue;
+        }
+        let writes : &[bool] = match op {
+            1 | 2 | 7 | 8 => &[false, false, true],
+            3 => &[true],
+            4 | 9 => &[false],
+            5 | 6 => &[false, false],
+            _ => &[],
+        };
+        let modes : Vec<i64> = writes.iter().map(|&write| if write { [0, 2][rng.range(0, 1) as usize] } else { rng.range(0, 2) }).collect();
+        program.push(op + modes.iter().enumerate().map(|(i, &m)| m * 10i64.pow(i as u32 + 2)).sum::<i64>());
+
+        for (i, &mode) in modes.iter().enumerate() {
+            let operand = if rng.chance(50) {
+                rng.range(-1000, 1000) * [1, 1_000_000, 1 << 40][rng.range(0, 2) as usize]
+            }
+            else {
+                match (mode, op, i) {
+                    (1, 5, 1) | (1, 6, 1) => starts[rng.range(0, starts.len() as i64 - 1) as usize] as i64,
+                    (1, 9, _) => rng.range(-4, 4),
+                    (1, _, _) => rng.range(-20, 20),
+                    (2, _, _) => rng.range(-2, DATA_SIZE as i64 - 1),
+                    _ if writes[i] && !rng.chance(8) => rng.range(code_length as i64, size - 1),
+                    _ => rng.range(0, size - 1),
+                }
+            };
+            program.push(operand);
+        }
+    }
+    program.extend((0..DATA_SIZE).map(|_| rng.range(-100, 100)));
+    program
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct FuzzConfig {
+    pub seed: u64,
+    pub iterations: usize,
+    // Instructions per generated program, not counting the setup and the final halt
+    pub instructions: usize,
+    pub step_limit: usize,
+}
+
+impl Default for FuzzConfig {
+    fn default() -> FuzzConfig {
+        FuzzConfig { seed: 1, iterations: 1000, instructions: 20, step_limit: 10_000 }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FuzzReport {
+    pub programs: usize,
+    pub halted: usize,
+    pub waiting: usize,
+    pub errors: usize,
+    pub step_limited: usize,
+    // The first failure found, already shrunk
+    pub failure: Option<Failure>,
+}
+
+// Generates and checks programs until the iterations run out or one fails
+pub fn fuzz(config: &FuzzConfig, engines: &[(&'static str, EngineFactory)]) -> FuzzReport {
+    let mut rng = Rng::new(config.seed);
+    let mut report = FuzzReport::default();
+    for _ in 0..config.iterations {
+        let program = generate_program(&mut rng, config.instructions);
+        let inputs : Vec<i64> = (0..rng.range(0, 4)).map(|_| rng.range(-50, 50)).collect();
+        report.programs += 1;
+
+        if let Some(failure) = check_program(&program, &inputs, engines, config.step_limit) {
+            let same_kind = |p: &[i64], i: &[i64]| check_program(p, i, engines, config.step_limit).is_some_and(|f| f.kind == failure.kind);
+            let (program, inputs) = shrink(&failure.program, &failure.inputs, same_kind);
+            report.failure = check_program(&program, &inputs, engines, config.step_limit);
+            return report;
+        }
+
+        let mut comp = IntOpCodeComp::new(&program, &inputs.iter().copied().collect());
+        match run_engine(&mut comp, config.step_limit).result {
+            Ok(RunState::Halted) => report.halted += 1,
+            Ok(RunState::WaitingOnInput) => report.waiting += 1,
+            Ok(RunState::Running) => report.step_limited += 1,
+            Err(_) => report.errors += 1,
+        }
+    }
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fuzz_engines_test() {
+        let config = FuzzConfig { iterations: 300, ..FuzzConfig::default() };
+        let report = fuzz(&config, &engines());
+        assert_eq!( report.failure, None );
+        assert_eq!( report.halted + report.waiting + report.errors + report.step_limited, 300 );
+        // the generator has to reach every ending for the run to mean much
+        assert!( report.halted > 0 && report.waiting > 0 && report.errors > 0 && report.step_limited > 0 );
+
+        // the same seed makes the same programs
+        assert_eq!( fuzz(&config, &engines()), report );
+    }
+
+    // Reference engine that gets every output one too high
+    struct OffByOne(IntOpCodeComp);
+
+    impl Engine for OffByOne {
+        fn step(&mut self) -> Result<RunState, IntOpCodeError> { self.0.step() }
+        fn ip(&self) -> usize { self.0.ip() }
+        fn relative_base(&self) -> i64 { Engine::relative_base(&self.0) }
+        fn outputs(&self) -> Vec<i64> { self.0.outputs().iter().map(|v| v + 1).collect() }
+        fn memory_cells(&self) -> Vec<(usize, i64)> { self.0.memory_cells() }
+    }
+
+    #[test]
+    fn differential_shrink_test() {
+        let mut broken = engines();
+        broken.push(("off by one", |intopcodes, input_values| Box::new(OffByOne(IntOpCodeComp::new(intopcodes, input_values)))));
+        let report = fuzz(&FuzzConfig::default(), &broken);
+
+        let failure = report.failure.unwrap();
+        assert_eq!( failure.kind, FailureKind::Mismatch("off by one") );
+        // an output and whatever it takes to reach it
+        assert!( failure.program.len() <= 2, "{:?}", failure.program );
+        assert!( failure.inputs.is_empty() );
+
+        assert_eq!( shrink(&[1, 5, 42, 7, 42], &[3, 4], |p, _| p.contains(&42)), (vec![42], vec![]) );
+    }
+}
@@ -0,0 +1,253 @@
+use std::collections::VecDeque;
+
+use crate::{IntOpCodeComp, IntOpCodeError, ParameterMode, RunState};
+use crate::intopcode_isa::ExecuteFn;
+use crate::intopcode_trace::MemoryWrite;
+
+// What an executed instruction changed, enough to put the machine back the way it was
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct Step {
+    ip: usize,
+    relative_base: i64,
+    state: RunState,
+    // Input value the instruction took off the queue
+    input: Option<i64>,
+    output_count: usize,
+    // Number of its first write counting every write ever recorded, the rest follow up to the next step's
+    first_write: usize,
+}
+
+// Undo log kept by a recording IntOpCodeComp, one step per executed instruction. With a limit
+// only the newest steps are kept, older ones are forgotten along with their writes.
+#[derive(Debug, Clone, Default)]
+pub struct History {
+    steps: VecDeque<Step>,
+    writes: VecDeque<MemoryWrite>,
+    limit: Option<usize>,
+    // How many steps and writes have been forgotten, which is where the kept ones are numbered from
+    forgotten_steps: usize,
+    forgotten_writes: usize,
+}
+
+// A write found in the history. Step 0 is the first instruction run after recording started,
+// even once it has been forgotten.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WriteEvent {
+    pub step: usize,
+    pub ip: usize,
+    pub write: MemoryWrite,
+}
+
+impl History {
+    pub fn len(&self) -> usize {
+        self.steps.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.steps.is_empty()
+    }
+
+    // Number of the oldest step kept, more than 0 once the limit has been reached
+    pub fn first_step(&self) -> usize {
+        self.forgotten_steps
+    }
+
+    // Index into steps of the step that made the write at the given index into writes
+    fn step_of_write(&self, write: usize) -> usize {
+        self.steps.partition_point(|step| step.first_write - self.forgotten_writes <= write) - 1
+    }
+
+    // Most recent write to address made before the given step, as far back as the history goes
+    pub fn last_write_before(&self, address: usize, step: usize) -> Option<WriteEvent> {
+        let end = match step.checked_sub(self.forgotten_steps) {
+            Some(step) => self.steps.get(step).map_or(self.writes.len(), |step| step.first_write - self.forgotten_writes),
+            None => 0,
+        };
+        let index = self.writes.range(..end).rposition(|write| write.address == address)?;
+        let step = self.step_of_write(index);
+        Some(WriteEvent { step: self.forgotten_steps + step, ip: self.steps[step].ip, write: self.writes[index] })
+    }
+
+    pub fn last_write(&self, address: usize) -> Option<WriteEvent> {
+        self.last_write_before(address, self.forgotten_steps + self.steps.len())
+    }
+
+    // Empty history with the same limit
+    pub(crate) fn restarted(&self) -> History {
+        History { limit: self.limit, ..History::default() }
+    }
+
+    fn push(&mut self, step: Step) {
+        self.steps.push_back(step);
+        if self.limit.is_some_and(|limit| self.steps.len() > limit) {
+            self.steps.pop_front();
+            self.forgotten_steps += 1;
+            let first_kept = self.steps.front().map_or(self.forgotten_writes + self.writes.len(), |step| step.first_write);
+            self.writes.drain(..first_kept - self.forgotten_writes);
+            self.forgotten_writes = first_kept;
+        }
+    }
+}
+
+impl IntOpCodeComp {
+    // Starts keeping an undo log of every instruction executed from here on, so they can be
+    // rewound. Any earlier log is thrown away.
+    pub fn start_recording(&mut self) {
+        self.history = Some(History::default());
+    }
+
+    // start_recording keeping only the newest max_steps instructions, so a long run can't use
+    // up all the memory. Rewinding stops once it reaches the oldest one kept.
+    pub fn start_recording_with_limit(&mut self, max_steps: usize) {
+        self.history = Some(History { limit: Some(max_steps), ..History::default() });
+    }
+
+    pub fn stop_recording(&mut self) -> Option<History> {
+        self.history.take()
+    }
+
+    pub fn history(&self) -> Option<&History> {
+        self.history.as_ref()
+    }
+
+    pub fn last_write(&self, address: usize) -> Option<WriteEvent> {
+        self.history.as_ref()?.last_write(address)
+    }
+
+    // Undoes up to count recorded instructions, newest first, returning how many were undone.
+    // Memory, ip, relative base, state and the input queue go back to how they were. Outputs
+    // the instructions made are taken back off output_values if they are still there. Memory
+    // that grew stays grown, the new cells read as 0 like they did before.
+    pub fn rewind(&mut self, count: usize) -> usize {
+        let mut history = match self.history.take() {
+            Some(history) => history,
+            None => return 0
+        };
+        let mut rewound = 0;
+        while rewound < count {
+            let step = match history.steps.pop_back() {
+                Some(step) => step,
+                None => break
+            };
+            for write in history.writes.drain(step.first_write - history.forgotten_writes..).rev() {
+                // the value was there before, so putting it back can't go over the memory limit
+                let _ = self.memory.write(write.address, write.old_value);
+            }
+            if let Some(input) = step.input {
+                self.input_values.push_front(input);
+            }
+            self.output_values.truncate(step.output_count);
+            self.index = step.ip;
+            self.relative_base = step.relative_base;
+            self.state = step.state;
+            rewound += 1;
+        }
+        self.history = Some(history);
+        rewound
+    }
+
+    pub(crate) fn record_write(&mut self, address: usize, old_value: i64, new_value: i64) {
+        if let Some(history) = self.history.as_mut() {
+            history.writes.push_back(MemoryWrite { address, old_value, new_value });
+        }
+    }
+
+    // Like execute, adding what the instruction did to the history
+    pub(crate) fn execute_recorded(&mut self, execute: ExecuteFn, modes: [ParameterMode; 3], parameter_count: usize) -> Result<(), IntOpCodeError> {
+        let first_write = self.history.as_ref().map_or(0, |history| history.forgotten_writes + history.writes.len());
+        let next_input = self.input_values.front().copied();
+        let input_count = self.input_values.len();
+        let mut step = Step { ip: self.index, relative_base: self.relative_base, state: self.state, input: None, output_count: self.output_values.len(), first_write };
+
+        let result = self.execute(execute, modes, parameter_count);
+        if self.input_values.len() < input_count {
+            step.input = next_input;
+        }
+        if let Some(history) = self.history.as_mut() {
+            // waiting on input changes nothing worth undoing, unless something was written first
+            let executed = result.is_ok() && self.state != RunState::WaitingOnInput;
+            if executed || history.forgotten_writes + history.writes.len() > first_write {
+                history.push(step);
+            }
+        }
+        result
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+    use crate::parse_intopcodes;
+
+    #[test]
+    fn rewind_test() {
+        let test = vec![3,9,8,9,10,9,4,9,99,-1,8];  // outputs whether the input equals 8
+        let mut comp = IntOpCodeComp::new(&test, &VecDeque::from(vec![8]));
+        comp.start_recording();
+        comp.tick_to_completion().unwrap();
+        assert_eq!( (comp.output_values.clone(), comp.state()), (VecDeque::from(vec![1]), RunState::Halted) );
+        assert_eq!( comp.history().unwrap().len(), 4 );
+
+        assert_eq!( comp.rewind(2), 2 );
+        assert_eq!( (comp.index, comp.output_values.len(), comp.state()), (6, 0, RunState::Running) );
+        assert_eq!( comp.rewind(10), 2 );
+        assert_eq!( comp.memory().to_vec(), Ok(test) );
+        assert_eq!( comp.input_values, vec![8] );
+
+        // running forward again from the start gives the same result
+        comp.tick_to_completion().unwrap();
+        assert_eq!( comp.output_values, vec![1] );
+
+        // a whole day 9 run goes back to the initial state
+        let program = parse_intopcodes(&std::fs::read_to_string("aocday9.txt").unwrap()).unwrap();
+        let mut comp = IntOpCodeComp::new(&program, &VecDeque::from(vec![1]));
+        comp.start_recording();
+        comp.tick_to_completion().unwrap();
+        let steps = comp.history().unwrap().len();
+        assert_eq!( comp.rewind(usize::MAX), steps );
+        assert_eq!( (comp.index, comp.relative_base, comp.input_values.clone()), (0, 0, VecDeque::from(vec![1])) );
+        assert_eq!( &comp.memory().to_vec().unwrap()[..program.len()], &program[..] );
+        assert!( comp.memory().to_vec().unwrap()[program.len()..].iter().all(|&v| v == 0) );
+    }
+
+    #[test]
+    fn last_write_test() {
+        // counts [100] up to 3
+        let test = vec![1001,100,1,100,1008,100,3,101,1006,101,0,99];
+        let mut comp = IntOpCodeComp::new(&test, &VecDeque::new());
+        assert_eq!( comp.last_write(100), None );
+        comp.start_recording();
+        comp.tick_to_completion().unwrap();
+
+        let last = comp.last_write(100).unwrap();
+        assert_eq!( (last.step, last.ip, last.write), (6, 0, MemoryWrite { address: 100, old_value: 2, new_value: 3 }) );
+        let history = comp.history().unwrap();
+        assert_eq!( history.last_write_before(100, 6).map(|event| (event.step, event.write.new_value)), Some((3, 2)) );
+        assert_eq!( history.last_write_before(100, 0), None );
+        assert_eq!( history.last_write(5), None );
+
+        comp.rewind(4);
+        assert_eq!( comp.last_write(100).map(|event| event.step), Some(3) );
+        assert_eq!( comp.memory().read(100), 2 );
+    }
+
+    #[test]
+    fn limit_test() {
+        // counts [100] up to 3, ten steps in all
+        let test = vec![1001,100,1,100,1008,100,3,101,1006,101,0,99];
+        let mut comp = IntOpCodeComp::new(&test, &VecDeque::new());
+        comp.start_recording_with_limit(4);
+        comp.tick_to_completion().unwrap();
+        let history = comp.history().unwrap();
+        assert_eq!( (history.len(), history.first_step()), (4, 6) );
+        assert_eq!( history.last_write(100).map(|event| (event.step, event.ip)), Some((6, 0)) );
+        assert_eq!( history.last_write_before(100, 6), None );
+        assert_eq!( history.last_write(101).map(|event| event.step), Some(7) );
+
+        assert_eq!( comp.rewind(10), 4 );
+        assert_eq!( (comp.index, comp.memory().read(100), comp.memory().read(101)), (0, 2, 0) );
+        comp.tick_to_completion().unwrap();
+        assert_eq!( comp.memory().to_vec().unwrap()[100..], [3, 1] );
+    }
+}
@@ -0,0 +1,311 @@
+use std::cell::RefCell;
+use std::collections::VecDeque;
+use std::io::BufRead;
+use std::rc::Rc;
+use std::sync::mpsc::{Receiver, Sender};
+
+use crate::{IntOpCodeComp, IntOpCodeError, RunEvent, RunState};
+
+// Supplies the value for opcode 3. None means nothing is available yet and the program waits.
+pub trait InputDevice {
+    fn read(&mut self) -> Option<i64>;
+}
+
+// Receives every value from opcode 4
+pub trait OutputDevice {
+    fn write(&mut self, value: i64);
+}
+
+// Lets a separate input and output be driven through run_with_device
+struct DevicePair<'a> {
+    input: &'a mut dyn InputDevice,
+    output: &'a mut dyn OutputDevice,
+}
+
+impl<'a> InputDevice for DevicePair<'a> {
+    fn read(&mut self) -> Option<i64> {
+        self.input.read()
+    }
+}
+
+impl<'a> OutputDevice for DevicePair<'a> {
+    fn write(&mut self, value: i64) {
+        self.output.write(value)
+    }
+}
+
+impl IntOpCodeComp {
+    // Runs until the program halts or the input device has nothing left to give.
+    // Anything already in the input queue is consumed before the device is asked.
+    pub fn run_with_devices(&mut self, input: &mut dyn InputDevice, output: &mut dyn OutputDevice) -> Result<RunState, IntOpCodeError> {
+        self.run_with_device(&mut DevicePair { input, output })
+    }
+
+    // Same as run_with_devices for a single device that handles both directions, e.g. a robot whose
+    // next input depends on the outputs it was sent
+    pub fn run_with_device<D: InputDevice + OutputDevice>(&mut self, device: &mut D) -> Result<RunState, IntOpCodeError> {
+        loop {
+            match self.run_until_event()? {
+                RunEvent::InputNeeded => match device.read() {
+                    Some(value) => self.push_input(value),
+                    None => return Ok(self.state),
+                },
+                RunEvent::Output(value) => device.write(value),
+                RunEvent::Halted => return Ok(self.state),
+            }
+        }
+    }
+}
+
+// A plain queue works as both ends
+impl InputDevice for VecDeque<i64> {
+    fn read(&mut self) -> Option<i64> {
+        self.pop_front()
+    }
+}
+
+impl OutputDevice for VecDeque<i64> {
+    fn write(&mut self, value: i64) {
+        self.push_back(value)
+    }
+}
+
+// Calls a closure for every input
+pub struct FnInput<F: FnMut() -> Option<i64>>(pub F);
+
+impl<F: FnMut() -> Option<i64>> InputDevice for FnInput<F> {
+    fn read(&mut self) -> Option<i64> {
+        (self.0)()
+    }
+}
+
+// Calls a closure for every output
+pub struct FnOutput<F: FnMut(i64)>(pub F);
+
+impl<F: FnMut(i64)> OutputDevice for FnOutput<F> {
+    fn write(&mut self, value: i64) {
+        (self.0)(value)
+    }
+}
+
+// Collects outputs into groups of a fixed size, e.g. the (x, y, tile) triples of a screen,
+// and calls a closure with each complete group
+pub struct ChunkedOutput<F: FnMut(&[i64])> {
+    size: usize,
+    pending: Vec<i64>,
+    handler: F,
+}
+
+impl<F: FnMut(&[i64])> ChunkedOutput<F> {
+    pub fn new(size: usize, handler: F) -> ChunkedOutput<F> {
+        ChunkedOutput { size, pending: Vec::with_capacity(size), handler }
+    }
+}
+
+impl<F: FnMut(&[i64])> OutputDevice for ChunkedOutput<F> {
+    fn write(&mut self, value: i64) {
+        self.pending.push(value);
+        if self.pending.len() == self.size {
+            (self.handler)(&self.pending);
+            self.pending.clear();
+        }
+    }
+}
+
+// Reads one integer per line from stdin, re-prompting on anything that doesn't parse. Ends at EOF.
+#[derive(Default)]
+pub struct StdinInput {}
+
+impl InputDevice for StdinInput {
+    fn read(&mut self) -> Option<i64> {
+        let stdin = std::io::stdin();
+        loop {
+            println!("Input:");
+            let mut line = String::new();
+            match stdin.lock().read_line(&mut line) {
+                Ok(0) => return None,
+                Ok(_) => match line.trim().parse() {
+                    Ok(value) => return Some(value),
+                    Err(error) => println!("error: {} try again", error),
+                },
+                Err(error) => {
+                    println!("error: {}", error);
+                    return None
+                }
+            }
+        }
+    }
+}
+
+// Prints each output on its own line
+#[derive(Default)]
+pub struct StdoutOutput {}
+
+impl OutputDevice for StdoutOutput {
+    fn write(&mut self, value: i64) {
+        println!("{}", value);
+    }
+}
+
+// Feeds text to the program one character code at a time
+#[derive(Debug, Default)]
+pub struct AsciiInput {
+    pending: VecDeque<i64>,
+}
+
+impl AsciiInput {
+    pub fn new(text: &str) -> AsciiInput {
+        let mut input = AsciiInput::default();
+        input.push_str(text);
+        input
+    }
+
+    pub fn push_str(&mut self, text: &str) {
+        self.pending.extend(text.bytes().map(i64::from));
+    }
+
+    // Queues a line as a command, adding the newline the program waits for
+    pub fn push_line(&mut self, line: &str) {
+        self.push_str(line);
+        self.pending.push_back(i64::from(b'\n'));
+    }
+}
+
+impl InputDevice for AsciiInput {
+    fn read(&mut self) -> Option<i64> {
+        self.pending.pop_front()
+    }
+}
+
+// Collects outputs as text. Values outside the ASCII range can't be characters, they are
+// usually the puzzle answer, and are kept separately.
+#[derive(Debug, Default)]
+pub struct AsciiOutput {
+    pub text: String,
+    pub values: Vec<i64>,
+}
+
+impl OutputDevice for AsciiOutput {
+    fn write(&mut self, value: i64) {
+        if (0..=127).contains(&value) {
+            self.text.push(value as u8 as char);
+        }
+        else {
+            self.values.push(value);
+        }
+    }
+}
+
+// Shared queue connecting one machine's output to another's input on the same thread.
+// Clones refer to the same queue.
+#[derive(Debug, Clone, Default)]
+pub struct Channel {
+    queue: Rc<RefCell<VecDeque<i64>>>,
+}
+
+impl Channel {
+    pub fn new() -> Channel {
+        Channel::default()
+    }
+
+    pub fn send(&self, value: i64) {
+        self.queue.borrow_mut().push_back(value);
+    }
+
+    pub fn len(&self) -> usize {
+        self.queue.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.queue.borrow().is_empty()
+    }
+
+    // Most recent value sent that hasn't been read yet
+    pub fn last(&self) -> Option<i64> {
+        self.queue.borrow().back().copied()
+    }
+}
+
+impl InputDevice for Channel {
+    fn read(&mut self) -> Option<i64> {
+        self.queue.borrow_mut().pop_front()
+    }
+}
+
+impl OutputDevice for Channel {
+    fn write(&mut self, value: i64) {
+        self.send(value)
+    }
+}
+
+// Machines on different threads can be connected with std::sync::mpsc. Reading blocks until
+// a value arrives and ends once every sender is gone.
+impl InputDevice for Receiver<i64> {
+    fn read(&mut self) -> Option<i64> {
+        self.recv().ok()
+    }
+}
+
+// A disconnected receiver just means nobody is listening any more, so the value is dropped
+impl OutputDevice for Sender<i64> {
+    fn write(&mut self, value: i64) {
+        let _ = self.send(value);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn closure_and_chunked_devices_test() {
+        let test = vec![3,14,1001,14,1,14,4,14,4,14,1105,1,0,99,0];  // outputs every input + 1 twice
+        let mut comp = IntOpCodeComp::new(&test, &VecDeque::new());
+        let mut inputs = vec![1, 5].into_iter();
+        let mut pairs = Vec::new();
+        let state = comp.run_with_devices(&mut FnInput(|| inputs.next()), &mut ChunkedOutput::new(2, |pair| pairs.push(pair.to_vec()))).unwrap();
+        assert_eq!( state, RunState::WaitingOnInput );
+        assert_eq!( pairs, vec![vec![2, 2], vec![6, 6]] );
+    }
+
+    #[test]
+    fn ascii_devices_test() {
+        // Echoes characters until a newline, then outputs 1000
+        let test = vec![3,100,4,100,1008,100,10,101,1006,101,0,104,1000,99];
+        let mut comp = IntOpCodeComp::new(&test, &VecDeque::new());
+        let mut input = AsciiInput::default();
+        input.push_line("hi");
+        let mut output = AsciiOutput::default();
+        assert_eq!( comp.run_with_devices(&mut input, &mut output), Ok(RunState::Halted) );
+        assert_eq!( output.text, "hi\n" );
+        assert_eq!( output.values, vec![1000] );
+    }
+
+    #[test]
+    fn channel_devices_test() {
+        let double = vec![3,9,1002,9,2,9,4,9,99,0];
+        let mut first = IntOpCodeComp::new(&double, &VecDeque::from(vec![3]));
+        let mut second = IntOpCodeComp::new(&double, &VecDeque::new());
+        let between = Channel::new();
+        let mut result = VecDeque::new();
+
+        // The second machine waits until the first has sent it something
+        assert_eq!( second.run_with_devices(&mut between.clone(), &mut result), Ok(RunState::WaitingOnInput) );
+        assert_eq!( first.run_with_devices(&mut VecDeque::new(), &mut between.clone()), Ok(RunState::Halted) );
+        assert_eq!( between.last(), Some(6) );
+        assert_eq!( second.run_with_devices(&mut between.clone(), &mut result), Ok(RunState::Halted) );
+        assert!( between.is_empty() );
+        assert_eq!( result, vec![12] );
+
+        let (sender, mut receiver) = std::sync::mpsc::channel();
+        let mut producer = IntOpCodeComp::new(&double, &VecDeque::from(vec![5]));
+        let handle = std::thread::spawn(move || {
+            let mut sender = sender;
+            producer.run_with_devices(&mut VecDeque::new(), &mut sender)
+        });
+        let mut output = VecDeque::new();
+        assert_eq!( IntOpCodeComp::new(&double, &VecDeque::new()).run_with_devices(&mut receiver, &mut output), Ok(RunState::Halted) );
+        assert_eq!( handle.join().unwrap(), Ok(RunState::Halted) );
+        assert_eq!( output, vec![20] );
+    }
+}
@@ -0,0 +1,489 @@
+use std::convert::TryInto;
+use std::error::Error;
+use std::fmt;
+use std::sync::{Arc, OnceLock};
+
+use crate::{IntOpCodeComp, IntOpCodeError, ParameterMode, RunState};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ParameterKind {
+    // The parameter's value is used
+    Read,
+    // The parameter is the address a result is stored at
+    Write,
+}
+
+// Runs an instruction whose opcode and parameter modes have already been checked
+pub type ExecuteFn = fn(&mut Instruction) -> Result<(), IntOpCodeError>;
+
+#[derive(Debug, Clone)]
+pub struct OpcodeDefinition {
+    // Two digit opcode, the digits above it are the parameter modes
+    pub opcode: i64,
+    pub mnemonic: &'static str,
+    pub parameters: Vec<ParameterKind>,
+    // Advent of Code day the opcode first appeared on, for strict day-N instruction sets
+    pub day: u32,
+    pub execute: ExecuteFn,
+}
+
+// Why register turned a definition down
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DefinitionError {
+    // Opcodes are the two digits below the parameter modes
+    OpcodeOutOfRange(i64),
+    // Only three mode digits fit above the opcode
+    TooManyParameters { opcode: i64, count: usize },
+}
+
+impl fmt::Display for DefinitionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            DefinitionError::OpcodeOutOfRange(opcode) => write!(f, "opcode {} isn't two digits", opcode),
+            DefinitionError::TooManyParameters { opcode, count } => write!(f, "opcode {} has {} parameters, at most 3 fit", opcode, count),
+        }
+    }
+}
+
+impl Error for DefinitionError {}
+
+// What happens to the instruction pointer once an instruction is done
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Next {
+    Advance,
+    Jump(usize),
+    Stay,
+}
+
+// The engine an instruction runs on, IntOpCodeComp or FastIntOpCodeComp. Parameters are
+// those of the instruction at the engine's ip.
+pub(crate) trait Machine {
+    // Value of the nth (1 based) parameter
+    fn read_param(&self, modes: &[ParameterMode], n: usize) -> Result<i64, IntOpCodeError>;
+    // Address the nth (1 based) parameter writes to
+    fn write_param_index(&self, modes: &[ParameterMode], n: usize) -> Result<usize, IntOpCodeError>;
+    fn store(&mut self, address: usize, value: i64) -> Result<(), IntOpCodeError>;
+    fn negative_address(&self, parameter: usize, address: i64) -> IntOpCodeError;
+    fn overflow(&self) -> IntOpCodeError;
+    // Next input value, the machine is left waiting on input if there is none
+    fn take_input(&mut self) -> Option<i64>;
+    fn output(&mut self, value: i64);
+    fn relative_base(&self) -> i64;
+    fn set_relative_base(&mut self, relative_base: i64);
+    // Marks the machine halted, returning the address just past its memory
+    fn halt(&mut self) -> usize;
+}
+
+// The instruction being run, handed to its ExecuteFn. Parameters are only read when asked
+// for, so e.g. a jump that isn't taken never touches its target.
+pub struct Instruction<'a> {
+    machine: &'a mut dyn Machine,
+    // Only the first parameter_count are used
+    modes: [ParameterMode; 3],
+    parameter_count: usize,
+    next: Next,
+}
+
+impl Instruction<'_> {
+    // Value of the nth (1 based) parameter
+    pub fn read(&self, n: usize) -> Result<i64, IntOpCodeError> {
+        self.machine.read_param(&self.modes[..self.parameter_count], n)
+    }
+
+    // Address the nth (1 based) parameter writes to
+    pub fn write_address(&self, n: usize) -> Result<usize, IntOpCodeError> {
+        self.machine.write_param_index(&self.modes[..self.parameter_count], n)
+    }
+
+    pub fn store(&mut self, address: usize, value: i64) -> Result<(), IntOpCodeError> {
+        self.machine.store(address, value)
+    }
+
+    // Stores value at the address of the nth (1 based) parameter
+    pub fn write(&mut self, n: usize, value: i64) -> Result<(), IntOpCodeError> {
+        let address = self.write_address(n)?;
+        self.store(address, value)
+    }
+
+    // Continues at target, which came from the nth parameter and is reported against it if negative
+    pub fn jump(&mut self, n: usize, target: i64) -> Result<(), IntOpCodeError> {
+        let target = target.try_into().map_err(|_| self.machine.negative_address(n, target))?;
+        self.next = Next::Jump(target);
+        Ok(())
+    }
+
+    // Next input value. With none available the program waits and the instruction runs again
+    // once there is one.
+    pub fn input(&mut self) -> Option<i64> {
+        let value = self.machine.take_input();
+        if value.is_none() {
+            self.next = Next::Stay;
+        }
+        value
+    }
+
+    pub fn output(&mut self, value: i64) {
+        self.machine.output(value);
+    }
+
+    pub fn relative_base(&self) -> i64 {
+        self.machine.relative_base()
+    }
+
+    pub fn set_relative_base(&mut self, relative_base: i64) {
+        self.machine.set_relative_base(relative_base);
+    }
+
+    pub fn halt(&mut self) {
+        self.next = Next::Jump(self.machine.halt());
+    }
+
+    // Error for a result that doesn't fit in an i64
+    pub fn overflow(&self) -> IntOpCodeError {
+        self.machine.overflow()
+    }
+}
+
+// Runs the instruction at the machine's ip, with its parameter modes already checked
+pub(crate) fn execute_on(machine: &mut dyn Machine, execute: ExecuteFn, modes: [ParameterMode; 3], parameter_count: usize) -> Result<Next, IntOpCodeError> {
+    let mut instruction = Instruction { machine, modes, parameter_count, next: Next::Advance };
+    execute(&mut instruction)?;
+    Ok(instruction.next)
+}
+
+// The opcodes a machine understands and the parameter modes it accepts
+#[derive(Debug, Clone)]
+pub struct InstructionSet {
+    // Indexed by opcode, looked up for every instruction run so it is a plain array
+    opcodes: [Option<OpcodeDefinition>; 100],
+    // Indexed by opcode, whether it still has the definition standard() gave it
+    standard: [bool; 100],
+    // Indexed by mode
+    modes: [bool; 3],
+    // Reject immediate mode on parameters that are written to, instead of treating it like position mode
+    strict_writes: bool,
+}
+
+fn add(instruction: &mut Instruction) -> Result<(), IntOpCodeError> {
+    // Adds the next two numbers together, store in index of third number
+    let a = instruction.read(1)?;
+    let b = instruction.read(2)?;
+    let store_index = instruction.write_address(3)?;
+    let sum = a.checked_add(b).ok_or_else(|| instruction.overflow())?;
+    instruction.store(store_index, sum)
+}
+
+fn multiply(instruction: &mut Instruction) -> Result<(), IntOpCodeError> {
+    // Multiplies the next two numbers together, store in index of third number
+    let a = instruction.read(1)?;
+    let b = instruction.read(2)?;
+    let store_index = instruction.write_address(3)?;
+    let product = a.checked_mul(b).ok_or_else(|| instruction.overflow())?;
+    instruction.store(store_index, product)
+}
+
+fn input(instruction: &mut Instruction) -> Result<(), IntOpCodeError> {
+    // Opcode 3 takes a single integer as input and saves it to the position given by its only parameter.
+    // For example, the instruction 3,50 would take an input value and store it at address 50.
+    let store_index = instruction.write_address(1)?;
+    match instruction.input() {
+        Some(value) => instruction.store(store_index, value),
+        None => Ok(())  // just wait until we get an input at some point
+    }
+}
+
+fn output(instruction: &mut Instruction) -> Result<(), IntOpCodeError> {
+    // Opcode 4 outputs the value of its only parameter.
+    // For example, the instruction 4,50 would output the value at address 50.
+    let value = instruction.read(1)?;
+    instruction.output(value);
+    Ok(())
+}
+
+fn jump_if_true(instruction: &mut Instruction) -> Result<(), IntOpCodeError> {
+    // if the first parameter is non-zero, it sets the instruction pointer to the value from the
+    // second parameter. Otherwise, it does nothing.
+    if instruction.read(1)? != 0 {
+        let target = instruction.read(2)?;
+        instruction.jump(2, target)?;
+    }
+    Ok(())
+}
+
+fn jump_if_false(instruction: &mut Instruction) -> Result<(), IntOpCodeError> {
+    // if the first parameter is zero, it sets the instruction pointer to the value from the
+    // second parameter. Otherwise, it does nothing.
+    if instruction.read(1)? == 0 {
+        let target = instruction.read(2)?;
+        instruction.jump(2, target)?;
+    }
+    Ok(())
+}
+
+fn less_than(instruction: &mut Instruction) -> Result<(), IntOpCodeError> {
+    // if the first parameter is less than the second parameter, it stores 1 in the position
+    // given by the third parameter. Otherwise, it stores 0.
+    let a = instruction.read(1)?;
+    let b = instruction.read(2)?;
+    instruction.write(3, (a < b) as i64)
+}
+
+fn equals(instruction: &mut Instruction) -> Result<(), IntOpCodeError> {
+    // if the first parameter is equal to the second parameter, it stores 1 in the position
+    // given by the third parameter. Otherwise, it stores 0.
+    let a = instruction.read(1)?;
+    let b = instruction.read(2)?;
+    instruction.write(3, (a == b) as i64)
+}
+
+fn adjust_relative_base(instruction: &mut Instruction) -> Result<(), IntOpCodeError> {
+    // adjusts the relative base by the value of its only parameter
+    let a = instruction.read(1)?;
+    let relative_base = instruction.relative_base().checked_add(a).ok_or_else(|| instruction.overflow())?;
+    instruction.set_relative_base(relative_base);
+    Ok(())
+}
+
+fn halt(instruction: &mut Instruction) -> Result<(), IntOpCodeError> {
+    instruction.halt();
+    Ok(())
+}
+
+// Day each parameter mode first appeared on: position on day 2, immediate on day 5, relative on day 9
+const MODE_DAYS: [(i64, u32); 3] = [(0, 2), (1, 5), (2, 9)];
+
+impl InstructionSet {
+    // No opcodes at all, accepting every parameter mode
+    pub fn empty() -> InstructionSet {
+        InstructionSet { opcodes: std::array::from_fn(|_| None), standard: [false; 100], modes: [true; 3], strict_writes: false }
+    }
+
+    // The complete 2019 instruction set
+    pub fn standard() -> InstructionSet {
+        use ParameterKind::{Read, Write};
+        let mut set = InstructionSet::empty();
+        let definitions : [(i64, &'static str, &[ParameterKind], u32, ExecuteFn); 10] = [
+            (1, "add", &[Read, Read, Write], 2, add),
+            (2, "mul", &[Read, Read, Write], 2, multiply),
+            (3, "in", &[Write], 5, input),
+            (4, "out", &[Read], 5, output),
+            (5, "jnz", &[Read, Read], 5, jump_if_true),
+            (6, "jz", &[Read, Read], 5, jump_if_false),
+            (7, "lt", &[Read, Read, Write], 5, less_than),
+            (8, "eq", &[Read, Read, Write], 5, equals),
+            (9, "arb", &[Read], 9, adjust_relative_base),
+            (99, "hlt", &[], 2, halt),
+        ];
+        for (opcode, mnemonic, parameters, day, execute) in definitions {
+            set.insert(OpcodeDefinition { opcode, mnemonic, parameters: parameters.to_vec(), day, execute });
+            set.standard[opcode as usize] = true;
+        }
+        set
+    }
+
+    // Shared copy of the standard set every IntOpCodeComp starts with
+    pub fn standard_shared() -> Arc<InstructionSet> {
+        static STANDARD: OnceLock<Arc<InstructionSet>> = OnceLock::new();
+        Arc::clone(STANDARD.get_or_init(|| Arc::new(InstructionSet::standard())))
+    }
+
+    // Only what the puzzles up to the given day had introduced, e.g. day 2 is add, multiply and
+    // halt in position mode. Immediate mode on a parameter that is written to is rejected too.
+    pub fn for_day(day: u32) -> InstructionSet {
+        let mut set = InstructionSet::standard();
+        for slot in &mut set.opcodes {
+            if slot.as_ref().is_some_and(|definition| definition.day > day) {
+                *slot = None;
+            }
+        }
+        for &(mode, mode_day) in &MODE_DAYS {
+            set.modes[mode as usize] = mode_day <= day;
+        }
+        set.strict_writes = true;
+        set
+    }
+
+    // Adds an opcode, replacing and returning any existing definition of it. Parameter modes
+    // come from the three digits above the opcode, so at most three parameters are possible.
+    pub fn register(&mut self, definition: OpcodeDefinition) -> Result<Option<OpcodeDefinition>, DefinitionError> {
+        if !(0..100).contains(&definition.opcode) {
+            return Err(DefinitionError::OpcodeOutOfRange(definition.opcode));
+        }
+        if definition.parameters.len() > 3 {
+            return Err(DefinitionError::TooManyParameters { opcode: definition.opcode, count: definition.parameters.len() });
+        }
+        Ok(self.insert(definition))
+    }
+
+    // register without the checks, for definitions known to be valid
+    fn insert(&mut self, definition: OpcodeDefinition) -> Option<OpcodeDefinition> {
+        let slot = definition.opcode as usize;
+        self.standard[slot] = false;
+        self.opcodes[slot].replace(definition)
+    }
+
+    fn slot(opcode: i64) -> Option<usize> {
+        if (0..100).contains(&opcode) { Some(opcode as usize) } else { None }
+    }
+
+    pub fn remove(&mut self, opcode: i64) -> Option<OpcodeDefinition> {
+        let slot = InstructionSet::slot(opcode)?;
+        self.standard[slot] = false;
+        self.opcodes[slot].take()
+    }
+
+    pub fn get(&self, opcode: i64) -> Option<&OpcodeDefinition> {
+        self.opcodes[InstructionSet::slot(opcode)?].as_ref()
+    }
+
+    // Every opcode defined, in order
+    pub fn opcodes(&self) -> impl Iterator<Item = &OpcodeDefinition> {
+        self.opcodes.iter().flatten()
+    }
+
+    // Whether opcode is one of the 2019 opcodes, still defined the standard way. Its meaning is
+    // then known without running it, e.g. to follow jumps or to run it without an ExecuteFn.
+    pub fn is_standard(&self, opcode: i64) -> bool {
+        InstructionSet::slot(opcode).is_some_and(|slot| self.standard[slot] && self.opcodes[slot].is_some())
+    }
+
+    // The definition whose mnemonic this is
+    pub fn find_mnemonic(&self, mnemonic: &str) -> Option<&OpcodeDefinition> {
+        self.opcodes().find(|definition| definition.mnemonic == mnemonic)
+    }
+
+    pub fn parameter_count(&self, opcode: i64) -> Option<usize> {
+        self.get(opcode).map(|definition| definition.parameters.len())
+    }
+
+    pub fn allows_mode(&self, mode: i64, kind: ParameterKind) -> bool {
+        (0..3).contains(&mode) && self.modes[mode as usize] && !(self.strict_writes && kind == ParameterKind::Write && mode == 1)
+    }
+}
+
+impl IntOpCodeComp {
+    pub fn instruction_set(&self) -> &InstructionSet {
+        &self.instruction_set
+    }
+
+    // Runs the program with different opcodes, e.g. InstructionSet::for_day(2) to reject
+    // anything a day 2 program shouldn't use
+    pub fn set_instruction_set(&mut self, instruction_set: Arc<InstructionSet>) {
+        self.instruction_set = instruction_set;
+    }
+
+    // Runs the instruction at ip, with its parameter modes already checked
+    pub(crate) fn execute(&mut self, execute: ExecuteFn, modes: [ParameterMode; 3], parameter_count: usize) -> Result<(), IntOpCodeError> {
+        match execute_on(self, execute, modes, parameter_count)? {
+            Next::Advance => self.index += parameter_count + 1,
+            Next::Jump(target) => self.index = target,
+            Next::Stay => {},
+        }
+        Ok(())
+    }
+}
+
+impl Machine for IntOpCodeComp {
+    fn read_param(&self, modes: &[ParameterMode], n: usize) -> Result<i64, IntOpCodeError> {
+        IntOpCodeComp::read_param(self, modes, n)
+    }
+
+    fn write_param_index(&self, modes: &[ParameterMode], n: usize) -> Result<usize, IntOpCodeError> {
+        IntOpCodeComp::write_param_index(self, modes, n)
+    }
+
+    fn store(&mut self, address: usize, value: i64) -> Result<(), IntOpCodeError> {
+        self.store_at_index(value, address)
+    }
+
+    fn negative_address(&self, parameter: usize, address: i64) -> IntOpCodeError {
+        IntOpCodeComp::negative_address(self, parameter, address)
+    }
+
+    fn overflow(&self) -> IntOpCodeError {
+        IntOpCodeComp::overflow(self)
+    }
+
+    fn take_input(&mut self) -> Option<i64> {
+        let value = self.input_values.pop_front();
+        self.state = if value.is_some() { RunState::Running } else { RunState::WaitingOnInput };
+        value
+    }
+
+    fn output(&mut self, value: i64) {
+        self.output_values.push_back(value);
+    }
+
+    fn relative_base(&self) -> i64 {
+        self.relative_base
+    }
+
+    fn set_relative_base(&mut self, relative_base: i64) {
+        self.relative_base = relative_base;
+    }
+
+    fn halt(&mut self) -> usize {
+        self.state = RunState::Halted;
+        self.memory.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    fn run(program: &[i64], instruction_set: &Arc<InstructionSet>) -> Result<Vec<i64>, IntOpCodeError> {
+        let mut comp = IntOpCodeComp::new(program, &VecDeque::new());
+        comp.set_instruction_set(Arc::clone(instruction_set));
+        Ok(comp.tick_to_completion()?.iter().copied().collect())
+    }
+
+    #[test]
+    fn custom_opcode_test() {
+        let mut set = InstructionSet::standard();
+        set.register(OpcodeDefinition { opcode: 10, mnemonic: "div", parameters: vec![ParameterKind::Read, ParameterKind::Read, ParameterKind::Write], day: 0, execute: |instruction| {
+            let quotient = instruction.read(1)?.checked_div(instruction.read(2)?).ok_or_else(|| instruction.overflow())?;
+            instruction.write(3, quotient)
+        } }).unwrap();
+        set.register(OpcodeDefinition { opcode: 11, mnemonic: "mod", parameters: vec![ParameterKind::Read, ParameterKind::Read, ParameterKind::Write], day: 0, execute: |instruction| {
+            let remainder = instruction.read(1)?.checked_rem(instruction.read(2)?).ok_or_else(|| instruction.overflow())?;
+            instruction.write(3, remainder)
+        } }).unwrap();
+        let set = Arc::new(set);
+
+        // 47 / 5 and 47 % 5
+        let program = [1110,47,5,13,1111,47,5,14,4,13,4,14,99];
+        assert_eq!( run(&program, &set), Ok(vec![9, 2]) );
+        assert_eq!( run(&[1110,1,0,0,99], &set), Err(IntOpCodeError::Overflow { ip: 0, opcode: 1110 }) );
+        assert_eq!( run(&program, &InstructionSet::standard_shared()), Err(IntOpCodeError::UnknownOpcode { ip: 0, opcode: 1110 }) );
+        assert_eq!( set.parameter_count(10), Some(3) );
+
+        let mut set = InstructionSet::standard();
+        let nop = |opcode, count| OpcodeDefinition { opcode, mnemonic: "nop", parameters: vec![ParameterKind::Read; count], day: 0, execute: |_| Ok(()) };
+        assert_eq!( set.register(nop(100, 0)).unwrap_err(), DefinitionError::OpcodeOutOfRange(100) );
+        assert_eq!( set.register(nop(-1, 0)).unwrap_err().to_string(), "opcode -1 isn't two digits" );
+        assert_eq!( set.register(nop(12, 4)).unwrap_err(), DefinitionError::TooManyParameters { opcode: 12, count: 4 } );
+        assert_eq!( set.register(nop(99, 0)).unwrap().map(|replaced| replaced.mnemonic), Some("hlt") );
+        assert_eq!( set.register(nop(12, 3)).unwrap().map(|replaced| replaced.mnemonic), None );
+    }
+
+    #[test]
+    fn day_instruction_set_test() {
+        let day2 = Arc::new(InstructionSet::for_day(2));
+        assert_eq!( run(&[1,0,0,0,99], &day2), Ok(vec![]) );
+        assert_eq!( run(&[1101,1,1,0,99], &day2), Err(IntOpCodeError::UnknownParameterMode { ip: 0, opcode: 1101, parameter: 1, mode: 1 }) );
+        assert_eq!( run(&[104,1,99], &day2), Err(IntOpCodeError::UnknownOpcode { ip: 0, opcode: 104 }) );
+
+        let day5 = Arc::new(InstructionSet::for_day(5));
+        assert_eq!( run(&[1101,1,1,0,4,0,99], &day5), Ok(vec![2]) );
+        assert_eq!( run(&[109,1,99], &day5), Err(IntOpCodeError::UnknownOpcode { ip: 0, opcode: 109 }) );
+        assert_eq!( run(&[11101,1,1,0,99], &day5), Err(IntOpCodeError::UnknownParameterMode { ip: 0, opcode: 11101, parameter: 3, mode: 1 }) );
+        assert_eq!( run(&[204,0,99], &day5), Err(IntOpCodeError::UnknownParameterMode { ip: 0, opcode: 204, parameter: 1, mode: 2 }) );
+
+        // the standard set lets an immediate mode write through as position mode, like it always has
+        assert_eq!( run(&[11101,1,1,0,4,0,99], &InstructionSet::standard_shared()), Ok(vec![2]) );
+        assert_eq!( run(&[109,1,204,-1,99], &Arc::new(InstructionSet::for_day(9))), Ok(vec![109]) );
+        assert_eq!( InstructionSet::for_day(9).opcodes().count(), InstructionSet::standard().opcodes().count() );
+    }
+}
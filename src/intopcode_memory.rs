@@ -0,0 +1,359 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+// Limit used by IntOpCodeComp::new, far more than any puzzle needs but small enough to always allocate
+pub const DEFAULT_MEMORY_LIMIT: usize = 1 << 24;
+
+// Number of words in each page of a PagedMemory
+pub const PAGE_SIZE: usize = 1024;
+
+// Returned by a write that would take a memory past its limit
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryLimitExceeded {
+    pub address: usize,
+    pub limit: usize,
+}
+
+impl fmt::Display for MemoryLimitExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "address {} is past the memory limit of {} words", self.address, self.limit)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MemoryKind {
+    Dense,
+    Paged,
+    Sparse,
+}
+
+impl MemoryKind {
+    pub fn create(self, intopcodes: &[i64], limit: Option<usize>) -> Result<Box<dyn IntOpCodeMemory>, MemoryLimitExceeded> {
+        Ok(match self {
+            MemoryKind::Dense => Box::new(DenseMemory::new(intopcodes, limit)?),
+            MemoryKind::Paged => Box::new(PagedMemory::new(intopcodes, limit)?),
+            MemoryKind::Sparse => Box::new(SparseMemory::new(intopcodes, limit)?),
+        })
+    }
+}
+
+impl fmt::Display for MemoryKind {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            MemoryKind::Dense => "dense",
+            MemoryKind::Paged => "paged",
+            MemoryKind::Sparse => "sparse",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+impl FromStr for MemoryKind {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<MemoryKind, String> {
+        match name {
+            "dense" => Ok(MemoryKind::Dense),
+            "paged" => Ok(MemoryKind::Paged),
+            "sparse" => Ok(MemoryKind::Sparse),
+            _ => Err(format!("unknown memory kind '{}', expected dense, paged or sparse", name))
+        }
+    }
+}
+
+// Storage behind an IntOpCodeComp. Every address reads as 0 until it is written.
+pub trait IntOpCodeMemory: fmt::Debug + Send {
+    fn read(&self, address: usize) -> i64;
+    fn write(&mut self, address: usize, value: i64) -> Result<(), MemoryLimitExceeded>;
+
+    // One past the highest address loaded or written
+    fn len(&self) -> usize;
+
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    // Number of words actually allocated, which is what the limit applies to
+    fn cells_used(&self) -> usize;
+
+    // Dense copy of addresses 0..len(). Refused if that is more words than the memory may use,
+    // or than DEFAULT_MEMORY_LIMIT without a limit, since a far away write makes it huge.
+    fn to_vec(&self) -> Result<Vec<i64>, MemoryLimitExceeded> {
+        check_limit(Some(self.limit().unwrap_or(DEFAULT_MEMORY_LIMIT)), self.len(), self.len().saturating_sub(1))?;
+        let mut words = vec![0; self.len()];
+        for (address, value) in self.cells() {
+            words[address] = value;
+        }
+        Ok(words)
+    }
+
+    // Every non-zero word as (address, value), in address order
+    fn cells(&self) -> Vec<(usize, i64)>;
+
+    fn kind(&self) -> MemoryKind;
+    fn limit(&self) -> Option<usize>;
+
+    fn box_clone(&self) -> Box<dyn IntOpCodeMemory>;
+}
+
+impl Clone for Box<dyn IntOpCodeMemory> {
+    fn clone(&self) -> Box<dyn IntOpCodeMemory> {
+        self.box_clone()
+    }
+}
+
+fn check_limit(limit: Option<usize>, cells_needed: usize, address: usize) -> Result<(), MemoryLimitExceeded> {
+    match limit {
+        Some(limit) if cells_needed > limit => Err(MemoryLimitExceeded { address, limit }),
+        _ => Ok(())
+    }
+}
+
+// A single vector grown up to the highest address written. Fastest, but writing to a
+// far away address allocates everything in between.
+#[derive(Debug, Clone)]
+pub struct DenseMemory {
+    cells: Vec<i64>,
+    limit: Option<usize>,
+}
+
+impl DenseMemory {
+    pub fn new(intopcodes: &[i64], limit: Option<usize>) -> Result<DenseMemory, MemoryLimitExceeded> {
+        check_limit(limit, intopcodes.len(), intopcodes.len().saturating_sub(1))?;
+        Ok(DenseMemory { cells: intopcodes.to_vec(), limit })
+    }
+}
+
+impl IntOpCodeMemory for DenseMemory {
+    fn read(&self, address: usize) -> i64 {
+        self.cells.get(address).copied().unwrap_or(0)
+    }
+
+    fn write(&mut self, address: usize, value: i64) -> Result<(), MemoryLimitExceeded> {
+        if address >= self.cells.len() {
+            check_limit(self.limit, address.saturating_add(1), address)?;
+            self.cells.resize(address + 1, 0);   // Expand memory to necessary size filled with 0s
+        }
+        self.cells[address] = value;
+        Ok(())
+    }
+
+    fn len(&self) -> usize {
+        self.cells.len()
+    }
+
+    fn cells_used(&self) -> usize {
+        self.cells.len()
+    }
+
+    fn to_vec(&self) -> Result<Vec<i64>, MemoryLimitExceeded> {
+        Ok(self.cells.clone())
+    }
+
+    fn cells(&self) -> Vec<(usize, i64)> {
+        self.cells.iter().copied().enumerate().filter(|&(_, value)| value != 0).collect()
+    }
+
+    fn kind(&self) -> MemoryKind {
+        MemoryKind::Dense
+    }
+
+    fn limit(&self) -> Option<usize> {
+        self.limit
+    }
+
+    fn box_clone(&self) -> Box<dyn IntOpCodeMemory> {
+        Box::new(self.clone())
+    }
+}
+
+// Fixed size pages allocated the first time anything in them is written. Keeps most of the
+// speed of DenseMemory for programs that use a few scattered regions.
+#[derive(Debug, Clone)]
+pub struct PagedMemory {
+    pages: HashMap<usize, Vec<i64>>,
+    len: usize,
+    limit: Option<usize>,
+}
+
+impl PagedMemory {
+    pub fn new(intopcodes: &[i64], limit: Option<usize>) -> Result<PagedMemory, MemoryLimitExceeded> {
+        check_limit(limit, intopcodes.chunks(PAGE_SIZE).len() * PAGE_SIZE, intopcodes.len().saturating_sub(1))?;
+        let mut pages = HashMap::new();
+        for (page_index, chunk) in intopcodes.chunks(PAGE_SIZE).enumerate() {
+            let mut page = chunk.to_vec();
+            page.resize(PAGE_SIZE, 0);
+            pages.insert(page_index, page);
+        }
+        Ok(PagedMemory { pages, len: intopcodes.len(), limit })
+    }
+}
+
+impl IntOpCodeMemory for PagedMemory {
+    fn read(&self, address: usize) -> i64 {
+        match self.pages.get(&(address / PAGE_SIZE)) {
+            Some(page) => page[address % PAGE_SIZE],
+            None => 0
+        }
+    }
+
+    fn write(&mut self, address: usize, value: i64) -> Result<(), MemoryLimitExceeded> {
+        let page_index = address / PAGE_SIZE;
+        if !self.pages.contains_key(&page_index) {
+            check_limit(self.limit, (self.pages.len() + 1) * PAGE_SIZE, address)?;
+            self.pages.insert(page_index, vec![0; PAGE_SIZE]);
+        }
+        self.pages.get_mut(&page_index).unwrap()[address % PAGE_SIZE] = value;
+        self.len = std::cmp::max(self.len, address.saturating_add(1));
+        Ok(())
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn cells_used(&self) -> usize {
+        self.pages.len() * PAGE_SIZE
+    }
+
+    fn cells(&self) -> Vec<(usize, i64)> {
+        let mut page_indexes : Vec<usize> = self.pages.keys().copied().collect();
+        page_indexes.sort_unstable();
+        page_indexes.iter()
+            .flat_map(|&page_index| self.pages[&page_index].iter().enumerate().map(move |(offset, &value)| (page_index * PAGE_SIZE + offset, value)))
+            .filter(|&(_, value)| value != 0)
+            .collect()
+    }
+
+    fn kind(&self) -> MemoryKind {
+        MemoryKind::Paged
+    }
+
+    fn limit(&self) -> Option<usize> {
+        self.limit
+    }
+
+    fn box_clone(&self) -> Box<dyn IntOpCodeMemory> {
+        Box::new(self.clone())
+    }
+}
+
+// One hash entry per non-zero word. Slowest per access but memory only grows with the
+// number of distinct addresses written.
+#[derive(Debug, Clone)]
+pub struct SparseMemory {
+    cells: HashMap<usize, i64>,
+    len: usize,
+    limit: Option<usize>,
+}
+
+impl SparseMemory {
+    pub fn new(intopcodes: &[i64], limit: Option<usize>) -> Result<SparseMemory, MemoryLimitExceeded> {
+        let cells : HashMap<usize, i64> = intopcodes.iter().enumerate()
+            .filter(|(_, &value)| value != 0)
+            .map(|(address, &value)| (address, value))
+            .collect();
+        check_limit(limit, cells.len(), intopcodes.len().saturating_sub(1))?;
+        Ok(SparseMemory { cells, len: intopcodes.len(), limit })
+    }
+}
+
+impl IntOpCodeMemory for SparseMemory {
+    fn read(&self, address: usize) -> i64 {
+        self.cells.get(&address).copied().unwrap_or(0)
+    }
+
+    fn write(&mut self, address: usize, value: i64) -> Result<(), MemoryLimitExceeded> {
+        if value == 0 {
+            self.cells.remove(&address);
+        }
+        else if !self.cells.contains_key(&address) {
+            check_limit(self.limit, self.cells.len() + 1, address)?;
+            self.cells.insert(address, value);
+        }
+        else {
+            self.cells.insert(address, value);
+        }
+        self.len = std::cmp::max(self.len, address.saturating_add(1));
+        Ok(())
+    }
+
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn cells_used(&self) -> usize {
+        self.cells.len()
+    }
+
+    fn cells(&self) -> Vec<(usize, i64)> {
+        let mut cells : Vec<(usize, i64)> = self.cells.iter().map(|(&address, &value)| (address, value)).collect();
+        cells.sort_unstable();
+        cells
+    }
+
+    fn kind(&self) -> MemoryKind {
+        MemoryKind::Sparse
+    }
+
+    fn limit(&self) -> Option<usize> {
+        self.limit
+    }
+
+    fn box_clone(&self) -> Box<dyn IntOpCodeMemory> {
+        Box::new(self.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn exercise_memory(memory: &mut dyn IntOpCodeMemory) {
+        assert_eq!(memory.read(1), 2);
+        assert_eq!(memory.read(5000), 0);
+        memory.write(1, 7).unwrap();
+        memory.write(PAGE_SIZE * 3 + 5, -4).unwrap();
+        assert_eq!(memory.read(1), 7);
+        assert_eq!(memory.read(PAGE_SIZE * 3 + 5), -4);
+        assert_eq!(memory.len(), PAGE_SIZE * 3 + 6);
+        let copy = memory.to_vec().unwrap();
+        assert_eq!(copy.len(), PAGE_SIZE * 3 + 6);
+        assert_eq!(&copy[..4], &[1, 7, 3, 0]);
+        assert_eq!(copy[PAGE_SIZE * 3 + 5], -4);
+        assert_eq!(memory.cells(), vec![(0, 1), (1, 7), (2, 3), (PAGE_SIZE * 3 + 5, -4)]);
+    }
+
+    #[test]
+    fn memory_backends_test() {
+        exercise_memory(&mut DenseMemory::new(&[1, 2, 3], None).unwrap());
+        exercise_memory(&mut PagedMemory::new(&[1, 2, 3], None).unwrap());
+        exercise_memory(&mut SparseMemory::new(&[1, 2, 3], None).unwrap());
+    }
+
+    #[test]
+    fn memory_limit_test() {
+        let far = 1_000_000_000_000;
+        assert_eq!(DenseMemory::new(&[1, 2, 3], Some(100)).unwrap().write(far, 1), Err(MemoryLimitExceeded { address: far, limit: 100 }));
+        assert_eq!(DenseMemory::new(&[1, 2, 3], Some(2)).unwrap_err(), MemoryLimitExceeded { address: 2, limit: 2 });
+
+        let mut paged = PagedMemory::new(&[1, 2, 3], Some(2 * PAGE_SIZE)).unwrap();
+        assert_eq!(paged.write(far, 1), Ok(()));
+        assert_eq!(paged.cells_used(), 2 * PAGE_SIZE);
+        assert_eq!(paged.write(far + PAGE_SIZE, 1), Err(MemoryLimitExceeded { address: far + PAGE_SIZE, limit: 2 * PAGE_SIZE }));
+        // a dense copy would take terabytes
+        assert_eq!(paged.to_vec(), Err(MemoryLimitExceeded { address: far, limit: 2 * PAGE_SIZE }));
+        assert_eq!(PagedMemory::new(&[1; PAGE_SIZE + 1], Some(PAGE_SIZE)).unwrap_err(), MemoryLimitExceeded { address: PAGE_SIZE, limit: PAGE_SIZE });
+
+        let mut sparse = SparseMemory::new(&[1, 0, 3], Some(3)).unwrap();
+        assert_eq!(sparse.write(far, 1), Ok(()));
+        assert_eq!(sparse.write(far, 0), Ok(()));   // zeroing a word frees its entry
+        assert_eq!(sparse.write(far + 1, 1), Ok(()));
+        assert_eq!(sparse.write(far + 2, 1), Err(MemoryLimitExceeded { address: far + 2, limit: 3 }));
+        assert_eq!(sparse.len(), far + 2);
+        assert_eq!(sparse.to_vec(), Err(MemoryLimitExceeded { address: far + 1, limit: 3 }));
+        assert_eq!(SparseMemory::new(&[1, 0, 3], Some(1)).unwrap_err(), MemoryLimitExceeded { address: 2, limit: 1 });
+        assert_eq!(SparseMemory::new(&[0; 10], None).unwrap().to_vec(), Ok(vec![0; 10]));
+    }
+}
@@ -0,0 +1,326 @@
+use std::collections::VecDeque;
+
+use crate::{IntOpCodeComp, IntOpCodeError, RunState};
+use crate::intopcode_budget::{RunBudget, RunOutcome};
+
+// A packet sent on a packet switched network, the program outputs it as address, x, y
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Packet {
+    pub address: i64,
+    pub x: i64,
+    pub y: i64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum NetworkStatus {
+    // Something happened during the last round, keep going
+    Running,
+    // Every machine has halted
+    Halted,
+    // Packet network where every machine is polling with nothing to receive
+    Idle,
+    // Every machine that hasn't halted is waiting on input nobody will send
+    Deadlocked,
+    // A machine went over the budget for one turn or was caught looping, outcome says which
+    OverBudget { machine: usize, outcome: RunOutcome },
+}
+
+// What a machine did in its turn
+struct Turn {
+    sent: Vec<i64>,
+    // Whether it did anything beyond waiting
+    active: bool,
+    // Why it was stopped before it needed input, if it was
+    over_budget: Option<RunOutcome>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Routing {
+    // Outputs are copied to every machine linked with connect
+    Channels,
+    // Outputs are (address, x, y) triples and x, y is delivered to the machine at that address
+    Packets,
+}
+
+// Any number of machines passing values between each other. Each round runs every machine in
+// turn until it needs input it doesn't have, then delivers whatever it sent.
+pub struct Network {
+    machines: Vec<IntOpCodeComp>,
+    routing: Routing,
+    // links[i] holds the machines receiving the outputs of machine i
+    links: Vec<Vec<usize>>,
+    // Outputs of machines with no links, or packets addressed outside the network
+    outputs: Vec<VecDeque<i64>>,
+    external_packets: Vec<Packet>,
+    // Partly written packets, waiting for the rest of their triple
+    partial_packets: Vec<Vec<i64>>,
+    last_outputs: Vec<Option<i64>>,
+    // Value a packet machine reads when nothing has been sent to it, e.g. -1
+    idle_input: Option<i64>,
+    // Limits on each turn of each machine
+    budget: RunBudget,
+    // Instructions run by every machine so far
+    steps: u64,
+}
+
+impl Network {
+    // Machines passing values along links added with connect
+    pub fn new(machines: Vec<IntOpCodeComp>) -> Network {
+        let count = machines.len();
+        Network {
+            machines,
+            routing: Routing::Channels,
+            links: vec![Vec::new(); count],
+            outputs: vec![VecDeque::new(); count],
+            external_packets: Vec::new(),
+            partial_packets: vec![Vec::new(); count],
+            last_outputs: vec![None; count],
+            idle_input: None,
+            budget: RunBudget::default(),
+            steps: 0,
+        }
+    }
+
+    // Machines addressed by their index, sending each other (address, x, y) packets
+    pub fn packet_switched(machines: Vec<IntOpCodeComp>, idle_input: Option<i64>) -> Network {
+        let mut network = Network::new(machines);
+        network.routing = Routing::Packets;
+        network.idle_input = idle_input;
+        network
+    }
+
+    // Limits every machine's turns, from running until it needs input to giving up on it.
+    // Nothing is limited by default.
+    pub fn set_budget(&mut self, budget: RunBudget) {
+        self.budget = budget;
+    }
+
+    pub fn connect(&mut self, from: usize, to: usize) {
+        self.links[from].push(to);
+    }
+
+    pub fn send(&mut self, machine: usize, value: i64) {
+        self.machines[machine].push_input(value);
+    }
+
+    // Delivers a packet from outside, e.g. from a NAT watching for an idle network
+    pub fn send_packet(&mut self, packet: Packet) {
+        self.route_packet(packet);
+    }
+
+    // Instructions run by every machine so far. A budget only limits a turn, so this is what
+    // tells a network passing values round forever apart from one that is nearly done.
+    pub fn steps(&self) -> u64 {
+        self.steps
+    }
+
+    pub fn machine(&self, machine: usize) -> &IntOpCodeComp {
+        &self.machines[machine]
+    }
+
+    pub fn last_output(&self, machine: usize) -> Option<i64> {
+        self.last_outputs[machine]
+    }
+
+    // Values sent by a machine that had nowhere to go
+    pub fn outputs(&mut self, machine: usize) -> &mut VecDeque<i64> {
+        &mut self.outputs[machine]
+    }
+
+    pub fn external_packets(&mut self) -> &mut Vec<Packet> {
+        &mut self.external_packets
+    }
+
+    fn route_packet(&mut self, packet: Packet) {
+        if packet.address >= 0 && (packet.address as usize) < self.machines.len() {
+            let machine = &mut self.machines[packet.address as usize];
+            machine.push_input(packet.x);
+            machine.push_input(packet.y);
+        }
+        else {
+            self.external_packets.push(packet);
+        }
+    }
+
+    fn route(&mut self, from: usize, value: i64) {
+        self.last_outputs[from] = Some(value);
+        match self.routing {
+            Routing::Channels => {
+                if self.links[from].is_empty() {
+                    self.outputs[from].push_back(value);
+                }
+                for i in 0..self.links[from].len() {
+                    let to = self.links[from][i];
+                    self.machines[to].push_input(value);
+                }
+            },
+            Routing::Packets => {
+                self.partial_packets[from].push(value);
+                if self.partial_packets[from].len() == 3 {
+                    let triple = std::mem::take(&mut self.partial_packets[from]);
+                    self.route_packet(Packet { address: triple[0], x: triple[1], y: triple[2] });
+                }
+            }
+        }
+    }
+
+    // Runs one machine until it needs input it doesn't have or goes over the budget
+    fn run_machine(&mut self, index: usize) -> Result<Turn, IntOpCodeError> {
+        let machine = &mut self.machines[index];
+        if machine.state == RunState::Halted {
+            return Ok(Turn { sent: Vec::new(), active: false, over_budget: None });
+        }
+
+        let mut active = !machine.input_values.is_empty();
+        let mut polled = false;
+        let output_count = machine.output_values.len();
+        let over_budget = loop {
+            let outcome = machine.run_with_budget(&self.budget)?;
+            self.steps += outcome.steps();
+            match outcome {
+                RunOutcome::WaitingOnInput { .. } => match self.idle_input {
+                    Some(idle_input) if !polled => {
+                        machine.push_input(idle_input);
+                        polled = true;
+                    },
+                    _ => break None,
+                },
+                RunOutcome::Halted { .. } => {
+                    active = true;
+                    break None;
+                },
+                outcome => break Some(outcome),
+            }
+        };
+        let sent : Vec<i64> = machine.output_values.drain(output_count..).collect();
+        active = active || !sent.is_empty();
+        Ok(Turn { sent, active, over_budget })
+    }
+
+    // Runs every machine once, delivering its outputs as soon as its turn is over
+    pub fn tick(&mut self) -> Result<NetworkStatus, IntOpCodeError> {
+        let mut active = false;
+        for index in 0..self.machines.len() {
+            let turn = self.run_machine(index)?;
+            active = active || turn.active;
+            for value in turn.sent {
+                self.route(index, value);
+            }
+            if let Some(outcome) = turn.over_budget {
+                return Ok(NetworkStatus::OverBudget { machine: index, outcome });
+            }
+        }
+
+        if self.machines.iter().all(|m| m.state == RunState::Halted) {
+            Ok(NetworkStatus::Halted)
+        }
+        else if active {
+            Ok(NetworkStatus::Running)
+        }
+        else if self.idle_input.is_some() {
+            Ok(NetworkStatus::Idle)
+        }
+        else {
+            Ok(NetworkStatus::Deadlocked)
+        }
+    }
+
+    // Runs until every machine halts, nothing can make progress any more or a machine goes over
+    // the budget
+    pub fn run(&mut self) -> Result<NetworkStatus, IntOpCodeError> {
+        loop {
+            let status = self.tick()?;
+            if status != NetworkStatus::Running {
+                return Ok(status);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::intopcode_asm::assemble;
+
+    #[test]
+    fn channel_deadlock_test() {
+        // Adds one to whatever it receives, forever
+        let increment = vec![3,11,1001,11,1,11,4,11,1105,1,0,0];
+        let machines = vec![IntOpCodeComp::new(&increment, &VecDeque::new()), IntOpCodeComp::new(&increment, &VecDeque::new())];
+        let mut network = Network::new(machines);
+        network.connect(0, 1);
+        assert_eq!( network.run(), Ok(NetworkStatus::Deadlocked) );
+
+        // A chain with one value to pass along, the second machine's outputs have nowhere to go
+        network.send(0, 5);
+        assert_eq!( network.tick(), Ok(NetworkStatus::Running) );
+        assert_eq!( network.run(), Ok(NetworkStatus::Deadlocked) );
+        assert_eq!( network.outputs(1), &VecDeque::from(vec![7]) );
+
+        // Closing the loop keeps the value going around
+        network.connect(1, 0);
+        network.send(0, 0);
+        for _ in 0..10 {
+            assert_eq!( network.tick(), Ok(NetworkStatus::Running) );
+        }
+        assert_eq!( network.last_output(1), Some(20) );
+        assert_eq!( network.steps(), 88 );
+    }
+
+    #[test]
+    fn budget_test() {
+        // Echoes its first input, then spins without any input or output
+        let spin = vec![3,7,4,7,1105,1,4,0];
+        let machines = vec![IntOpCodeComp::new(&[104,5,99], &VecDeque::new()), IntOpCodeComp::new(&spin, &VecDeque::new())];
+        let mut network = Network::new(machines);
+        network.connect(0, 1);
+        network.set_budget(RunBudget { detect_loops: true, ..RunBudget::default() });
+        assert_eq!( network.run(), Ok(NetworkStatus::OverBudget { machine: 1, outcome: RunOutcome::NonTerminating { steps: 3, ip: 4, period: 1 } }) );
+        assert_eq!( network.outputs(1), &VecDeque::from(vec![5]) );
+
+        let mut network = Network::new(vec![IntOpCodeComp::new(&[1101,1,1,0,1105,1,0], &VecDeque::new())]);
+        network.set_budget(RunBudget { max_steps: Some(100), ..RunBudget::default() });
+        assert_eq!( network.run(), Ok(NetworkStatus::OverBudget { machine: 0, outcome: RunOutcome::StepBudgetExhausted { steps: 100 } }) );
+    }
+
+    #[test]
+    fn packet_network_test() {
+        // Sends (address + 1, address, address * 10), then reports every packet received to 255
+        let program = assemble("
+                    in [addr]
+                    add [addr], #1, [dest]
+                    out [dest]
+                    out [addr]
+                    mul [addr], #10, [y]
+                    out [y]
+            poll:   in [x]
+                    eq [x], #-1, [idle]
+                    jnz [idle], #poll
+                    in [y]
+                    out #255
+                    out [x]
+                    out [y]
+                    jz #0, #poll
+            addr:   .data 0
+            dest:   .data 0
+            x:      .data 0
+            y:      .data 0
+            idle:   .data 0
+        ").unwrap();
+        let machines = (0..3).map(|address| IntOpCodeComp::new(&program, &VecDeque::from(vec![address]))).collect();
+        let mut network = Network::packet_switched(machines, Some(-1));
+        assert_eq!( network.run(), Ok(NetworkStatus::Idle) );
+
+        network.external_packets().sort();
+        assert_eq!( network.external_packets(), &vec![
+            Packet { address: 3, x: 2, y: 20 },
+            Packet { address: 255, x: 0, y: 0 },
+            Packet { address: 255, x: 1, y: 10 },
+        ] );
+
+        network.external_packets().clear();
+        network.send_packet(Packet { address: 0, x: 7, y: 8 });
+        assert_eq!( network.run(), Ok(NetworkStatus::Idle) );
+        assert_eq!( network.external_packets(), &vec![Packet { address: 255, x: 7, y: 8 }] );
+    }
+}
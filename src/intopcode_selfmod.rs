@@ -0,0 +1,119 @@
+use std::collections::BTreeMap;
+use std::collections::HashSet;
+
+use crate::{decode_opcode, IntOpCodeComp};
+use crate::intopcode_isa::InstructionSet;
+use crate::intopcode_trace::{ExecutionObserver, TraceRecord};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum SelfModificationKind {
+    // The address was part of an instruction that already ran
+    ExecutedCode,
+    // The address is the opcode or an operand of the very next instruction
+    NextInstruction,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SelfModification {
+    pub kind: SelfModificationKind,
+    pub count: u64,
+}
+
+// Watches the writes of a running program for ones that land on its own instructions
+#[derive(Debug, Clone, Default)]
+pub struct SelfModificationDetector {
+    executed: HashSet<usize>,
+    // Keyed by (ip of the writing instruction, address written)
+    modifications: BTreeMap<(usize, usize), SelfModification>,
+}
+
+fn instruction_length(instruction_set: &InstructionSet, opcode: i64) -> usize {
+    instruction_set.parameter_count(decode_opcode(opcode).0).map_or(1, |count| count + 1)
+}
+
+impl SelfModificationDetector {
+    pub fn new() -> SelfModificationDetector {
+        SelfModificationDetector::default()
+    }
+
+    pub fn modifications(&self) -> &BTreeMap<(usize, usize), SelfModification> {
+        &self.modifications
+    }
+
+    pub fn report(&self) -> String {
+        if self.modifications.is_empty() {
+            return String::from("no self-modifying writes");
+        }
+
+        let mut lines = Vec::new();
+        for (&(ip, address), modification) in &self.modifications {
+            let kind = match modification.kind {
+                SelfModificationKind::ExecutedCode => "already executed code",
+                SelfModificationKind::NextInstruction => "the next instruction",
+            };
+            let times = if modification.count == 1 { String::from("once") } else { format!("{} times", modification.count) };
+            lines.push(format!("ip {} wrote [{}] {}, {}", ip, address, times, kind));
+        }
+        lines.join("\n")
+    }
+}
+
+impl ExecutionObserver for SelfModificationDetector {
+    fn observe(&mut self, record: &TraceRecord, comp: &IntOpCodeComp) {
+        // The instruction that just ran counts as executed, so writing over itself is caught too
+        self.executed.extend(record.ip..record.ip + record.operands.len() + 1);
+
+        let write = match record.write {
+            Some(write) => write,
+            None => return
+        };
+
+        let next_opcode = comp.memory().read(record.next_ip);
+        let next_instruction = record.next_ip..record.next_ip + instruction_length(comp.instruction_set(), next_opcode);
+        let kind = if next_instruction.contains(&write.address) {
+            SelfModificationKind::NextInstruction
+        }
+        else if self.executed.contains(&write.address) {
+            SelfModificationKind::ExecutedCode
+        }
+        else {
+            return
+        };
+
+        let modification = self.modifications.entry((record.ip, write.address)).or_insert(SelfModification { kind, count: 0 });
+        modification.kind = std::cmp::max(modification.kind, kind);
+        modification.count += 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+    use crate::intopcode_trace::run_observed;
+
+    #[test]
+    fn self_modification_test() {
+        // Loops twice, adding 100 to its first instruction and counting down in data at 16
+        let test = vec![4,15,1001,0,100,0,1001,16,-1,16,1005,16,0,99,0,7,2];
+        let mut comp = IntOpCodeComp::new(&test, &VecDeque::new());
+        let mut detector = SelfModificationDetector::new();
+        run_observed(&mut comp, &mut [&mut detector]).unwrap();
+        assert_eq!( detector.modifications().len(), 1 );
+        assert_eq!( detector.modifications()[&(2, 0)], SelfModification { kind: SelfModificationKind::ExecutedCode, count: 2 } );
+        assert_eq!( detector.report(), "ip 2 wrote [0] 2 times, already executed code" );
+
+        // Patches the operand of the output right after it
+        let test = vec![1101,0,9,5,4,0,99,0,0,42];
+        let mut comp = IntOpCodeComp::new(&test, &VecDeque::new());
+        let mut detector = SelfModificationDetector::new();
+        run_observed(&mut comp, &mut [&mut detector]).unwrap();
+        assert_eq!( comp.output_values, vec![42] );
+        assert_eq!( detector.report(), "ip 0 wrote [5] once, the next instruction" );
+
+        let mut comp = IntOpCodeComp::new(&[1101,1,2,5,99,0], &VecDeque::new());
+        let mut detector = SelfModificationDetector::new();
+        run_observed(&mut comp, &mut [&mut detector]).unwrap();
+        assert_eq!( detector.report(), "no self-modifying writes" );
+    }
+}
@@ -0,0 +1,305 @@
+use std::collections::VecDeque;
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::Arc;
+
+use crate::{IntOpCodeComp, RunState};
+use crate::intopcode_isa::InstructionSet;
+use crate::intopcode_memory::{IntOpCodeMemory, MemoryKind, DEFAULT_MEMORY_LIMIT};
+
+// First line of every serialized snapshot, bumped if the format ever changes
+const SNAPSHOT_HEADER: &str = "intopcode-snapshot 1";
+
+// Everything needed to put an IntOpCodeComp back exactly where it was: memory, instruction pointer,
+// relative base, run state, both I/O queues and the instruction set.
+#[derive(Debug, Clone)]
+pub struct Snapshot {
+    memory: Box<dyn IntOpCodeMemory>,
+    index: usize,
+    relative_base: i64,
+    state: RunState,
+    input_values: VecDeque<i64>,
+    output_values: VecDeque<i64>,
+    // Not saved to text, opcodes are functions. A loaded snapshot has the standard set.
+    instruction_set: Arc<InstructionSet>,
+}
+
+impl IntOpCodeComp {
+    pub fn snapshot(&self) -> Snapshot {
+        Snapshot {
+            memory: self.memory.clone(),
+            index: self.index,
+            relative_base: self.relative_base,
+            state: self.state,
+            input_values: self.input_values.clone(),
+            output_values: self.output_values.clone(),
+            instruction_set: Arc::clone(&self.instruction_set),
+        }
+    }
+
+    // Puts the machine back where the snapshot was taken. It keeps its own instruction set, so
+    // e.g. a strict day 2 machine stays strict whichever snapshot it is given. The recorded
+    // history can't be rewound past the jump to the snapshot, so it is thrown away. A machine
+    // that was recording starts again from the restored state, with the same limit.
+    pub fn restore(&mut self, snapshot: &Snapshot) {
+        let instruction_set = Arc::clone(&self.instruction_set);
+        let history = self.history.take();
+        *self = IntOpCodeComp::from(snapshot.clone());
+        self.instruction_set = instruction_set;
+        self.history = history.map(|history| history.restarted());
+    }
+
+    // Independent copy that continues from the current state, e.g. to try each branch of a search
+    pub fn fork(&self) -> IntOpCodeComp {
+        self.clone()
+    }
+}
+
+impl From<Snapshot> for IntOpCodeComp {
+    fn from(snapshot: Snapshot) -> IntOpCodeComp {
+        let mut comp = IntOpCodeComp::with_memory(snapshot.memory, &snapshot.input_values);
+        comp.output_values = snapshot.output_values;
+        comp.index = snapshot.index;
+        comp.relative_base = snapshot.relative_base;
+        comp.state = snapshot.state;
+        comp.instruction_set = snapshot.instruction_set;
+        comp
+    }
+}
+
+#[derive(Debug, PartialEq)]
+pub struct SnapshotError {
+    pub line: usize,
+    pub message: String,
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "snapshot line {}: {}", self.line, self.message)
+    }
+}
+
+impl Error for SnapshotError {}
+
+fn format_values<'a>(values: impl Iterator<Item = &'a i64>) -> String {
+    values.map(|v| v.to_string()).collect::<Vec<String>>().join(",")
+}
+
+// Text format, one field per line followed by one "address value" line per non-zero word:
+//   intopcode-snapshot 1
+//   memory <kind> <len> <limit or none>
+//   ip <index>
+//   relative_base <relative_base>
+//   state <running|waiting|halted>
+//   input <comma separated values>
+//   output <comma separated values>
+//   <address> <value>
+impl fmt::Display for Snapshot {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let limit = match self.memory.limit() {
+            Some(limit) => limit.to_string(),
+            None => String::from("none"),
+        };
+        let state = match self.state {
+            RunState::Running => "running",
+            RunState::WaitingOnInput => "waiting",
+            RunState::Halted => "halted",
+        };
+
+        writeln!(f, "{}", SNAPSHOT_HEADER)?;
+        writeln!(f, "memory {} {} {}", self.memory.kind(), self.memory.len(), limit)?;
+        writeln!(f, "ip {}", self.index)?;
+        writeln!(f, "relative_base {}", self.relative_base)?;
+        writeln!(f, "state {}", state)?;
+        writeln!(f, "input {}", format_values(self.input_values.iter()))?;
+        writeln!(f, "output {}", format_values(self.output_values.iter()))?;
+        for (address, value) in self.memory.cells() {
+            writeln!(f, "{} {}", address, value)?;
+        }
+        Ok(())
+    }
+}
+
+fn parse_number<T: FromStr>(line: usize, text: &str) -> Result<T, SnapshotError> {
+    text.trim().parse().map_err(|_| SnapshotError { line, message: format!("invalid number '{}'", text) })
+}
+
+fn parse_values(line: usize, text: &str) -> Result<VecDeque<i64>, SnapshotError> {
+    if text.is_empty() {
+        return Ok(VecDeque::new());
+    }
+    text.split(',').map(|v| parse_number(line, v)).collect()
+}
+
+impl FromStr for Snapshot {
+    type Err = SnapshotError;
+
+    fn from_str(text: &str) -> Result<Snapshot, SnapshotError> {
+        let mut lines = text.lines().enumerate().map(|(i, l)| (i + 1, l.trim()));
+
+        // The fixed fields come first, in order, each prefixed with its name
+        let mut field = |name: &str| -> Result<(usize, &str), SnapshotError> {
+            match lines.next() {
+                Some((line, l)) if l == name || l.starts_with(&format!("{} ", name)) => Ok((line, l[name.len()..].trim())),
+                Some((line, _)) => Err(SnapshotError { line, message: format!("expected '{}'", name) }),
+                None => Err(SnapshotError { line: 0, message: format!("missing '{}'", name) })
+            }
+        };
+
+        field(SNAPSHOT_HEADER)?;
+
+        let (line, memory) = field("memory")?;
+        let memory : Vec<&str> = memory.split_whitespace().collect();
+        if memory.len() != 3 {
+            return Err(SnapshotError { line, message: String::from("expected 'memory <kind> <len> <limit>'") });
+        }
+        let kind : MemoryKind = memory[0].parse().map_err(|message| SnapshotError { line, message })?;
+        let len : usize = parse_number(line, memory[1])?;
+        let limit = match memory[2] {
+            "none" => None,
+            limit => Some(parse_number(line, limit)?),
+        };
+        // dense memory allocates its whole length up front, so a made up length could take it all
+        let dense_limit = limit.unwrap_or(DEFAULT_MEMORY_LIMIT);
+        if kind == MemoryKind::Dense && len > dense_limit {
+            return Err(SnapshotError { line, message: format!("length {} exceeds the memory limit of {} words", len, dense_limit) });
+        }
+
+        let (line, index) = field("ip")?;
+        let index = parse_number(line, index)?;
+        let (line, relative_base) = field("relative_base")?;
+        let relative_base = parse_number(line, relative_base)?;
+        let (line, state) = field("state")?;
+        let state = match state {
+            "running" => RunState::Running,
+            "waiting" => RunState::WaitingOnInput,
+            "halted" => RunState::Halted,
+            _ => return Err(SnapshotError { line, message: format!("unknown state '{}'", state) })
+        };
+        let (line, input_values) = field("input")?;
+        let input_values = parse_values(line, input_values)?;
+        let (line, output_values) = field("output")?;
+        let output_values = parse_values(line, output_values)?;
+
+        let mut cells = kind.create(&[], limit).map_err(|error| SnapshotError { line: 2, message: error.to_string() })?;
+        for (line, l) in lines.filter(|(_, l)| !l.is_empty()) {
+            let mut words = l.split_whitespace();
+            let (address, value) = match (words.next(), words.next(), words.next()) {
+                (Some(address), Some(value), None) => (parse_number(line, address)?, parse_number(line, value)?),
+                _ => return Err(SnapshotError { line, message: String::from("expected '<address> <value>'") })
+            };
+            if address >= len {
+                return Err(SnapshotError { line, message: format!("address {} is past the memory length of {}", address, len) });
+            }
+            cells.write(address, value).map_err(|error| SnapshotError { line, message: format!("address {} exceeds the memory limit of {} words", error.address, error.limit) })?;
+        }
+        // Trailing zeros aren't listed, writing the last word back restores the full length
+        if len > cells.len() {
+            let last = cells.read(len - 1);
+            cells.write(len - 1, last).map_err(|error| SnapshotError { line: 2, message: format!("length {} exceeds the memory limit of {} words", len, error.limit) })?;
+        }
+
+        Ok(Snapshot { memory: cells, index, relative_base, state, input_values, output_values, instruction_set: InstructionSet::standard_shared() })
+    }
+}
+
+impl Snapshot {
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> Result<(), Box<dyn Error>> {
+        fs::write(path, self.to_string())?;
+        Ok(())
+    }
+
+    pub fn load<P: AsRef<Path>>(path: P) -> Result<Snapshot, Box<dyn Error>> {
+        Ok(fs::read_to_string(path)?.parse()?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::RunEvent;
+    use crate::intopcode_memory::PagedMemory;
+
+    #[test]
+    fn snapshot_restore_and_fork_test() {
+        let test = vec![3,13,1001,13,1,13,4,13,1105,1,0,99,0,0];  // forever adds 1 to every input and outputs it
+        let mut comp = IntOpCodeComp::new(&test, &VecDeque::from(vec![10]));
+        assert_eq!( comp.run_until_event(), Ok(RunEvent::Output(11)) );
+        let snapshot = comp.snapshot();
+
+        let mut fork = comp.fork();
+        fork.push_input(20);
+        assert_eq!( fork.run_until_event(), Ok(RunEvent::Output(21)) );
+        assert_eq!( comp.run_until_event(), Ok(RunEvent::InputNeeded) ); // the fork's input isn't shared
+
+        comp.push_input(30);
+        assert_eq!( comp.run_until_event(), Ok(RunEvent::Output(31)) );
+        comp.restore(&snapshot);
+        assert_eq!( comp.memory().read(13), 11 );
+        comp.push_input(40);
+        assert_eq!( comp.run_until_event(), Ok(RunEvent::Output(41)) );
+    }
+
+    #[test]
+    fn restore_keeps_instruction_set_test() {
+        let mut comp = IntOpCodeComp::new(&[1,0,0,0,99], &VecDeque::new());
+        comp.set_instruction_set(Arc::new(InstructionSet::for_day(2)));
+        let snapshot = comp.snapshot();
+        assert_eq!( IntOpCodeComp::from(snapshot.clone()).instruction_set().opcodes().count(), 3 );
+        comp.restore(&snapshot);
+        assert_eq!( comp.instruction_set().opcodes().count(), 3 );
+
+        // one read back from text has the standard set, the machine restored from it keeps its own
+        comp.restore(&snapshot.to_string().parse().unwrap());
+        assert_eq!( comp.instruction_set().opcodes().count(), 3 );
+        assert_eq!( IntOpCodeComp::from(snapshot.to_string().parse::<Snapshot>().unwrap()).instruction_set().opcodes().count(), 10 );
+    }
+
+    #[test]
+    fn restore_restarts_recording_test() {
+        let test = vec![1001,100,1,100,1008,100,3,101,1006,101,0,99];
+        let mut comp = IntOpCodeComp::new(&test, &VecDeque::new());
+        let snapshot = comp.snapshot();
+        comp.start_recording_with_limit(2);
+        comp.tick_to_completion().unwrap();
+        comp.restore(&snapshot);
+        assert_eq!( comp.history().map(|history| history.len()), Some(0) );
+        assert_eq!( comp.rewind(1), 0 );
+
+        // the recording goes on from the snapshot, keeping its limit
+        comp.tick_to_completion().unwrap();
+        assert_eq!( comp.history().map(|history| (history.len(), history.first_step())), Some((2, 8)) );
+        assert_eq!( comp.rewind(5), 2 );
+        assert_eq!( comp.memory().read(100), 3 );
+    }
+
+    #[test]
+    fn snapshot_serialize_test() {
+        let test = vec![109,5,3,1000000,104,7,99,0];
+        let mut comp = IntOpCodeComp::with_memory(Box::new(PagedMemory::new(&test, None).unwrap()), &VecDeque::from(vec![-3, 4]));
+        assert_eq!( comp.run_until_event(), Ok(RunEvent::Output(7)) );
+        let text = comp.snapshot().to_string();
+        assert!( text.starts_with("intopcode-snapshot 1\nmemory paged 1000001 none\nip 6\nrelative_base 5\nstate running\ninput 4\noutput \n") );
+        assert!( text.ends_with("\n1000000 -3\n") );
+
+        let mut restored = IntOpCodeComp::from(text.parse::<Snapshot>().unwrap());
+        assert_eq!( restored.snapshot().to_string(), text );
+        assert_eq!( restored.memory().kind(), MemoryKind::Paged );
+        assert_eq!( restored.run_until_event(), Ok(RunEvent::Halted) );
+
+        let path = std::env::temp_dir().join(format!("intopcode_snapshot_test_{}.txt", std::process::id()));
+        comp.snapshot().save(&path).unwrap();
+        assert_eq!( Snapshot::load(&path).unwrap().to_string(), text );
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!( "intopcode-snapshot 1\nmemory huge 1 none".parse::<Snapshot>().unwrap_err().line, 2 );
+        assert_eq!( "intopcode-snapshot 1\nmemory dense 8 2\nip 0\nrelative_base 0\nstate halted\ninput\noutput\n1 1".parse::<Snapshot>().unwrap_err().line, 2 );
+        assert_eq!( "intopcode-snapshot 1\nmemory dense 1000000000000 none".parse::<Snapshot>().unwrap_err(),
+            SnapshotError { line: 2, message: format!("length 1000000000000 exceeds the memory limit of {} words", DEFAULT_MEMORY_LIMIT) } );
+        assert_eq!( "intopcode-snapshot 1\nmemory dense 8 none\nip 0\nrelative_base 0\nstate halted\ninput\noutput\n1000000000000 1".parse::<Snapshot>().unwrap_err(),
+            SnapshotError { line: 8, message: String::from("address 1000000000000 is past the memory length of 8") } );
+    }
+}
@@ -0,0 +1,484 @@
+use std::error::Error;
+use std::fmt;
+
+use crate::{decode_opcode, opcode_parameter_count, RunState};
+use crate::intopcode_fast::FastIntOpCodeComp;
+use crate::intopcode_memory::DEFAULT_MEMORY_LIMIT;
+
+// Memory cell whose starting value is searched over, e.g. the noun at address 1 for day 2
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FreeVariable {
+    pub address: usize,
+    pub min: i64,
+    pub max: i64,
+}
+
+pub type GoalPredicate = Box<dyn Fn(&[i64]) -> bool + Sync>;
+
+// What has to hold once the program halts
+pub enum Goal {
+    Equals { address: usize, target: i64 },
+    // Called with the memory of the halted program. Only ever answered by brute force.
+    Predicate(GoalPredicate),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SolveMethod {
+    Symbolic,
+    BruteForce,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Solution {
+    // Every satisfying assignment, with values in the same order as the variables, sorted
+    pub assignments: Vec<Vec<i64>>,
+    pub method: SolveMethod,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SolveError {
+    // More assignments to try than fit in a usize
+    SearchSpaceTooLarge,
+}
+
+impl fmt::Display for SolveError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SolveError::SearchSpaceTooLarge => write!(f, "too many assignments to search"),
+        }
+    }
+}
+
+impl Error for SolveError {}
+
+fn range_size(variable: &FreeVariable) -> usize {
+    if variable.max < variable.min { 0 } else { (variable.max as i128 - variable.min as i128 + 1) as usize }
+}
+
+fn search_space(variables: &[FreeVariable]) -> Result<usize, SolveError> {
+    variables.iter().try_fold(1usize, |total, v| total.checked_mul(range_size(v)).ok_or(SolveError::SearchSpaceTooLarge))
+}
+
+// The index'th assignment, counting with the first variable as the most significant digit
+fn nth_assignment(variables: &[FreeVariable], mut index: usize) -> Vec<i64> {
+    let mut assignment = vec![0; variables.len()];
+    for (i, variable) in variables.iter().enumerate().rev() {
+        let size = range_size(variable);
+        assignment[i] = variable.min + (index % size) as i64;
+        index /= size;
+    }
+    assignment
+}
+
+// A value computed from the free variables
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Value {
+    // constant + sum of coefficients[i] * variable i
+    Exact { constant: i64, coefficients: Vec<i64> },
+    // Depends on the variables in a way that isn't tracked, but always lies in min..=max
+    Unknown { min: i64, max: i64 },
+}
+
+// Why symbolic execution gave up, in which case brute force takes over
+struct GiveUp;
+
+fn fits(value: i128) -> Result<i64, GiveUp> {
+    if value < i64::MIN as i128 || value > i64::MAX as i128 { Err(GiveUp) } else { Ok(value as i64) }
+}
+
+impl Value {
+    fn constant(value: i64, variable_count: usize) -> Value {
+        Value::Exact { constant: value, coefficients: vec![0; variable_count] }
+    }
+
+    fn as_constant(&self) -> Option<i64> {
+        match self {
+            Value::Exact { constant, coefficients } if coefficients.iter().all(|&c| c == 0) => Some(*constant),
+            _ => None
+        }
+    }
+
+    // Smallest and largest value over every assignment
+    fn bounds(&self, variables: &[FreeVariable]) -> (i128, i128) {
+        match self {
+            Value::Exact { constant, coefficients } => coefficients.iter().zip(variables).fold((*constant as i128, *constant as i128), |(min, max), (&c, v)| {
+                let (low, high) = (c as i128 * v.min as i128, c as i128 * v.max as i128);
+                (min + std::cmp::min(low, high), max + std::cmp::max(low, high))
+            }),
+            Value::Unknown { min, max } => (*min as i128, *max as i128),
+        }
+    }
+
+    // Unknown value with the given bounds, giving up if some assignment would overflow
+    fn unknown((min, max): (i128, i128)) -> Result<Value, GiveUp> {
+        Ok(Value::Unknown { min: fits(min)?, max: fits(max)? })
+    }
+
+    fn add(&self, other: &Value, variables: &[FreeVariable]) -> Result<Value, GiveUp> {
+        let (a_min, a_max) = self.bounds(variables);
+        let (b_min, b_max) = other.bounds(variables);
+        let bounds = (a_min + b_min, a_max + b_max);
+        match (self, other) {
+            (Value::Exact { constant: a, coefficients: a_coefficients }, Value::Exact { constant: b, coefficients: b_coefficients }) => {
+                // Checking the bounds makes sure the concrete add can't overflow for any assignment
+                let sum = Value::Exact {
+                    constant: fits(*a as i128 + *b as i128)?,
+                    coefficients: a_coefficients.iter().zip(b_coefficients).map(|(&x, &y)| fits(x as i128 + y as i128)).collect::<Result<_, _>>()?,
+                };
+                fits(bounds.0)?;
+                fits(bounds.1)?;
+                Ok(sum)
+            },
+            _ => Value::unknown(bounds),
+        }
+    }
+
+    fn mul(&self, other: &Value, variables: &[FreeVariable]) -> Result<Value, GiveUp> {
+        let (a_min, a_max) = self.bounds(variables);
+        let (b_min, b_max) = other.bounds(variables);
+        let corners = [a_min * b_min, a_min * b_max, a_max * b_min, a_max * b_max];
+        let bounds = (*corners.iter().min().unwrap(), *corners.iter().max().unwrap());
+
+        let (scaled, factor) = match (self.as_constant(), other.as_constant()) {
+            (_, Some(factor)) => (self, factor),
+            (Some(factor), _) => (other, factor),
+            _ => return Value::unknown(bounds),
+        };
+        match scaled {
+            Value::Exact { constant, coefficients } => {
+                let product = Value::Exact {
+                    constant: fits(*constant as i128 * factor as i128)?,
+                    coefficients: coefficients.iter().map(|&c| fits(c as i128 * factor as i128)).collect::<Result<_, _>>()?,
+                };
+                fits(bounds.0)?;
+                fits(bounds.1)?;
+                Ok(product)
+            },
+            Value::Unknown { .. } => Value::unknown(bounds),
+        }
+    }
+
+    // Result of opcodes 7 and 8, decided from the bounds when the values aren't both constant
+    fn compare(&self, other: &Value, less_than: bool, variables: &[FreeVariable]) -> Value {
+        let count = variables.len();
+        if let (Some(a), Some(b)) = (self.as_constant(), other.as_constant()) {
+            let result = if less_than { a < b } else { a == b };
+            return Value::constant(result as i64, count);
+        }
+        let (a_min, a_max) = self.bounds(variables);
+        let (b_min, b_max) = other.bounds(variables);
+        let result = if less_than {
+            if a_max < b_min { Some(1) } else if a_min >= b_max { Some(0) } else { None }
+        }
+        else if a_max < b_min || a_min > b_max {
+            Some(0)
+        }
+        else if matches!(self, Value::Exact { .. }) && self == other {
+            Some(1)
+        }
+        else {
+            None
+        };
+        result.map_or(Value::Unknown { min: 0, max: 1 }, |r| Value::constant(r, count))
+    }
+}
+
+// Runs the program with the variables left as unknowns. Only works while every jump, address
+// and instruction the program uses is the same for all assignments.
+struct SymbolicComp<'a> {
+    memory: Vec<Value>,
+    variables: &'a [FreeVariable],
+    index: usize,
+    relative_base: i64,
+}
+
+// Symbolic runs longer than this are left to brute force
+const SYMBOLIC_STEP_LIMIT: usize = 1_000_000;
+
+// Pointers with more possible targets than this are left to brute force
+const SYMBOLIC_POINTER_SPREAD: i128 = 1 << 16;
+
+impl<'a> SymbolicComp<'a> {
+    fn new(intopcodes: &[i64], variables: &'a [FreeVariable]) -> SymbolicComp<'a> {
+        let mut memory : Vec<Value> = intopcodes.iter().map(|&v| Value::constant(v, variables.len())).collect();
+        for (i, variable) in variables.iter().enumerate() {
+            if variable.address >= memory.len() {
+                memory.resize(variable.address + 1, Value::constant(0, variables.len()));
+            }
+            let mut coefficients = vec![0; variables.len()];
+            coefficients[i] = 1;
+            memory[variable.address] = Value::Exact { constant: 0, coefficients };
+        }
+        SymbolicComp { memory, variables, index: 0, relative_base: 0 }
+    }
+
+    fn read(&self, address: usize) -> Value {
+        self.memory.get(address).cloned().unwrap_or_else(|| Value::constant(0, self.variables.len()))
+    }
+
+    fn read_constant(&self, address: usize) -> Result<i64, GiveUp> {
+        self.read(address).as_constant().ok_or(GiveUp)
+    }
+
+    fn write(&mut self, address: usize, value: Value) -> Result<(), GiveUp> {
+        if address >= self.memory.len() {
+            if address >= DEFAULT_MEMORY_LIMIT {
+                return Err(GiveUp);
+            }
+            self.memory.resize(address + 1, Value::constant(0, self.variables.len()));
+        }
+        self.memory[address] = value;
+        Ok(())
+    }
+
+    // Reads through a pointer that may depend on the variables, as long as it can never be negative
+    fn read_through(&self, pointer: &Value) -> Result<Value, GiveUp> {
+        if let Some(address) = pointer.as_constant() {
+            return if address < 0 { Err(GiveUp) } else { Ok(self.read(address as usize)) };
+        }
+        let (min, max) = pointer.bounds(self.variables);
+        if min < 0 || max - min > SYMBOLIC_POINTER_SPREAD {
+            return Err(GiveUp);
+        }
+        let (mut low, mut high) = (i128::MAX, i128::MIN);
+        for address in min..=max {
+            let (cell_min, cell_max) = self.read(address as usize).bounds(self.variables);
+            low = std::cmp::min(low, cell_min);
+            high = std::cmp::max(high, cell_max);
+        }
+        Value::unknown((low, high))
+    }
+
+    fn param_value(&self, mode: i64, parameter: usize) -> Result<Value, GiveUp> {
+        let word = self.read(self.index + parameter);
+        match mode {
+            0 => self.read_through(&word),
+            1 => Ok(word),
+            2 => self.read_through(&Value::constant(self.relative_base, self.variables.len()).add(&word, self.variables)?),
+            _ => Err(GiveUp)
+        }
+    }
+
+    fn param_address(&self, mode: i64, parameter: usize) -> Result<usize, GiveUp> {
+        let word = self.read_constant(self.index + parameter)?;
+        let address = match mode {
+            0 => word,
+            2 => self.relative_base.checked_add(word).ok_or(GiveUp)?,
+            _ => return Err(GiveUp)
+        };
+        if address < 0 { Err(GiveUp) } else { Ok(address as usize) }
+    }
+
+    // Runs to the halt, returning the memory it ends with
+    fn run(mut self) -> Result<Vec<Value>, GiveUp> {
+        for _ in 0..SYMBOLIC_STEP_LIMIT {
+            let opcode = self.read_constant(self.index)?;
+            let (two_digit_opcode, modes) = decode_opcode(opcode);
+            opcode_parameter_count(two_digit_opcode).ok_or(GiveUp)?;
+            match two_digit_opcode {
+                1 | 2 | 7 | 8 => {
+                    let a = self.param_value(modes[0], 1)?;
+                    let b = self.param_value(modes[1], 2)?;
+                    let address = self.param_address(modes[2], 3)?;
+                    let result = match two_digit_opcode {
+                        1 => a.add(&b, self.variables)?,
+                        2 => a.mul(&b, self.variables)?,
+                        _ => a.compare(&b, two_digit_opcode == 7, self.variables),
+                    };
+                    self.write(address, result)?;
+                    self.index += 4;
+                },
+                // the brute force only counts halted runs, so a program wanting input never matches
+                3 => return Err(GiveUp),
+                4 => {
+                    self.param_value(modes[0], 1)?;
+                    self.index += 2;
+                },
+                5 | 6 => {
+                    let condition = self.param_value(modes[0], 1)?.as_constant().ok_or(GiveUp)?;
+                    if (condition != 0) == (two_digit_opcode == 5) {
+                        let target = self.param_value(modes[1], 2)?.as_constant().ok_or(GiveUp)?;
+                        if target < 0 {
+                            return Err(GiveUp);
+                        }
+                        self.index = target as usize;
+                    }
+                    else {
+                        self.index += 3;
+                    }
+                },
+                9 => {
+                    let offset = self.param_value(modes[0], 1)?.as_constant().ok_or(GiveUp)?;
+                    self.relative_base = self.relative_base.checked_add(offset).ok_or(GiveUp)?;
+                    self.index += 2;
+                },
+                _ => return Ok(self.memory),
+            }
+        }
+        Err(GiveUp)
+    }
+}
+
+// Every assignment where the linear value equals target, without trying them all: the last
+// variable the value depends on is solved for directly.
+fn solve_linear(variables: &[FreeVariable], value: &Value, target: i64) -> Result<Vec<Vec<i64>>, SolveError> {
+    let (constant, coefficients) = match value {
+        Value::Exact { constant, coefficients } => (*constant, coefficients),
+        Value::Unknown { .. } => unreachable!(),
+    };
+    let solved = match coefficients.iter().rposition(|&c| c != 0) {
+        Some(solved) => solved,
+        None => {
+            if constant != target {
+                return Ok(Vec::new());
+            }
+            let count = search_space(variables)?;
+            return Ok((0..count).map(|index| nth_assignment(variables, index)).collect());
+        }
+    };
+
+    let others : Vec<FreeVariable> = variables.iter().enumerate().filter(|&(i, _)| i != solved).map(|(_, v)| *v).collect();
+    let mut assignments = Vec::new();
+    for index in 0..search_space(&others)? {
+        let mut assignment = nth_assignment(&others, index);
+        let mut remainder = target as i128 - constant as i128;
+        for (j, &x) in assignment.iter().enumerate() {
+            let i = if j < solved { j } else { j + 1 };
+            remainder -= coefficients[i] as i128 * x as i128;
+        }
+        let coefficient = coefficients[solved] as i128;
+        if remainder % coefficient != 0 {
+            continue;
+        }
+        let x = remainder / coefficient;
+        if x >= variables[solved].min as i128 && x <= variables[solved].max as i128 {
+            assignment.insert(solved, x as i64);
+            assignments.push(assignment);
+        }
+    }
+    assignments.sort();
+    Ok(assignments)
+}
+
+// Solves memory[address] == target by running the program once with the variables unknown.
+// Returns None when the program does something that depends on the variables in a way that
+// can't be followed, e.g. jumping on one of them.
+pub fn solve_symbolic(intopcodes: &[i64], variables: &[FreeVariable], address: usize, target: i64) -> Result<Option<Vec<Vec<i64>>>, SolveError> {
+    let memory = match SymbolicComp::new(intopcodes, variables).run() {
+        Ok(memory) => memory,
+        Err(GiveUp) => return Ok(None),
+    };
+    match memory.get(address).cloned().unwrap_or_else(|| Value::constant(0, variables.len())) {
+        value @ Value::Exact { .. } => solve_linear(variables, &value, target).map(Some),
+        Value::Unknown { .. } => Ok(None),
+    }
+}
+
+// Runs the program once for every assignment, split across all CPU cores. Assignments that make
+// the program crash or wait for input don't count.
+pub fn solve_brute_force(intopcodes: &[i64], variables: &[FreeVariable], goal: &Goal) -> Result<Vec<Vec<i64>>, SolveError> {
+    let count = search_space(variables)?;
+    if count == 0 {
+        return Ok(Vec::new());
+    }
+    let base_comp = FastIntOpCodeComp::new(intopcodes, &Default::default());
+    let thread_count = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1).min(count);
+    let chunk_size = count.div_ceil(thread_count);
+
+    let results : Vec<Vec<Vec<i64>>> = std::thread::scope(|scope| {
+        let handles : Vec<_> = (0..thread_count).map(|t| {
+            let base_comp = &base_comp;
+            scope.spawn(move || {
+                let mut found = Vec::new();
+                for index in t * chunk_size..std::cmp::min((t + 1) * chunk_size, count) {
+                    let assignment = nth_assignment(variables, index);
+                    // every attempt starts from a copy of the loaded program, keeping the
+                    // instructions it already decoded that the variables don't touch
+                    let mut comp = base_comp.clone();
+                    let attempt = variables.iter().zip(&assignment).try_for_each(|(v, &x)| comp.write_memory(v.address, x))
+                        .and_then(|_| comp.tick_to_completion().map(|_| ()));
+                    if attempt.is_err() || comp.state() != RunState::Halted {
+                        continue;
+                    }
+                    let satisfied = match goal {
+                        Goal::Equals { address, target } => comp.memory().get(*address).copied().unwrap_or(0) == *target,
+                        Goal::Predicate(predicate) => predicate(comp.memory()),
+                    };
+                    if satisfied {
+                        found.push(assignment);
+                    }
+                }
+                found
+            })
+        }).collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    });
+
+    // chunks are in order, so the assignments already are too
+    Ok(results.into_iter().flatten().collect())
+}
+
+// Finds every assignment of the variables that makes the goal hold once the program halts,
+// symbolically when possible and by brute force otherwise
+pub fn solve(intopcodes: &[i64], variables: &[FreeVariable], goal: &Goal) -> Result<Solution, SolveError> {
+    if let Goal::Equals { address, target } = goal {
+        if let Some(assignments) = solve_symbolic(intopcodes, variables, *address, *target)? {
+            return Ok(Solution { assignments, method: SolveMethod::Symbolic });
+        }
+    }
+    Ok(Solution { assignments: solve_brute_force(intopcodes, variables, goal)?, method: SolveMethod::BruteForce })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parse_intopcodes;
+    use crate::intopcode_asm::assemble;
+
+    #[test]
+    fn symbolic_solve_test() {
+        // [0] = 3 * x + y
+        let program = assemble("
+                    mul [x], #3, [0]
+                    add [0], [y], [0]
+                    hlt
+            x:      .data 0
+            y:      .data 0
+        ").unwrap();
+        let variables = [FreeVariable { address: 9, min: 0, max: 10 }, FreeVariable { address: 10, min: 0, max: 10 }];
+        let goal = Goal::Equals { address: 0, target: 20 };
+        let expected = vec![vec![4, 8], vec![5, 5], vec![6, 2]];
+        assert_eq!( solve(&program, &variables, &goal), Ok(Solution { assignments: expected.clone(), method: SolveMethod::Symbolic }) );
+        assert_eq!( solve_brute_force(&program, &variables, &goal), Ok(expected) );
+
+        // day 2 reads through the noun and verb before overwriting the result, which the symbolic run can follow
+        let intopcodes = parse_intopcodes(&std::fs::read_to_string("aocday2.txt").unwrap()).unwrap();
+        let variables = [FreeVariable { address: 1, min: 0, max: 99 }, FreeVariable { address: 2, min: 0, max: 99 }];
+        let goal = Goal::Equals { address: 0, target: 19690720 };
+        assert_eq!( solve(&intopcodes, &variables, &goal), Ok(Solution { assignments: vec![vec![20, 3]], method: SolveMethod::Symbolic }) );
+        assert_eq!( solve_brute_force(&intopcodes, &variables, &goal), Ok(vec![vec![20, 3]]) );
+    }
+
+    #[test]
+    fn brute_force_solve_test() {
+        // [0] = 7 if x is 0, x * x otherwise
+        let program = assemble("
+                    jz [x], #zero
+                    mul [x], [x], [0]
+                    hlt
+            zero:   add #7, #0, [0]
+                    hlt
+            x:      .data 0
+        ").unwrap();
+        let variables = [FreeVariable { address: 13, min: 0, max: 10 }];
+        let solution = solve(&program, &variables, &Goal::Equals { address: 0, target: 49 }).unwrap();
+        assert_eq!( solution, Solution { assignments: vec![vec![7]], method: SolveMethod::BruteForce } );
+
+        let goal = Goal::Predicate(Box::new(|memory| memory[0] > 5));
+        let assignments = solve(&program, &variables, &goal).unwrap().assignments;
+        assert_eq!( assignments, vec![vec![0], vec![3], vec![4], vec![5], vec![6], vec![7], vec![8], vec![9], vec![10]] );
+
+        // an empty range has nothing to satisfy it
+        assert_eq!( solve_brute_force(&program, &[FreeVariable { address: 13, min: 1, max: 0 }], &goal), Ok(vec![]) );
+    }
+}
@@ -0,0 +1,226 @@
+use std::collections::BTreeMap;
+use std::collections::HashMap;
+use std::io;
+use std::io::Write;
+
+use crate::{decode_opcode, IntOpCodeComp, IntOpCodeError, ParameterMode, RunState};
+use crate::intopcode_isa::ParameterKind;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MemoryWrite {
+    pub address: usize,
+    pub old_value: i64,
+    pub new_value: i64,
+}
+
+// Everything one executed instruction did
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TraceRecord {
+    pub ip: usize,
+    pub opcode: i64,
+    // Raw parameter words, before their modes are applied
+    pub operands: Vec<i64>,
+    pub write: Option<MemoryWrite>,
+    pub output: Option<i64>,
+    pub next_ip: usize,
+    pub relative_base: i64,
+}
+
+impl IntOpCodeComp {
+    // Address the instruction at ip is going to write to, if it writes at all
+    fn pending_write_address(&self, opcode: i64) -> Option<usize> {
+        let (two_digit_opcode, modes) = decode_opcode(opcode);
+        let parameters = &self.instruction_set.get(two_digit_opcode)?.parameters;
+        let parameter = parameters.iter().position(|&kind| kind == ParameterKind::Write)? + 1;
+        let mode = ParameterMode::from_index(modes[parameter - 1], self.relative_base)?;
+        mode.get_index_for_write(self.memory.as_ref(), self.read_memory(self.index + parameter)).ok()
+    }
+
+    // Executes one instruction like tick and describes what it did. Returns None when nothing
+    // ran because the program has halted or is waiting on input.
+    pub fn traced_tick(&mut self) -> Result<Option<TraceRecord>, IntOpCodeError> {
+        if self.state == RunState::Halted {
+            return Ok(None);
+        }
+
+        let ip = self.index;
+        let opcode = self.read_memory(ip);
+        let param_count = self.instruction_set.parameter_count(decode_opcode(opcode).0).unwrap_or(0);
+        let operands = (1..=param_count).map(|n| self.read_memory(ip + n)).collect();
+        let write_address = self.pending_write_address(opcode);
+        let old_value = write_address.map(|address| self.read_memory(address));
+        let output_count = self.output_values.len();
+
+        self.tick()?;
+        if self.state == RunState::WaitingOnInput {
+            return Ok(None);
+        }
+
+        let write = match (write_address, old_value) {
+            (Some(address), Some(old_value)) => Some(MemoryWrite { address, old_value, new_value: self.read_memory(address) }),
+            _ => None
+        };
+        let output = if self.output_values.len() > output_count { self.output_values.back().copied() } else { None };
+        Ok(Some(TraceRecord { ip, opcode, operands, write, output, next_ip: self.index, relative_base: self.relative_base }))
+    }
+}
+
+// Gets told about every instruction run through run_observed
+pub trait ExecutionObserver {
+    fn observe(&mut self, record: &TraceRecord, comp: &IntOpCodeComp);
+}
+
+// Runs until the program halts or needs input, handing every instruction to each observer in order
+pub fn run_observed(comp: &mut IntOpCodeComp, observers: &mut [&mut dyn ExecutionObserver]) -> Result<RunState, IntOpCodeError> {
+    while let Some(record) = comp.traced_tick()? {
+        for observer in observers.iter_mut() {
+            observer.observe(&record, comp);
+        }
+    }
+    Ok(comp.state)
+}
+
+fn json_list(values: &[i64]) -> String {
+    let values : Vec<String> = values.iter().map(|v| v.to_string()).collect();
+    format!("[{}]", values.join(","))
+}
+
+fn json_option(value: Option<i64>) -> String {
+    value.map_or(String::from("null"), |v| v.to_string())
+}
+
+// Writes one JSON object per instruction, e.g.
+// {"step":1,"ip":0,"opcode":1101,"operands":[1,2,5],"write":{"address":5,"old":0,"new":3},"output":null,"next_ip":4,"relative_base":0}
+pub struct JsonTraceWriter<W: Write> {
+    writer: W,
+    steps: u64,
+    error: Option<io::Error>,
+}
+
+impl<W: Write> JsonTraceWriter<W> {
+    pub fn new(writer: W) -> JsonTraceWriter<W> {
+        JsonTraceWriter { writer, steps: 0, error: None }
+    }
+
+    // Flushes the trace, returning the first error hit while writing it
+    pub fn finish(mut self) -> io::Result<W> {
+        if let Some(error) = self.error.take() {
+            return Err(error);
+        }
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+impl<W: Write> ExecutionObserver for JsonTraceWriter<W> {
+    fn observe(&mut self, record: &TraceRecord, _comp: &IntOpCodeComp) {
+        if self.error.is_some() {
+            return;
+        }
+        self.steps += 1;
+        let write = match record.write {
+            Some(write) => format!("{{\"address\":{},\"old\":{},\"new\":{}}}", write.address, write.old_value, write.new_value),
+            None => String::from("null"),
+        };
+        let result = writeln!(self.writer, "{{\"step\":{},\"ip\":{},\"opcode\":{},\"operands\":{},\"write\":{},\"output\":{},\"next_ip\":{},\"relative_base\":{}}}",
+            self.steps, record.ip, record.opcode, json_list(&record.operands), write, json_option(record.output), record.next_ip, record.relative_base);
+        if let Err(error) = result {
+            self.error = Some(error);
+        }
+    }
+}
+
+// Counts what a program spends its time on
+#[derive(Debug, Clone, Default)]
+pub struct Profiler {
+    pub steps: u64,
+    // Keyed by the two digit opcode, so all modes of an instruction count together
+    pub opcode_counts: BTreeMap<i64, u64>,
+    pub address_counts: HashMap<usize, u64>,
+    // Highest memory length and number of allocated words seen
+    pub memory_high_water: usize,
+    pub cells_high_water: usize,
+}
+
+impl Profiler {
+    pub fn new() -> Profiler {
+        Profiler::default()
+    }
+
+    // The most executed instruction addresses, ties broken by address
+    pub fn hottest_addresses(&self, count: usize) -> Vec<(usize, u64)> {
+        let mut addresses : Vec<(usize, u64)> = self.address_counts.iter().map(|(&a, &c)| (a, c)).collect();
+        addresses.sort_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0)));
+        addresses.truncate(count);
+        addresses
+    }
+
+    pub fn report(&self, hottest: usize) -> String {
+        let mut lines = vec![format!("instructions executed: {}", self.steps), String::from("per opcode:")];
+        for (opcode, count) in &self.opcode_counts {
+            lines.push(format!("  {:>3} {:>12} {:>6.2}%", opcode, count, *count as f64 * 100.0 / std::cmp::max(self.steps, 1) as f64));
+        }
+        lines.push(format!("hottest {} addresses:", hottest));
+        for (address, count) in self.hottest_addresses(hottest) {
+            lines.push(format!("  {:>8} {:>12}", address, count));
+        }
+        lines.push(format!("memory high-water mark: {} words ({} allocated)", self.memory_high_water, self.cells_high_water));
+        lines.join("\n")
+    }
+}
+
+impl ExecutionObserver for Profiler {
+    fn observe(&mut self, record: &TraceRecord, comp: &IntOpCodeComp) {
+        self.steps += 1;
+        *self.opcode_counts.entry(decode_opcode(record.opcode).0).or_insert(0) += 1;
+        *self.address_counts.entry(record.ip).or_insert(0) += 1;
+        self.memory_high_water = std::cmp::max(self.memory_high_water, comp.memory().len());
+        self.cells_high_water = std::cmp::max(self.cells_high_water, comp.memory().cells_used());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::VecDeque;
+
+    #[test]
+    fn traced_tick_test() {
+        let test = vec![109,11,203,0,21001,11,1,1,204,1,99,0,0];  // reads into rb+0, adds 1 into rb+1 and outputs it
+        let mut comp = IntOpCodeComp::new(&test, &VecDeque::new());
+        assert_eq!( comp.traced_tick(), Ok(Some(TraceRecord { ip: 0, opcode: 109, operands: vec![11], write: None, output: None, next_ip: 2, relative_base: 11 })) );
+        assert_eq!( comp.traced_tick(), Ok(None) ); // waiting on input
+        comp.push_input(41);
+        assert_eq!( comp.traced_tick(), Ok(Some(TraceRecord { ip: 2, opcode: 203, operands: vec![0], write: Some(MemoryWrite { address: 11, old_value: 0, new_value: 41 }), output: None, next_ip: 4, relative_base: 11 })) );
+        assert_eq!( comp.traced_tick().unwrap().unwrap().write, Some(MemoryWrite { address: 12, old_value: 0, new_value: 42 }) );
+        assert_eq!( comp.traced_tick().unwrap().unwrap().output, Some(42) );
+        assert_eq!( comp.traced_tick().unwrap().unwrap().next_ip, 13 );
+        assert_eq!( comp.traced_tick(), Ok(None) );
+    }
+
+    #[test]
+    fn json_trace_and_profiler_test() {
+        let test = vec![1101,2,3,5,99,0];
+        let mut comp = IntOpCodeComp::new(&test, &VecDeque::new());
+        let mut trace = JsonTraceWriter::new(Vec::new());
+        let mut profiler = Profiler::new();
+        assert_eq!( run_observed(&mut comp, &mut [&mut trace, &mut profiler]).unwrap(), RunState::Halted );
+
+        let trace = String::from_utf8(trace.finish().unwrap()).unwrap();
+        assert_eq!( trace, "\
+{\"step\":1,\"ip\":0,\"opcode\":1101,\"operands\":[2,3,5],\"write\":{\"address\":5,\"old\":0,\"new\":5},\"output\":null,\"next_ip\":4,\"relative_base\":0}
+{\"step\":2,\"ip\":4,\"opcode\":99,\"operands\":[],\"write\":null,\"output\":null,\"next_ip\":6,\"relative_base\":0}
+" );
+
+        // count down from 3 to 0
+        let test = vec![1001,9,-1,9,1005,9,0,99,0,3];
+        let mut comp = IntOpCodeComp::new(&test, &VecDeque::new());
+        let mut profiler = Profiler::new();
+        run_observed(&mut comp, &mut [&mut profiler]).unwrap();
+        assert_eq!( profiler.steps, 7 );
+        assert_eq!( profiler.opcode_counts, vec![(1, 3), (5, 3), (99, 1)].into_iter().collect() );
+        assert_eq!( profiler.hottest_addresses(2), vec![(0, 3), (4, 3)] );
+        assert_eq!( profiler.memory_high_water, 10 );
+        assert!( profiler.report(2).contains("memory high-water mark: 10 words") );
+    }
+}
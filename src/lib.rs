@@ -1,108 +1,563 @@
 extern crate num;
 extern crate nalgebra as na;
 
-use permutohedron::LexicalPermutation;
 use std::collections::HashMap;
 use std::collections::HashSet;
 use std::collections::VecDeque;
 use std::convert::TryInto;
 use std::convert::TryFrom;
 use std::error::Error;
+use std::fmt;
 use std::fs;
+use std::sync::Arc;
 use na::Vector2;
 use na::Vector3;
 use crate::num::Integer;
 
+pub mod aoc_bench;
+pub mod aoc_output;
+pub mod aoc_registry;
+pub mod aoc_regression;
+pub mod intopcode_asm;
+pub mod intopcode_budget;
+pub mod intopcode_cfg;
+pub mod intopcode_conformance;
+pub mod intopcode_console;
+pub mod intopcode_debugger;
+pub mod intopcode_fast;
+pub mod intopcode_fuzz;
+pub mod intopcode_history;
+pub mod intopcode_io;
+pub mod intopcode_isa;
+pub mod intopcode_memory;
+pub mod intopcode_network;
+pub mod intopcode_selfmod;
+pub mod intopcode_snapshot;
+pub mod intopcode_solver;
+pub mod intopcode_trace;
+
+use aoc_output::OutputFormat;
+use aoc_registry::{Answer, Details, PuzzleResult, Registry, Solver};
+use intopcode_io::{ChunkedOutput, InputDevice, OutputDevice};
+use intopcode_isa::InstructionSet;
+use intopcode_memory::{DenseMemory, IntOpCodeMemory, DEFAULT_MEMORY_LIMIT};
+use intopcode_network::Network;
+
+pub enum Command {
+    Puzzle(u32, Option<u32>, OutputFormat), // solve a day's puzzle, both parts unless --part is given
+    AllPuzzles(OutputFormat),   // solve every puzzle, reading each day's input from aocday<N>.txt in a directory
+    Regression(String, Vec<u32>),   // check every puzzle against the answers file, recording the answers for the given days first
+    Benchmark(usize, Option<String>, Vec<u32>), // time parsing and each part of the given days (all if none) over a number of iterations, adding the run to the history file
+    Disassemble,    // print an intopcode program as assembly
+    Assemble,       // turn assembly back into a comma separated intopcode program
+    Ascii(Option<String>),  // play a program that talks in text, replaying the script file first if given
+    Conformance,    // run every test case file in a directory
+    ControlFlowGraph,   // print the control flow graph of an intopcode program as Graphviz DOT
+    Run(intopcode_budget::RunBudget, Option<u32>, Vec<i64>), // run a program, stopping on loops or when over the --steps/--seconds budget, --strict N only allows opcodes from up to day N
+    Debug(Vec<i64>),    // step through an intopcode program, starting with the given input values
+    Trace(String, Vec<i64>),    // write every executed instruction to a JSON lines file
+    Profile(Vec<i64>),  // count executed instructions per opcode and address
+    SelfModification(Vec<i64>), // report writes a program makes to its own instructions
+}
+
 pub struct Config {
-    pub aoc_day: u32,
+    pub command: Command,
     pub input_filename: String
 }
 
+const USAGE: &str = "\
+usage:
+  --day <n> [--part <p>] [--format f] <input>
+                                      solve a puzzle, both parts unless one is given
+  all [directory] [--format f]        solve every puzzle, reading aocday<n>.txt files (default .)
+  regression [answers] [--inputs directory] [--record <day>...|all]
+                                      check every puzzle against the known answers (default answers.txt)
+  bench [--iterations n] [--history file|--no-history] [--inputs directory] [day...]
+                                      time every puzzle (default 10 iterations, bench_history.jsonl)
+  disasm | asm | cfg <file>
+  run <program> [--steps n] [--seconds s] [--strict day] [inputs...]
+  debug | profile | selfmod <program> [inputs...]
+  trace <program> <trace file> [inputs...]
+  ascii <program> [script]
+  conformance <directory>
+an input or program of - is read from stdin
+answers are printed as text, or one record per part with --format json or csv";
+
 impl Config {
-    pub fn new(args: &[String]) -> Result<Config, &'static str> {
-        if args.len() < 3 {
-            return Err("not enough arguments");
-        }
-        let aoc_day = args[1].clone().parse().unwrap();
+    pub fn new(args: &[String]) -> Result<Config, String> {
+        match args.get(1).map(String::as_str) {
+            // --day, --part and --format can come in any order, before or after the input
+            Some(flag) if flag.starts_with("--") => return Config::puzzle(&args[1..]),
+            Some("regression") => return Config::regression(&args[2..]),
+            Some("bench") => return Config::benchmark(&args[2..]),
+            Some("all") => return Config::all_puzzles(&args[2..]),
+            Some(_) if args.len() >= 3 => {},
+            _ => return Err(format!("not enough arguments\n{}", USAGE)),
+        }
+        let command = match args[1].as_str() {
+            "disasm" => Command::Disassemble,
+            "asm" => Command::Assemble,
+            "cfg" => Command::ControlFlowGraph,
+            "conformance" => Command::Conformance,
+            "ascii" => Command::Ascii(args.get(3).cloned()),
+            "run" => {
+                let mut budget = intopcode_budget::RunBudget { detect_loops: true, ..intopcode_budget::RunBudget::default() };
+                let mut input_values = Vec::new();
+                let mut strict_day = None;
+                let mut rest = args[3..].iter();
+                while let Some(arg) = rest.next() {
+                    match arg.as_str() {
+                        "--steps" => {
+                            let steps = rest.next().and_then(|a| a.parse().ok()).ok_or("--steps needs a number of steps")?;
+                            budget.max_steps = Some(steps);
+                        },
+                        "--seconds" => {
+                            // try_from_secs_f64 turns away negative, infinite, NaN and too large values
+                            let duration = rest.next().and_then(|a| a.parse().ok()).and_then(|s| std::time::Duration::try_from_secs_f64(s).ok());
+                            budget.max_duration = Some(duration.ok_or("--seconds needs a number of seconds")?);
+                        },
+                        "--strict" => {
+                            strict_day = Some(rest.next().and_then(|a| a.parse().ok()).ok_or("--strict needs a day number")?);
+                        },
+                        _ => input_values.extend(parse_input_values(std::slice::from_ref(arg), "run")?),
+                    }
+                }
+                Command::Run(budget, strict_day, input_values)
+            },
+            "debug" => {
+                Command::Debug(parse_input_values(&args[3..], "debug")?)
+            },
+            "trace" => {
+                if args.len() < 4 {
+                    return Err(String::from("trace needs a program and a trace file"));
+                }
+                Command::Trace(args[3].clone(), parse_input_values(&args[4..], "trace")?)
+            },
+            "profile" => {
+                Command::Profile(parse_input_values(&args[3..], "profile")?)
+            },
+            "selfmod" => {
+                Command::SelfModification(parse_input_values(&args[3..], "selfmod")?)
+            },
+            _ if args[2..].iter().any(|a| a == "--day") => return Config::puzzle(&args[1..]),
+            other if other.parse::<u32>().is_ok() => {
+                return Err(format!("puzzles are picked with --day and --part now, not '{}'\n{}", other, USAGE));
+            },
+            other => return Err(format!("unknown command '{}'\n{}", other, USAGE)),
+        };
         let input_filename = args[2].clone();
     
-        Ok(Config { aoc_day, input_filename })
+        Ok(Config { command, input_filename })
+    }
+
+    // [answers file] [--inputs <directory>] [--record <day>...|all]
+    fn regression(args: &[String]) -> Result<Config, String> {
+        let (mut answers_filename, mut input_directory, mut record) = (None, String::from("."), Vec::new());
+        let mut rest = args.iter().peekable();
+        while let Some(arg) = rest.next() {
+            match arg.as_str() {
+                "--inputs" => input_directory = rest.next().ok_or("--inputs needs a directory")?.clone(),
+                "--record" => {
+                    if rest.next_if(|a| *a == "all").is_some() {
+                        record.extend(Registry::all_days().days());
+                    }
+                    while let Some(day) = rest.next_if(|a| !a.starts_with("--")) {
+                        record.push(day.parse().map_err(|_| format!("--record needs day numbers or all, not '{}'", day))?);
+                    }
+                    if record.is_empty() {
+                        return Err(String::from("--record needs day numbers or all"));
+                    }
+                },
+                filename if answers_filename.is_none() => answers_filename = Some(filename.to_string()),
+                extra => return Err(format!("unexpected argument '{}'", extra)),
+            }
+        }
+        let input_filename = answers_filename.unwrap_or_else(|| String::from("answers.txt"));
+        Ok(Config { command: Command::Regression(input_directory, record), input_filename })
+    }
+
+    // [--iterations <n>] [--history <file>|--no-history] [--inputs <directory>] [day...]
+    fn benchmark(args: &[String]) -> Result<Config, String> {
+        let (mut iterations, mut history, mut input_directory, mut days) = (10, Some(String::from("bench_history.jsonl")), String::from("."), Vec::new());
+        let registry = Registry::all_days();
+        let mut rest = args.iter();
+        while let Some(arg) = rest.next() {
+            match arg.as_str() {
+                "--iterations" => iterations = rest.next().and_then(|a| a.parse().ok()).filter(|&n| n > 0).ok_or("--iterations needs a positive number")?,
+                "--history" => history = Some(rest.next().ok_or("--history needs a file")?.clone()),
+                "--no-history" => history = None,
+                "--inputs" => input_directory = rest.next().ok_or("--inputs needs a directory")?.clone(),
+                day => {
+                    let day = day.parse().map_err(|_| format!("unexpected argument '{}'", day))?;
+                    if !registry.contains(day) {
+                        return Err(format!("day {} isn't solved", day));
+                    }
+                    days.push(day);
+                },
+            }
+        }
+        Ok(Config { command: Command::Benchmark(iterations, history, days), input_filename: input_directory })
+    }
+
+    // [directory] [--format <format>]
+    fn all_puzzles(args: &[String]) -> Result<Config, String> {
+        let (mut directory, mut format) = (None, OutputFormat::Text);
+        let mut rest = args.iter();
+        while let Some(arg) = rest.next() {
+            match arg.as_str() {
+                "--format" => format = rest.next().ok_or("--format needs json, csv or text")?.parse()?,
+                name if directory.is_none() => directory = Some(name.to_string()),
+                extra => return Err(format!("unexpected argument '{}'", extra)),
+            }
+        }
+        Ok(Config { command: Command::AllPuzzles(format), input_filename: directory.unwrap_or_else(|| String::from(".")) })
+    }
+
+    // --day <n> [--part <p>] [--format <format>] <input>, in any order
+    fn puzzle(args: &[String]) -> Result<Config, String> {
+        let (mut day, mut part, mut format, mut input_filename) = (None, None, OutputFormat::Text, None);
+        let mut rest = args.iter();
+        while let Some(arg) = rest.next() {
+            match arg.as_str() {
+                "--day" => {
+                    let value = rest.next().ok_or("--day needs a day number")?;
+                    day = Some(value.parse::<u32>().map_err(|_| format!("--day needs a day number, not '{}'", value))?);
+                },
+                "--part" => {
+                    let value = rest.next().ok_or("--part needs a part number")?;
+                    part = Some(value.parse::<u32>().ok().filter(|p| (1..=2).contains(p)).ok_or_else(|| format!("--part is 1 or 2, not '{}'", value))?);
+                },
+                "--format" => format = rest.next().ok_or("--format needs json, csv or text")?.parse()?,
+                filename if input_filename.is_none() => input_filename = Some(filename.to_string()),
+                extra => return Err(format!("unexpected argument '{}'", extra)),
+            }
+        }
+
+        let day = day.ok_or("--day needs a day number")?;
+        let registry = Registry::all_days();
+        if !registry.contains(day) {
+            let solved : Vec<String> = registry.days().map(|d| d.to_string()).collect();
+            return Err(format!("day {} isn't solved, solved days are {}", day, solved.join(", ")));
+        }
+        let input_filename = input_filename.ok_or_else(|| format!("day {} needs an input file, or - for stdin", day))?;
+        Ok(Config { command: Command::Puzzle(day, part, format), input_filename })
     }
 }
 
-pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
-    let contents = fs::read_to_string(config.input_filename)?;
-
-    match config.aoc_day {
-        1 => aoc_dayone(&contents, false),
-        2 => aoc_dayone(&contents, true),
-        3 => aoc_daytwo(&contents),
-        4 => aoc_daytwo_part2(&contents),
-        5 => aoc_daythree(&contents),
-        6 => aoc_daythree(&contents),
-        7 => aoc_dayfour(&contents, false),
-        8 => aoc_dayfour(&contents, true),
-        9 => aoc_dayfive(&contents, 1),
-        10 => aoc_dayfive(&contents, 5),
-        11 => aoc_daysix(&contents),
-        12 => aoc_daysix_parttwo(&contents),
-        13 => aoc_dayseven(&contents),
-        14 => aoc_dayseven_parttwo(&contents),
-        15 => aoc_dayeight(&contents),
-        16 => aoc_dayeight_parttwo(&contents),
-        17 => aoc_daynine(&contents,1),
-        18 => aoc_daynine(&contents,2),
-        19 => aoc_dayten(&contents),
-        20 => aoc_dayten_parttwo(&contents),
-        21 => aoc_dayeleven(&contents),
-        22 => aoc_dayeleven_parttwo(&contents),
-        23 => aoc_daytwelve(&contents),
-        24 => aoc_daytwelve_part2(&contents),
-        25 => aoc_daythirteen(&contents),
-        26 => aoc_daythirteen_parttwo(&contents),
-        27 => aoc_dayfourteen(&contents),
-        28 => aoc_dayfourtteen_parttwo(&contents),
-        31 => aoc_daysixteen(&contents),
-        32 => aoc_daysixteen_parttwo(&contents),
-        _ => ()
+// Input values given after a program on the command line
+fn parse_input_values(args: &[String], command: &str) -> Result<Vec<i64>, String> {
+    args.iter().map(|a| a.parse()).collect::<Result<_, _>>().map_err(|_| format!("{} input values must be integers", command))
+}
+
+// Reads a whole file, or stdin for -
+fn read_input(filename: &str) -> std::io::Result<String> {
+    if filename == "-" {
+        let mut contents = String::new();
+        std::io::Read::read_to_string(&mut std::io::stdin(), &mut contents)?;
+        return Ok(contents);
+    }
+    fs::read_to_string(filename)
+}
+
+// Checks every puzzle against the answers file, first recording the current answers of the given days into it
+fn run_regression(answers_filename: &str, input_directory: &str, record: &[u32]) -> Result<(), Box<dyn Error>> {
+    let mut known = match fs::read_to_string(answers_filename) {
+        Ok(text) => aoc_regression::parse_known_answers(&text).map_err(|error| format!("{}: {}", answers_filename, error))?,
+        // recording is how the file gets started
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound && !record.is_empty() => aoc_regression::KnownAnswers::new(),
+        Err(error) if error.kind() == std::io::ErrorKind::NotFound => return Err(format!("{}: {}, start it with --record", answers_filename, error).into()),
+        Err(error) => return Err(format!("{}: {}", answers_filename, error).into()),
     };
+    let registry = Registry::all_days();
+    let mut report = aoc_regression::run_regression(&registry, std::path::Path::new(input_directory), &known);
+
+    if !record.is_empty() {
+        for (day, part) in aoc_regression::record_answers(&mut known, &report, record)? {
+            println!("recorded day {} part {}", day, part);
+        }
+        fs::write(answers_filename, aoc_regression::format_known_answers(&known))?;
+        for result in report.results.iter_mut() {
+            result.expected = known.get(&(result.day, result.part)).cloned();
+        }
+    }
 
+    println!("{}", report);
+    if report.all_passed() { Ok(()) } else { Err("puzzle answers don't match the known answers".into()) }
+}
+
+// Times the given days, or all of them, comparing with and then adding to the history file if there is one
+fn run_benchmark(input_directory: &str, iterations: usize, history_filename: Option<&str>, days: &[u32]) -> Result<(), Box<dyn Error>> {
+    let registry = Registry::all_days();
+    let days = if days.is_empty() { registry.days().collect() } else { days.to_vec() };
+    if cfg!(debug_assertions) {
+        println!("this is a debug build, timings from a --release build are more useful");
+    }
+    let run = aoc_bench::run_benchmarks(&registry, std::path::Path::new(input_directory), &days, iterations)?;
+    let history = match history_filename {
+        Some(filename) => aoc_bench::read_history(filename)?,
+        None => Vec::new(),
+    };
+    print!("{}", aoc_bench::BenchmarkReport { run: &run, history: &history });
+
+    if let Some(filename) = history_filename {
+        aoc_bench::append_history(filename, &run).map_err(|error| format!("{}: {}", filename, error))?;
+        println!("added to {}", filename);
+    }
     Ok(())
 }
 
+// Solves every puzzle with its input from the directory, carrying on past ones that fail
+fn run_all_puzzles(directory: &str, format: OutputFormat) -> Result<(), Box<dyn Error>> {
+    let registry = Registry::all_days();
+    let mut failures = Vec::new();
+    if let Some(header) = aoc_output::header(format) {
+        println!("{}", header);
+    }
+    for day in registry.days() {
+        let filename = std::path::Path::new(directory).join(format!("aocday{}.txt", day));
+        let result = fs::read_to_string(&filename)
+            .map_err(|error| format!("{}: {}", filename.display(), error).into())
+            .and_then(|contents| registry.solve_detailed(day, &contents, &[1, 2], format.shows_details()));
+        match result {
+            Ok(results) => {
+                for result in &results {
+                    println!("{}", aoc_output::format_result(format, day, result));
+                }
+            },
+            Err(error) => {
+                println!("{}", aoc_output::format_error(format, day, &error.to_string()));
+                failures.push(format!("day {}", day));
+            }
+        }
+    }
+    if failures.is_empty() { Ok(()) } else { Err(format!("failed: {}", failures.join(", ")).into()) }
+}
+
+pub fn run(config: Config) -> Result<(), Box<dyn Error>> {
+    // takes a directory rather than a program
+    if let Command::Conformance = config.command {
+        let report = intopcode_conformance::run_directory(&config.input_filename)?;
+        println!("{}", report);
+        return if report.all_passed() { Ok(()) } else { Err("conformance cases failed".into()) };
+    }
+    if let Command::AllPuzzles(format) = config.command {
+        return run_all_puzzles(&config.input_filename, format);
+    }
+    if let Command::Regression(input_directory, record) = &config.command {
+        return run_regression(&config.input_filename, input_directory, record);
+    }
+    if let Command::Benchmark(iterations, history_filename, days) = &config.command {
+        return run_benchmark(&config.input_filename, *iterations, history_filename.as_deref(), days);
+    }
+
+    let contents = read_input(&config.input_filename).map_err(|error| format!("{}: {}", config.input_filename, error))?;
+
+    match config.command {
+        Command::Puzzle(day, part, format) => {
+            let parts = match part { Some(part) => vec![part], None => vec![1, 2] };
+            if let Some(header) = aoc_output::header(format) {
+                println!("{}", header);
+            }
+            for result in Registry::all_days().solve_detailed(day, &contents, &parts, format.shows_details())? {
+                println!("{}", aoc_output::format_result(format, day, &result));
+            }
+            Ok(())
+        },
+        Command::Disassemble => {
+            print!("{}", intopcode_asm::disassemble(&parse_intopcodes(&contents)?));
+            Ok(())
+        },
+        Command::Assemble => {
+            println!("{}", format_intopcodes(&intopcode_asm::assemble(&contents)?));
+            Ok(())
+        },
+        Command::Conformance | Command::AllPuzzles(..) | Command::Regression(..) | Command::Benchmark(..) => unreachable!(),
+        Command::Ascii(script_filename) => {
+            let comp = IntOpCodeComp::new(&parse_intopcodes(&contents)?, &VecDeque::new());
+            let mut console = intopcode_console::Console::new(comp, std::io::stdout());
+            if let Some(script_filename) = script_filename {
+                console.run_script(&fs::read_to_string(script_filename)?)?;
+            }
+            console.run_interactive(std::io::stdin().lock())?;
+            console.finish()?;
+            Ok(())
+        },
+        Command::ControlFlowGraph => {
+            print!("{}", intopcode_cfg::analyze(&parse_intopcodes(&contents)?).to_dot());
+            Ok(())
+        },
+        Command::Run(budget, strict_day, input_values) => {
+            let mut comp = IntOpCodeComp::new(&parse_intopcodes(&contents)?, &input_values.into_iter().collect());
+            if let Some(day) = strict_day {
+                comp.set_instruction_set(Arc::new(InstructionSet::for_day(day)));
+            }
+            let outcome = comp.run_with_budget(&budget)?;
+            println!("Output={:?}", comp.output_values);
+            println!("{}", outcome);
+            Ok(())
+        },
+        Command::Debug(input_values) => {
+            let intopcodes = parse_intopcodes(&contents)?;
+            intopcode_debugger::run_debugger(&intopcodes, &input_values.into_iter().collect());
+            Ok(())
+        },
+        Command::Trace(trace_filename, input_values) => {
+            let mut comp = IntOpCodeComp::new(&parse_intopcodes(&contents)?, &input_values.into_iter().collect());
+            let mut trace = intopcode_trace::JsonTraceWriter::new(std::io::BufWriter::new(fs::File::create(trace_filename)?));
+            let state = intopcode_trace::run_observed(&mut comp, &mut [&mut trace])?;
+            trace.finish()?;
+            report_observed_run(state, &comp);
+            Ok(())
+        },
+        Command::Profile(input_values) => {
+            let mut comp = IntOpCodeComp::new(&parse_intopcodes(&contents)?, &input_values.into_iter().collect());
+            let mut profiler = intopcode_trace::Profiler::new();
+            let state = intopcode_trace::run_observed(&mut comp, &mut [&mut profiler])?;
+            report_observed_run(state, &comp);
+            println!("{}", profiler.report(10));
+            Ok(())
+        },
+        Command::SelfModification(input_values) => {
+            let mut comp = IntOpCodeComp::new(&parse_intopcodes(&contents)?, &input_values.into_iter().collect());
+            let mut detector = intopcode_selfmod::SelfModificationDetector::new();
+            let state = intopcode_trace::run_observed(&mut comp, &mut [&mut detector])?;
+            report_observed_run(state, &comp);
+            println!("{}", detector.report());
+            Ok(())
+        },
+    }
+}
+
+// Every solved day, for Registry::all_days
+fn register_solvers(registry: &mut Registry) {
+    registry.register(1, AocDayOne);
+    registry.register(2, AocDayTwo);
+    registry.register(3, AocDayThree);
+    registry.register(4, AocDayFour);
+    registry.register(5, AocDayFive);
+    registry.register(6, AocDaySix);
+    registry.register(7, AocDaySeven);
+    registry.register(8, AocDayEight);
+    registry.register(9, AocDayNine);
+    registry.register(10, AocDayTen);
+    registry.register(11, AocDayEleven);
+    registry.register(12, AocDayTwelve);
+    registry.register(13, AocDayThirteen);
+    registry.register(14, AocDayFourteen);
+    registry.register(16, AocDaySixteen);
+}
+
+fn report_observed_run(state: RunState, comp: &IntOpCodeComp) {
+    println!("Output={:?}", comp.output_values);
+    if state == RunState::WaitingOnInput {
+        println!("Stopped waiting on input at ip {}", comp.index);
+    }
+}
+
 fn aoc_dayone_fuel_req(mass : i32, include_fuel_mass : bool) -> i32 {
     // Fuel required to launch a given module is based on its mass.
     // Specifically, to find the fuel required for a module, take its mass, divide by three, round down, and subtract 2.
     let mut fuel_req = std::cmp::max( ( mass / 3 ) - 2, 0 );
 
-    if fuel_req > 0 && include_fuel_mass == true {
+    if fuel_req > 0 && include_fuel_mass {
         fuel_req += aoc_dayone_fuel_req(fuel_req, true);
     }
     
     fuel_req
 }
 
-fn aoc_dayone(input: &str, include_fuel_mass : bool) {
-    let mut fuel_req = 0;
-    for line in input.lines() {
-        let mass : i32 = line.parse().unwrap();
-        let fuel_for_mass = aoc_dayone_fuel_req(mass, include_fuel_mass);
-        fuel_req += fuel_for_mass;
-        println!("Mass: {}, Fuel for Mass: {}, Fuel Req: {}", mass, fuel_for_mass, fuel_req);
+struct AocDayOne;
+
+impl Solver for AocDayOne {
+    type Input = Vec<i32>;
+
+    fn parse(&self, input: &str) -> PuzzleResult<Vec<i32>> {
+        Ok(input.lines().map(|line| line.trim().parse()).collect::<Result<_, _>>()?)
+    }
+
+    fn part1(&self, masses: &Vec<i32>) -> PuzzleResult<Answer> {
+        Ok(masses.iter().map(|&mass| aoc_dayone_fuel_req(mass, false)).sum::<i32>().into())
+    }
+
+    fn part2(&self, masses: &Vec<i32>) -> PuzzleResult<Answer> {
+        Ok(masses.iter().map(|&mass| aoc_dayone_fuel_req(mass, true)).sum::<i32>().into())
     }
 }
 
-fn compute_intopcodes_from_string(input: &str) -> Vec<i64> {
-    let mut intopcodes = Vec::new();
-    let v: Vec<&str> = input.split_terminator(',').collect();
-    for intopcode_str in v.iter() {
-        let intopcode : i64 = intopcode_str.parse().unwrap();
-        intopcodes.push(intopcode);
+// The comma separated program, with an error for values that aren't numbers or no program at all
+fn parse_intopcodes(input: &str) -> PuzzleResult<Vec<i64>> {
+    if input.trim().is_empty() {
+        return Err("the input has no intopcodes".into());
     }
-    intopcodes
+    input.trim().split_terminator(',')
+        .map(|v| v.trim().parse().map_err(|error| format!("invalid intopcode '{}': {}", v.trim(), error).into()))
+        .collect()
 }
 
-#[derive(Debug)]
+// The last output is the answer, any before it are self tests that output 0 when they pass
+fn diagnostic_code(outputs: &VecDeque<i64>) -> PuzzleResult<Answer> {
+    let code = *outputs.back().ok_or("the program gave no output")?;
+    if outputs.iter().rev().skip(1).any(|&test| test != 0) {
+        return Err(format!("the program's self tests failed: {:?}", outputs).into());
+    }
+    Ok(code.into())
+}
+
+// Splits an instruction word into its two digit opcode and the mode digits of its three parameters
+fn decode_opcode(opcode: i64) -> (i64, [i64; 3]) {
+    (opcode % 100, [(opcode / 100) % 10, (opcode / 1000) % 10, (opcode / 10000) % 10])
+}
+
+// Number of parameters following each opcode, None if the opcode is unknown
+fn opcode_parameter_count(two_digit_opcode: i64) -> Option<usize> {
+    match two_digit_opcode {
+        1 | 2 | 7 | 8 => Some(3),
+        3 | 4 | 9 => Some(1),
+        5 | 6 => Some(2),
+        99 => Some(0),
+        _ => None
+    }
+}
+
+fn format_intopcodes(intopcodes: &[i64]) -> String {
+    let strs : Vec<String> = intopcodes.iter().map(|i| i.to_string()).collect();
+    strs.join(",")
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum IntOpCodeError {
+    UnknownOpcode { ip: usize, opcode: i64 },
+    UnknownParameterMode { ip: usize, opcode: i64, parameter: usize, mode: i64 },
+    NegativeAddress { ip: usize, opcode: i64, parameter: usize, address: i64 },
+    Overflow { ip: usize, opcode: i64 },
+    MemoryLimitExceeded { ip: usize, address: usize, limit: usize },
+}
+
+impl fmt::Display for IntOpCodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            IntOpCodeError::UnknownOpcode { ip, opcode } => {
+                write!(f, "unknown opcode {} at ip {}", opcode, ip)
+            },
+            IntOpCodeError::UnknownParameterMode { ip, opcode, parameter, mode } => {
+                write!(f, "unknown mode {} for parameter {} of opcode {} at ip {}", mode, parameter, opcode, ip)
+            },
+            IntOpCodeError::NegativeAddress { ip, opcode, parameter, address } => {
+                write!(f, "negative address {} from parameter {} of opcode {} at ip {}", address, parameter, opcode, ip)
+            },
+            IntOpCodeError::Overflow { ip, opcode } => {
+                write!(f, "arithmetic overflow in opcode {} at ip {}", opcode, ip)
+            },
+            IntOpCodeError::MemoryLimitExceeded { ip, address, limit } => {
+                write!(f, "write to address {} at ip {} exceeds the memory limit of {} words", address, ip, limit)
+            },
+        }
+    }
+}
+
+impl Error for IntOpCodeError {}
+
+#[derive(Debug, Clone, Copy)]
 enum ParameterMode {
     Position, // parameter is interpreted as a position
     ImmediateMode, // parameter is interpreted as a value
@@ -110,325 +565,369 @@ enum ParameterMode {
 }
 
 impl ParameterMode {
-    fn from_index(idx: i64, relative_base: i64) -> ParameterMode {
+    fn from_index(idx: i64, relative_base: i64) -> Option<ParameterMode> {
         match idx {
-            0 => ParameterMode::Position,
-            1 => ParameterMode::ImmediateMode,
-            2 => ParameterMode::RelativeMode(relative_base),
-            _ => panic!("Unknown Parameter Mode {}", idx)
+            0 => Some(ParameterMode::Position),
+            1 => Some(ParameterMode::ImmediateMode),
+            2 => Some(ParameterMode::RelativeMode(relative_base)),
+            _ => None
         }
     }
 
-    fn get_index_for_write(&self, _intopcodes: &Vec<i64>, param: i64) -> usize {
-        match self {
+    // Err holds the negative address the parameter pointed to
+    fn get_index_for_write(&self, _intopcodes: &dyn IntOpCodeMemory, param: i64) -> Result<usize, i64> {
+        let index = match self {
             ParameterMode::RelativeMode(relative_base) => {
-                relative_base + param
+                relative_base.saturating_add(param)
             },
             _ => {  // default behavior is to use Immediate
                 param
             },
-        }.try_into().unwrap()
+        };
+        index.try_into().map_err(|_| index)
     }
 
-    fn get_value(&self, intopcodes: &Vec<i64>, param: i64) -> i64 {
-        match self {
-            ParameterMode::Position => {
-                let index : usize = param.try_into().unwrap();
-                if index < intopcodes.len() {
-                    intopcodes[index]
-                }
-                else {
-                    0
-                }
-            },
+    // Err holds the negative address the parameter pointed to. Memory past the end of the program reads as 0.
+    fn get_value(&self, intopcodes: &dyn IntOpCodeMemory, param: i64) -> Result<i64, i64> {
+        let address = match self {
+            ParameterMode::Position => param,
             ParameterMode::ImmediateMode => {
-                param
+                return Ok(param);
             },
-            ParameterMode::RelativeMode(relative_base) => {
-                let index : usize = (relative_base + param).try_into().unwrap();
-                if index < intopcodes.len() {
-                    intopcodes[index]
-                }
-                else {
-                    0
-                }
-            }
-        }
+            ParameterMode::RelativeMode(relative_base) => relative_base.saturating_add(param)
+        };
+        let index : usize = address.try_into().map_err(|_| address)?;
+        Ok(intopcodes.read(index))
     }
 }
 
-#[derive(Debug)]
-struct IntOpCodeComp {    
-    intopcodes: Vec<i64>,
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunState {
+    Running,
+    WaitingOnInput, // blocked on opcode 3 with an empty input queue
+    Halted,         // reached opcode 99
+}
+
+// What stopped a call to run_until_event
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RunEvent {
+    InputNeeded,    // blocked on opcode 3, push to input_values and run again
+    Output(i64),    // opcode 4 produced a value, it is taken off output_values
+    Halted,
+}
+
+#[derive(Debug, Clone)]
+pub struct IntOpCodeComp {    
+    memory: Box<dyn IntOpCodeMemory>,
     input_values: VecDeque<i64>,
     output_values: VecDeque<i64>,
     index: usize,
     relative_base: i64,
-    waiting_on_input: bool,
-    complete: bool
+    state: RunState,
+    instruction_set: Arc<InstructionSet>,
+    // Undo log, only kept while recording
+    history: Option<intopcode_history::History>,
 }
 
 impl IntOpCodeComp {
-    fn new(intopcodes: &Vec<i64>, input_values: &VecDeque<i64>) -> IntOpCodeComp {
+    pub fn new(intopcodes: &[i64], input_values: &VecDeque<i64>) -> IntOpCodeComp {
+        // a program bigger than the default limit still loads, it just can't grow
+        let limit = DEFAULT_MEMORY_LIMIT.max(intopcodes.len());
+        IntOpCodeComp::with_memory(Box::new(DenseMemory::new(intopcodes, Some(limit)).expect("the limit covers the program")), input_values)
+    }
+
+    // Runs the program already loaded into memory, e.g. a PagedMemory or SparseMemory for programs using huge addresses
+    pub fn with_memory(memory: Box<dyn IntOpCodeMemory>, input_values: &VecDeque<i64>) -> IntOpCodeComp {
+        let state = if memory.is_empty() { RunState::Halted } else { RunState::Running };
         IntOpCodeComp{
-            intopcodes: intopcodes.clone(),
+            memory,
             input_values: input_values.clone(),
             output_values: VecDeque::new(),
             index: 0,
             relative_base: 0,
-            waiting_on_input: false,
-            complete: intopcodes.len() == 0,
+            state,
+            instruction_set: InstructionSet::standard_shared(),
+            history: None,
         }
     }
 
-    fn store_at_index(&mut self, value: i64, index: usize) {
-        if index >= self.intopcodes.len() {
-            self.intopcodes.resize(index + 1, 0);   // Expand memory to necessary size filled with 0s
-        }
-        self.intopcodes[index] = value;
+    pub fn state(&self) -> RunState {
+        self.state
+    }
+
+    pub fn memory(&self) -> &dyn IntOpCodeMemory {
+        self.memory.as_ref()
+    }
+
+    pub fn push_input(&mut self, value: i64) {
+        self.input_values.push_back(value);
     }
 
-    fn tick_to_completion(&mut self) -> &VecDeque<i64> {
-        while self.complete == false {
-            self.tick();
+    fn store_at_index(&mut self, value: i64, index: usize) -> Result<(), IntOpCodeError> {
+        let old_value = if self.history.is_some() { Some(self.read_memory(index)) } else { None };
+        self.memory.write(index, value).map_err(|error| IntOpCodeError::MemoryLimitExceeded { ip: self.index, address: error.address, limit: error.limit })?;
+        if let Some(old_value) = old_value {
+            self.record_write(index, old_value, value);
         }
+        Ok(())
+    }
+
+    fn read_memory(&self, index: usize) -> i64 {
+        self.memory.read(index)
+    }
+
+    fn negative_address(&self, parameter: usize, address: i64) -> IntOpCodeError {
+        IntOpCodeError::NegativeAddress { ip: self.index, opcode: self.read_memory(self.index), parameter, address }
+    }
+
+    // Value of the nth (1 based) parameter of the current instruction
+    fn read_param(&self, modes: &[ParameterMode], n: usize) -> Result<i64, IntOpCodeError> {
+        modes[n - 1].get_value(self.memory.as_ref(), self.read_memory(self.index + n))
+            .map_err(|address| self.negative_address(n, address))
+    }
 
-        &self.output_values
+    // Address the nth (1 based) parameter of the current instruction writes to
+    fn write_param_index(&self, modes: &[ParameterMode], n: usize) -> Result<usize, IntOpCodeError> {
+        modes[n - 1].get_index_for_write(self.memory.as_ref(), self.read_memory(self.index + n))
+            .map_err(|address| self.negative_address(n, address))
     }
 
-    fn tick(&mut self) {
-        if self.complete == true {
-            return;
+    fn overflow(&self) -> IntOpCodeError {
+        IntOpCodeError::Overflow { ip: self.index, opcode: self.read_memory(self.index) }
+    }
+
+    // Runs until the program halts or needs input it hasn't been given. Use run_with_budget
+    // for programs that might never do either.
+    pub fn tick_to_completion(&mut self) -> Result<&VecDeque<i64>, IntOpCodeError> {
+        while self.state == RunState::Running {
+            self.tick()?;
         }
 
-        let opcode = self.intopcodes[self.index];
-        let two_digit_opcode = opcode % 100;
-        let mode_1st_param = ParameterMode::from_index((opcode / 100) % 10, self.relative_base);
-        let mode_2nd_param = ParameterMode::from_index((opcode / 1000) % 10, self.relative_base);
-        let mode_3rd_param = ParameterMode::from_index((opcode / 10000) % 10, self.relative_base);
-        
-        match two_digit_opcode {
-            1 => {
-                // Adds the next two numbers together, store in index of third number                
-                let a = mode_1st_param.get_value(&self.intopcodes, self.intopcodes[self.index + 1]);
-                let b = mode_2nd_param.get_value(&self.intopcodes, self.intopcodes[self.index + 2]);                
-                let store_index = mode_3rd_param.get_index_for_write(&self.intopcodes, self.intopcodes[self.index + 3]);
-                //println!("Idx {} Op1 ({:?}, {:?}): {} + {} => {} -> StoreAtIdx {}", index, mode_1st_param, mode_2nd_param, a, b, (a+b), store_index);
-                self.store_at_index(a + b, store_index);
-                self.index += 4;
-            },
-            2 => {
-                // Multiplies the next two numbers together, store in index of third number
-                let a = mode_1st_param.get_value(&self.intopcodes, self.intopcodes[self.index + 1]);
-                let b = mode_2nd_param.get_value(&self.intopcodes, self.intopcodes[self.index + 2]);                
-                let store_index = mode_3rd_param.get_index_for_write(&self.intopcodes, self.intopcodes[self.index + 3]);
-                //println!("Idx {} Op2 ({:?}, {:?}): {} * {} => {} -> StoreAtIdx {}", index, mode_1st_param, mode_2nd_param, a, b, (a*b), store_index);
-                self.store_at_index(a * b, store_index);
-                self.index += 4;
-            },
-            3 => {
-                // Opcode 3 takes a single integer as input and saves it to the position given by its only parameter. 
-                // For example, the instruction 3,50 would take an input value and store it at address 50.
-                let store_index = mode_1st_param.get_index_for_write(&self.intopcodes, self.intopcodes[self.index + 1]);
-                //println!("Op3: {} = {:?}", store_index, self.input_values.front() );
-                match self.input_values.pop_front() {
-                    Some(val) => {
-                        self.store_at_index(val, store_index);
-                        self.index += 2;
-                        self.waiting_on_input = false;
-                    },
-                    None => self.waiting_on_input = true  // just wait until we get an input at some point
-                };
-            },
-            4 => {
-                // Opcode 4 outputs the value of its only parameter.
-                // For example, the instruction 4,50 would output the value at address 50.
-                let output_index = self.intopcodes[self.index + 1];
-                self.output_values.push_back(mode_1st_param.get_value(&self.intopcodes, output_index).try_into().unwrap());
-                //println!("Op4: {} = {}", output_index, self.output_values.back().unwrap());
-                self.index += 2;
-            },
-            5 => {
-                // jump-if-true: if the first parameter is non-zero, 
-                // it sets the instruction pointer to the value from the second parameter. 
-                // Otherwise, it does nothing.
-                let a = mode_1st_param.get_value(&self.intopcodes, self.intopcodes[self.index + 1]);
-                if a != 0 {
-                    let b = mode_2nd_param.get_value(&self.intopcodes, self.intopcodes[self.index + 2]);
-                    self.index = b.try_into().unwrap();
-                }
-                else {
-                    self.index += 3;
-                }
-            },
-            6 => {
-                // jump-if-false: if the first parameter is zero,
-                // it sets the instruction pointer to the value from the second parameter. 
-                // Otherwise, it does nothing.
-                let a = mode_1st_param.get_value(&self.intopcodes, self.intopcodes[self.index + 1]);
-                if a == 0 {
-                    let b = mode_2nd_param.get_value(&self.intopcodes, self.intopcodes[self.index + 2]);
-                    self.index = b.try_into().unwrap();
-                }
-                else {
-                    self.index += 3;
-                }
-            },
-            7 => {
-                // less than: if the first parameter is less than the second parameter,
-                // it stores 1 in the position given by the third parameter. Otherwise, it stores 0.
-                let a = mode_1st_param.get_value(&self.intopcodes, self.intopcodes[self.index + 1]);
-                let b = mode_2nd_param.get_value(&self.intopcodes, self.intopcodes[self.index + 2]);
-                let store_index = mode_3rd_param.get_index_for_write(&self.intopcodes, self.intopcodes[self.index + 3]);
-                self.store_at_index(match a < b { true => 1, false => 0}, store_index);
-                self.index += 4;
-            },
-            8 => {
-                // equals: if the first parameter is equal to the second parameter,
-                // it stores 1 in the position given by the third parameter. Otherwise, it stores 0.
-                let a = mode_1st_param.get_value(&self.intopcodes, self.intopcodes[self.index + 1]);
-                let b = mode_2nd_param.get_value(&self.intopcodes, self.intopcodes[self.index + 2]);
-                let store_index = mode_3rd_param.get_index_for_write(&self.intopcodes, self.intopcodes[self.index + 3]);
-                self.store_at_index(match a == b { true => 1, false => 0 }, store_index);
-                self.index += 4;
-            },
-            9 => {
-                // adjusts the relative base by the value of its only parameter
-                let a = mode_1st_param.get_value(&self.intopcodes, self.intopcodes[self.index + 1]);
-                self.relative_base += a;
-                self.index += 2;
-            },
-            99 => {
-                self.index = self.intopcodes.len();
-                self.complete = true;
+        Ok(&self.output_values)
+    }
+
+    // Executes instructions until the program needs input, produces an output or halts
+    pub fn run_until_event(&mut self) -> Result<RunEvent, IntOpCodeError> {
+        loop {
+            if self.state == RunState::Halted {
+                return Ok(RunEvent::Halted);
             }
-            _ => panic!("Unexpected op code {}", two_digit_opcode)
+
+            let output_count = self.output_values.len();
+            self.tick()?;
+
+            if self.state == RunState::WaitingOnInput {
+                return Ok(RunEvent::InputNeeded);
+            }
+            if self.output_values.len() > output_count {
+                return Ok(RunEvent::Output(self.output_values.pop_back().unwrap()));
+            }
+        }
+    }
+
+    pub fn tick(&mut self) -> Result<(), IntOpCodeError> {
+        if self.state == RunState::Halted {
+            return Ok(());
         }
+
+        let opcode = self.read_memory(self.index);
+        let (two_digit_opcode, modes) = decode_opcode(opcode);
+        let definition = self.instruction_set.get(two_digit_opcode)
+            .ok_or(IntOpCodeError::UnknownOpcode { ip: self.index, opcode })?;
+        let mut param_modes = [ParameterMode::Position; 3];
+        for (i, (&mode, &kind)) in modes.iter().zip(&definition.parameters).enumerate() {
+            param_modes[i] = ParameterMode::from_index(mode, self.relative_base)
+                .filter(|_| self.instruction_set.allows_mode(mode, kind))
+                .ok_or(IntOpCodeError::UnknownParameterMode { ip: self.index, opcode, parameter: i + 1, mode })?;
+        }
+
+        // the opcodes themselves live in intopcode_isa, so they can be swapped out or added to
+        let (execute, parameter_count) = (definition.execute, definition.parameters.len());
+        if self.history.is_some() {
+            return self.execute_recorded(execute, param_modes, parameter_count);
+        }
+        self.execute(execute, param_modes, parameter_count)
     }
 }
 
-#[derive(Debug)]
-struct Amplifiers {
-    computers: Vec<IntOpCodeComp>,
-    feedback: bool
+// Amplifiers chained in phase order with the first one given its 0 signal, ready to run
+fn amplifier_network(intopcodes: &[i64], phases: &[i64], feedback: bool) -> Network {
+    // Create a new computer for each phase, its first input is the phase, and chain them together
+    let computers = phases.iter().map(|p| IntOpCodeComp::new(intopcodes, &VecDeque::from(vec![*p]))).collect();
+    let mut amps = Network::new(computers);
+    for i in 1..phases.len() {
+        amps.connect(i - 1, i);
+    }
+    if feedback {
+        amps.connect(phases.len() - 1, 0);
+    }
+
+    amps.send(0, 0);
+    amps
 }
 
-impl Amplifiers {
-    fn new(intopcode_base: &Vec<i64>, phases: &Vec<i64>, feedback: bool) -> Amplifiers {
-        // Create a new computer for each phase
-        let mut amps = Amplifiers {
-            computers: Vec::new(),
-            feedback
-        };
-        for p in phases {
-            let mut input_values = VecDeque::new();
-            input_values.push_back( *p );
-            amps.computers.push( IntOpCodeComp::new(&intopcode_base, &input_values) );
-        }
-        amps
-    }
-
-    fn tick(&mut self) {
-        for i in 0..self.computers.len() {
-            // feed in any output from previous computers as our input
-            if i > 0 || self.feedback == true {
-                let prev_index = if i == 0 { self.computers.len() - 1 } else { i - 1 };
-                match self.computers[prev_index].output_values.pop_front() {
-                    Some(val) => self.computers[i].input_values.push_back(val),
-                    None => ()
-                };
-            }
-            self.computers[i].tick();         
+fn process_amp_intopcode_with_phases(intopcodes: &[i64], phases: &[i64], feedback: bool) -> Result<i64, IntOpCodeError> {
+    let mut amps = amplifier_network(intopcodes, phases, feedback);
+    amps.run()?;
+    Ok(amps.last_output(phases.len() - 1).unwrap_or(0))
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PhaseSetting {
+    pub phases: Vec<i64>,
+    pub output: i64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum PhaseSearchError {
+    TooManyPhases(usize),   // more orderings than can be counted, let alone tried
+    Program(IntOpCodeError),
+}
+
+impl fmt::Display for PhaseSearchError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            PhaseSearchError::TooManyPhases(count) => write!(f, "{} phases have too many orderings to try", count),
+            PhaseSearchError::Program(error) => write!(f, "{}", error),
         }
     }
+}
 
-    fn tick_to_completion(&mut self) -> i64 {
-        let mut last_valid_output = 0;
-        while self.computers.last().unwrap().complete == false {
-            self.tick();
-            match self.computers.last().unwrap().output_values.front() {
-                Some(val) => last_valid_output = *val,
-                None => ()
-            };  
-        }
+impl Error for PhaseSearchError {}
 
-        last_valid_output
+impl From<IntOpCodeError> for PhaseSearchError {
+    fn from(error: IntOpCodeError) -> PhaseSearchError {
+        PhaseSearchError::Program(error)
     }
 }
 
-fn process_amp_intopcode_with_phases(intopcodes: &Vec<i64>, phases: &Vec<i64>, feedback: bool) -> i64 {
-    let mut amps = Amplifiers::new(intopcodes, phases, feedback);
-    amps.computers[0].input_values.push_back(0);
-    amps.tick_to_completion()
+// Number of orderings of count phases, None if it doesn't fit in a usize
+fn permutation_count(count: usize) -> Option<usize> {
+    (1..=count).try_fold(1usize, |product, n| product.checked_mul(n))
 }
 
-fn compute_max_amp_intopcode(intopcodes: &Vec<i64>, phases: &[i64;5], feedback: bool) -> i64 {
-    let mut max_output = 0;
-    let mut phase_perm = phases.clone();
-    loop {
-        let output = process_amp_intopcode_with_phases(&intopcodes, &phase_perm.to_vec(), feedback);
-        max_output = std::cmp::max(max_output, output);
-        if !phase_perm.next_permutation() {
-            break;
+// Best (output, index) found by one search thread, or the first index that failed
+type PhaseChunkResult = Result<Option<(i64, usize)>, (usize, IntOpCodeError)>;
+
+// The permutation at position index in lexical order of the positions of phases
+fn nth_phase_permutation(phases: &[i64], mut index: usize) -> Vec<i64> {
+    let mut remaining = phases.to_vec();
+    let mut permutation = Vec::with_capacity(phases.len());
+    while !remaining.is_empty() {
+        // a block too big to count is bigger than any index, so the index is inside the first one
+        match permutation_count(remaining.len() - 1) {
+            Some(block_size) => {
+                permutation.push(remaining.remove(index / block_size));
+                index %= block_size;
+            },
+            None => permutation.push(remaining.remove(0)),
         }
     }
-    max_output
+    permutation
 }
 
-fn get_intopcode_output(intopcodes_base: &Vec<i64>, input_value: i64) -> VecDeque<i64> {
+// Tries every ordering of the phases, split across all CPU cores, and returns the one giving the
+// highest output. Ties go to the ordering found first, so the result doesn't depend on thread timing.
+pub fn find_best_phases(intopcodes: &[i64], phases: &[i64], feedback: bool) -> Result<Option<PhaseSetting>, PhaseSearchError> {
+    if phases.is_empty() {
+        return Ok(None);
+    }
+
+    let mut sorted_phases = phases.to_vec();
+    sorted_phases.sort_unstable();
+    let permutation_count = permutation_count(phases.len()).ok_or(PhaseSearchError::TooManyPhases(phases.len()))?;
+    let thread_count = std::thread::available_parallelism().map(|n| n.get()).unwrap_or(1).min(permutation_count);
+    let chunk_size = permutation_count.div_ceil(thread_count);
+
+    let results : Vec<PhaseChunkResult> = std::thread::scope(|scope| {
+        let handles : Vec<_> = (0..thread_count).map(|t| {
+            let sorted_phases = &sorted_phases;
+            scope.spawn(move || {
+                let mut best : Option<(i64, usize)> = None;
+                for index in t * chunk_size..std::cmp::min((t + 1) * chunk_size, permutation_count) {
+                    let permutation = nth_phase_permutation(sorted_phases, index);
+                    let output = process_amp_intopcode_with_phases(intopcodes, &permutation, feedback).map_err(|error| (index, error))?;
+                    if best.map_or(true, |(best_output, _)| output > best_output) {
+                        best = Some((output, index));
+                    }
+                }
+                Ok(best)
+            })
+        }).collect();
+        handles.into_iter().map(|h| h.join().unwrap()).collect()
+    });
+
+    let mut best : Option<(i64, usize)> = None;
+    for result in results {
+        // chunks are in order, so the first error is also the one with the lowest index
+        if let Some((output, index)) = result.map_err(|(_, error)| error)? {
+            if best.map_or(true, |(best_output, _)| output > best_output) {
+                best = Some((output, index));
+            }
+        }
+    }
+
+    Ok(best.map(|(output, index)| PhaseSetting { phases: nth_phase_permutation(&sorted_phases, index), output }))
+}
+
+fn get_intopcode_output(intopcodes_base: &[i64], input_value: i64) -> Result<VecDeque<i64>, IntOpCodeError> {
     let mut input_values = VecDeque::new();
     input_values.push_back(input_value);
     let mut comp = IntOpCodeComp::new(intopcodes_base, &input_values);
-    comp.tick_to_completion().clone()
+    Ok(comp.tick_to_completion()?.clone())
 }
 
-fn process_simple_intopcode(intopcodes_base: &Vec<i64>) -> Vec<i64> {    
+fn process_simple_intopcode(intopcodes_base: &[i64]) -> Result<Vec<i64>, IntOpCodeError> {    
     let input_values = VecDeque::new();
     let mut comp = IntOpCodeComp::new(intopcodes_base, &input_values);
-    comp.tick_to_completion();
-    comp.intopcodes.clone()
+    comp.tick_to_completion()?;
+    comp.memory.to_vec().map_err(|error| IntOpCodeError::MemoryLimitExceeded { ip: comp.index, address: error.address, limit: error.limit })
 }
 
-fn aoc_daytwo(input: &str) {
-    let mut intopcodes = compute_intopcodes_from_string(input);
+struct AocDayTwo;
 
-    // before running the program, replace position 1 with the value 12
-    // and replace position 2 with the value 2.
-    // What value is left at position 0 after the program halts?
-    intopcodes[1] = 12;
-    intopcodes[2] = 2;
-    
-    let processed_intopcodes = process_simple_intopcode(&intopcodes);
+impl Solver for AocDayTwo {
+    type Input = Vec<i64>;
 
-    println!("{:?}", processed_intopcodes);
-}
+    fn parse(&self, input: &str) -> PuzzleResult<Vec<i64>> {
+        let intopcodes = parse_intopcodes(input)?;
+        if intopcodes.len() < 3 {
+            return Err("the program is too short to have a noun and verb at addresses 1 and 2".into());
+        }
+        Ok(intopcodes)
+    }
 
-fn aoc_daytwo_part2(input: &str) {
-    // find values for index 1 and 2 (between 0 and 99) to produce result 19690720
-    let mut intopcodes = compute_intopcodes_from_string(input);
+    fn part1(&self, intopcodes: &Vec<i64>) -> PuzzleResult<Answer> {
+        let mut intopcodes = intopcodes.clone();
 
-    let mut index1 = 0;
-    let mut index2 = 0;
-    
-    while index1 < 100 {
-        while index2 < 100 {
-            intopcodes[1] = index1;
-            intopcodes[2] = index2;
+        // before running the program, replace position 1 with the value 12
+        // and replace position 2 with the value 2.
+        // What value is left at position 0 after the program halts?
+        intopcodes[1] = 12;
+        intopcodes[2] = 2;
 
-            let processed_intopcodes = process_simple_intopcode(&intopcodes);
+        let processed_intopcodes = process_simple_intopcode(&intopcodes)?;
+        Ok(processed_intopcodes[0].into())
+    }
 
-            if processed_intopcodes[0] == 19690720 {
-                println!("{}", index1 * 100 + index2);
-                println!("{:?}", processed_intopcodes);
-                return
-            }
-            index2 += 1;
-        }
-        index1 += 1;
-        index2 = 0;
+    fn part2(&self, intopcodes: &Vec<i64>) -> PuzzleResult<Answer> {
+        aoc_daytwo_part2(intopcodes)
     }
+}
+
+fn aoc_daytwo_part2(intopcodes: &[i64]) -> PuzzleResult<Answer> {
+    // find values for index 1 and 2 (between 0 and 99) to produce result 19690720
+    let variables = [
+        intopcode_solver::FreeVariable { address: 1, min: 0, max: 99 },
+        intopcode_solver::FreeVariable { address: 2, min: 0, max: 99 },
+    ];
+    let goal = intopcode_solver::Goal::Equals { address: 0, target: 19690720 };
+    let solution = intopcode_solver::solve(intopcodes, &variables, &goal)?;
 
-    println!("No values found.");
+    match solution.assignments.first() {
+        Some(assignment) => Ok((assignment[0] * 100 + assignment[1]).into()),
+        None => Err("no noun and verb give 19690720".into())
+    }
 }
 
 struct WireCmd {
@@ -436,19 +935,23 @@ struct WireCmd {
     dist : u32
 }
 
-fn compute_wire_cmds(input: &str) -> Vec<WireCmd> {
+fn compute_wire_cmds(input: &str) -> PuzzleResult<Vec<WireCmd>> {
     let mut wirecmds = Vec::new();
     let v: Vec<&str> = input.trim().split_terminator(',').collect();
     for cmd in v.iter() {
-        let dir = cmd.chars().next().unwrap();
-        let dist : u32 = cmd[1..].parse().unwrap();
-        wirecmds.push( WireCmd{ dir, dist } );
+        let mut chars = cmd.chars();
+        let dir = chars.next().filter(|dir| "RLUD".contains(*dir));
+        let dist = chars.as_str().parse::<u32>();
+        match (dir, dist) {
+            (Some(dir), Ok(dist)) => wirecmds.push( WireCmd{ dir, dist } ),
+            _ => return Err(format!("invalid wire step '{}', expected a direction R, L, U or D and a distance", cmd).into())
+        }
     }
 
-    wirecmds
+    Ok(wirecmds)
 }
 
-fn compute_position_hash(wirecmds : &Vec<WireCmd>) -> HashMap<(i32, i32), i32> {
+fn compute_position_hash(wirecmds : &[WireCmd]) -> HashMap<(i32, i32), i32> {
     let mut position_hash = HashMap::new();
     let mut pos = (0, 0);
     let mut dist = 0;
@@ -472,36 +975,50 @@ fn compute_position_hash(wirecmds : &Vec<WireCmd>) -> HashMap<(i32, i32), i32> {
     position_hash
 }
 
-fn compute_min_dist(input: &str) -> (i32, i32) {
-    let mut lines = input.lines();
-    let wirecmds1 = compute_wire_cmds( lines.next().unwrap() );
-    let wirecmds2 = compute_wire_cmds( lines.next().unwrap() );
+fn parse_wires(input: &str) -> PuzzleResult<(Vec<WireCmd>, Vec<WireCmd>)> {
+    let lines : Vec<&str> = input.lines().filter(|line| !line.trim().is_empty()).collect();
+    if lines.len() != 2 {
+        return Err(format!("expected two wires, one per line, found {}", lines.len()).into());
+    }
+    Ok((compute_wire_cmds(lines[0])?, compute_wire_cmds(lines[1])?))
+}
 
-    let hashpos1 = compute_position_hash( &wirecmds1 );
-    let hashpos2 = compute_position_hash( &wirecmds2 );
+fn compute_min_dist(wirecmds1: &[WireCmd], wirecmds2: &[WireCmd]) -> (i32, i32) {
+    let hashpos1 = compute_position_hash( wirecmds1 );
+    let hashpos2 = compute_position_hash( wirecmds2 );
 
     let mut min_dist = 999999;
     let mut min_wiredist = 99999;
     for (key, wiredist1) in &hashpos1 {
-        match hashpos2.get(key) {
-            Some(wiredist2) => {
-                let int_dist = key.0.abs() + key.1.abs();
-                //println!("Intersection: {:?}, Dist: {}", intersection, int_dist);
-                min_dist = std::cmp::min( min_dist, int_dist );
-                
-                let wired_dist = wiredist1 + wiredist2;
-                min_wiredist = std::cmp::min( min_wiredist, wired_dist );
-            },
-            None => ()
+        if let Some(wiredist2) = hashpos2.get(key) {
+            let int_dist = key.0.abs() + key.1.abs();
+            //println!("Intersection: {:?}, Dist: {}", intersection, int_dist);
+            min_dist = std::cmp::min( min_dist, int_dist );
+            
+            let wired_dist = wiredist1 + wiredist2;
+            min_wiredist = std::cmp::min( min_wiredist, wired_dist );
         }
     }
     //println!("=================================");
     (min_dist, min_wiredist)
 }
 
-fn aoc_daythree(input: &str) {
-    let dist = compute_min_dist(&input);
-    println!("Min Dist {}, Min Wire Dist {}", dist.0, dist.1);
+struct AocDayThree;
+
+impl Solver for AocDayThree {
+    type Input = (Vec<WireCmd>, Vec<WireCmd>);
+
+    fn parse(&self, input: &str) -> PuzzleResult<(Vec<WireCmd>, Vec<WireCmd>)> {
+        parse_wires(input)
+    }
+
+    fn part1(&self, (wire1, wire2): &(Vec<WireCmd>, Vec<WireCmd>)) -> PuzzleResult<Answer> {
+        Ok(compute_min_dist(wire1, wire2).0.into())
+    }
+
+    fn part2(&self, (wire1, wire2): &(Vec<WireCmd>, Vec<WireCmd>)) -> PuzzleResult<Answer> {
+        Ok(compute_min_dist(wire1, wire2).1.into())
+    }
 }
 
 fn vet_password(pass: u32, must_find_pair : bool) -> bool {
@@ -543,11 +1060,11 @@ fn vet_password(pass: u32, must_find_pair : bool) -> bool {
         found_pair = true;
     }
 
-    if found_dupe == false {
+    if !found_dupe {
         return false;
     }
 
-    if must_find_pair == true && found_pair == false {
+    if must_find_pair && !found_pair {
         return false;
     }
 
@@ -559,7 +1076,7 @@ fn aoc_dayfour_range(begin: u32, end: u32, must_find_pair : bool) -> u32 {
 
     // The value is within the range given in your puzzle input.
     for pass in begin..end {
-        if vet_password(pass, must_find_pair) == true {
+        if vet_password(pass, must_find_pair) {
             valid_passwords += 1;
         }
     }
@@ -567,35 +1084,64 @@ fn aoc_dayfour_range(begin: u32, end: u32, must_find_pair : bool) -> u32 {
     valid_passwords
 }
 
-fn aoc_dayfour(input: &str, must_find_pair : bool) {
-    let v: Vec<&str> = input.split_terminator('-').collect();
-    let begin : u32 = v[0].parse().unwrap();
-    let end : u32 = v[1].parse().unwrap();
-    
-    let count = aoc_dayfour_range(begin, end, must_find_pair);
-    println!("Valid passwords between {}-{} = {}", begin, end, count);
+struct AocDayFour;
+
+impl Solver for AocDayFour {
+    type Input = (u32, u32);
+
+    fn parse(&self, input: &str) -> PuzzleResult<(u32, u32)> {
+        let (begin, end) = input.trim().split_once('-').ok_or("expected a range like 123456-654321")?;
+        Ok((begin.parse()?, end.parse()?))
+    }
+
+    fn part1(&self, &(begin, end): &(u32, u32)) -> PuzzleResult<Answer> {
+        Ok(aoc_dayfour_range(begin, end, false).into())
+    }
+
+    fn part2(&self, &(begin, end): &(u32, u32)) -> PuzzleResult<Answer> {
+        Ok(aoc_dayfour_range(begin, end, true).into())
+    }
 }
 
-fn aoc_dayfive(input: &str, input_value: i64) {
-    let intopcodes = compute_intopcodes_from_string(input);
-    let output = get_intopcode_output(&intopcodes, input_value);
+struct AocDayFive;
+
+impl Solver for AocDayFive {
+    type Input = Vec<i64>;
+
+    fn parse(&self, input: &str) -> PuzzleResult<Vec<i64>> {
+        parse_intopcodes(input)
+    }
+
+    // system ID 1 is the air conditioner unit
+    fn part1(&self, intopcodes: &Vec<i64>) -> PuzzleResult<Answer> {
+        diagnostic_code(&get_intopcode_output(intopcodes, 1)?)
+    }
 
-    println!("Output={:?}", output);
+    // system ID 5 is the thermal radiator controller
+    fn part2(&self, intopcodes: &Vec<i64>) -> PuzzleResult<Answer> {
+        diagnostic_code(&get_intopcode_output(intopcodes, 5)?)
+    }
 }
 
-fn parse_orbits_and_planets(input: &str) -> (Vec<(&str, &str)>, HashSet<&str>) {
+// (center, orbiting) pairs and every planet named in them
+type OrbitsAndPlanets<'a> = (Vec<(&'a str, &'a str)>, HashSet<&'a str>);
+
+fn parse_orbits_and_planets(input: &str) -> PuzzleResult<OrbitsAndPlanets<'_>> {
     let mut orbits = Vec::new();
     let mut planets = HashSet::new();
 
     let lines = input.lines();
     for line in lines {
-        let v: Vec<&str> = line.trim().split_terminator(')').collect();
+        let v: Vec<&str> = line.trim().split(')').collect();
+        if v.len() != 2 || v.iter().any(|planet| planet.is_empty()) {
+            return Err(format!("expected an orbit like 'COM)B', found '{}'", line.trim()).into());
+        }
         planets.insert( v[0] );
         planets.insert( v[1] );
         orbits.push( ( v[0], v[1] ) );
     }
 
-    (orbits, planets)
+    Ok((orbits, planets))
 }
 
 fn compute_orbits(orbits: &Vec<(&str, &str)>, planet: &str) -> u32 {
@@ -611,31 +1157,45 @@ fn compute_orbits(orbits: &Vec<(&str, &str)>, planet: &str) -> u32 {
 fn sum_all_orbits(orbits: &Vec<(&str, &str)>, planets: &HashSet<&str>) -> u32 {
     let mut total_orbits = 0;
     for p in planets {
-        let num_orbits = compute_orbits(&orbits, p);
+        let num_orbits = compute_orbits(orbits, p);
         total_orbits += num_orbits;
     }
     total_orbits
 }
 
-fn aoc_daysix(input: &str) {
-    let (orbits, planets) = parse_orbits_and_planets(input);
-    let total_orbits = sum_all_orbits(&orbits, &planets);
+struct AocDaySix;
+
+impl Solver for AocDaySix {
+    // orbits borrow from the input, so each part parses it again
+    type Input = String;
 
-    println!("Total Orbits {}", total_orbits);
+    fn parse(&self, input: &str) -> PuzzleResult<String> {
+        parse_orbits_and_planets(input)?;
+        Ok(input.to_string())
+    }
+
+    fn part1(&self, input: &String) -> PuzzleResult<Answer> {
+        let (orbits, planets) = parse_orbits_and_planets(input)?;
+        Ok(sum_all_orbits(&orbits, &planets).into())
+    }
+
+    fn part2(&self, input: &String) -> PuzzleResult<Answer> {
+        Ok(orbital_distance(input, "YOU", "SAN")?.into())
+    }
 }
 
 fn build_planet_hierarchy(orbits: &Vec<(&str, &str)>, planet: &str, hierarchy: &mut HashSet<String>) {
     for o in orbits {
         if o.1 == planet {
             hierarchy.insert(String::from(o.0));
-            build_planet_hierarchy(&orbits, o.0, hierarchy);
+            build_planet_hierarchy(orbits, o.0, hierarchy);
             break;
         }
     }
 }
 
-fn orbital_distance(input: &str, planet1: &str, planet2: &str) -> u32 {
-    let (orbits, _planets) = parse_orbits_and_planets(input);
+fn orbital_distance(input: &str, planet1: &str, planet2: &str) -> PuzzleResult<u32> {
+    let (orbits, _planets) = parse_orbits_and_planets(input)?;
     let mut p1_hierarchy = HashSet::new();
     let mut p2_hierarchy = HashSet::new();
     build_planet_hierarchy(&orbits, planet1, &mut p1_hierarchy );
@@ -645,27 +1205,37 @@ fn orbital_distance(input: &str, planet1: &str, planet2: &str) -> u32 {
     //println!("P2: {}, Hier: {:?}", planet2, p2_hierarchy);
     let difference = p1_hierarchy.symmetric_difference( &p2_hierarchy );
     //println!("SYMDIFF {:?}", difference);
-    let distance : u32 = difference.count().try_into().unwrap();
-    distance
+    let distance : u32 = difference.count().try_into()?;
+    Ok(distance)
 }
 
-fn aoc_daysix_parttwo(input: &str) {
-    let distance = orbital_distance(input, "YOU", "SAN");
-    println!("Distance: {}", distance);
-}
+struct AocDaySeven;
 
-fn aoc_dayseven(input: &str) {
-    let intopcode = compute_intopcodes_from_string(input);
-    let phases = [0,1,2,3,4];
-    let output = compute_max_amp_intopcode(&intopcode, &phases, false);
-    println!("MaxOutput: {}", output);
-}
+impl Solver for AocDaySeven {
+    type Input = Vec<i64>;
 
-fn aoc_dayseven_parttwo(input: &str) {
-    let intopcode = compute_intopcodes_from_string(input);
-    let phases = [5,6,7,8,9];
-    let output = compute_max_amp_intopcode(&intopcode, &phases, true);
-    println!("MaxOutput: {}", output);
+    fn parse(&self, input: &str) -> PuzzleResult<Vec<i64>> {
+        parse_intopcodes(input)
+    }
+
+    fn part1(&self, intopcode: &Vec<i64>) -> PuzzleResult<Answer> {
+        Ok(self.solve_part(intopcode, 1)?.0)
+    }
+
+    fn part2(&self, intopcode: &Vec<i64>) -> PuzzleResult<Answer> {
+        Ok(self.solve_part(intopcode, 2)?.0)
+    }
+
+    fn solve_part(&self, intopcode: &Vec<i64>, part: u32) -> PuzzleResult<(Answer, Details)> {
+        let (phases, feedback) = match part {
+            1 => ([0,1,2,3,4], false),
+            2 => ([5,6,7,8,9], true),
+            _ => return Err(format!("there is no part {}", part).into()),
+        };
+        let best = find_best_phases(intopcode, &phases, feedback)?.ok_or("no phase settings to try")?;
+        let phases : Vec<String> = best.phases.iter().map(|phase| phase.to_string()).collect();
+        Ok((best.output.into(), vec![("phases", phases.join(","))]))
+    }
 }
 
 struct ElfImage {
@@ -678,7 +1248,7 @@ impl ElfImage {
     fn from_input(height: u32, width: u32, input: &str) -> ElfImage {
         let mut layers : Vec<Vec<u32>> = Vec::new();
         let mut parsed_input = input;
-        while parsed_input.len() > 0 {
+        while !parsed_input.is_empty() {
             let (transmission, remaining_input) = parsed_input.split_at( (height * width).try_into().unwrap() );
             parsed_input = remaining_input;
             
@@ -730,32 +1300,59 @@ impl ElfImage {
     }
 }
 
-fn aoc_dayeight(input: &str)
-{
-    let img = ElfImage::from_input(6, 25, input);
-    let layer_str = img.layer_with_min_character(0);
-    let one_count = layer_str.iter().filter(|&n| *n == 1).count();
-    let two_count = layer_str.iter().filter(|&n| *n == 2).count();
-    println!("Min 0 layer {:?}, 1 count * 2 count = {}", layer_str, one_count * two_count);
-}
+struct AocDayEight;
 
-fn aoc_dayeight_parttwo(input: &str)
-{
-    let img = ElfImage::from_input(6, 25, input);
-    let decoded = img.decode_image();
-    for h in 0..img.height {
-        for w in 0..img.width {
-            let index : usize = (w + h * img.width).try_into().unwrap();
-            print!( "{}", if decoded[index] == 1 { '#' } else { ' ' } );
+impl Solver for AocDayEight {
+    type Input = ElfImage;
+
+    fn parse(&self, input: &str) -> PuzzleResult<ElfImage> {
+        let input = input.trim();
+        if input.is_empty() || input.len() % (6 * 25) != 0 || !input.chars().all(|c| c.is_ascii_digit()) {
+            return Err("expected layers of 6x25 digits".into());
         }
-        println!("");
+        Ok(ElfImage::from_input(6, 25, input))
+    }
+
+    fn part1(&self, img: &ElfImage) -> PuzzleResult<Answer> {
+        let layer_str = img.layer_with_min_character(0);
+        let one_count = layer_str.iter().filter(|&n| *n == 1).count();
+        let two_count = layer_str.iter().filter(|&n| *n == 2).count();
+        Ok((one_count * two_count).into())
+    }
+
+    fn part2(&self, img: &ElfImage) -> PuzzleResult<Answer> {
+        let decoded = img.decode_image();
+        let mut rows = Vec::new();
+        for h in 0..img.height {
+            let mut row = String::new();
+            for w in 0..img.width {
+                let index : usize = (w + h * img.width).try_into().unwrap();
+                row.push( if decoded[index] == 1 { '#' } else { ' ' } );
+            }
+            rows.push(row);
+        }
+        Ok(rows.join("\n").into())
     }
 }
 
-fn aoc_daynine(input: &str, input_value: i64) {
-    let intopcode = compute_intopcodes_from_string(input);
-    let output = get_intopcode_output(&intopcode, input_value);
-    println!("Output {:?}", output);
+struct AocDayNine;
+
+impl Solver for AocDayNine {
+    type Input = Vec<i64>;
+
+    fn parse(&self, input: &str) -> PuzzleResult<Vec<i64>> {
+        parse_intopcodes(input)
+    }
+
+    // test mode, outputs the BOOST keycode
+    fn part1(&self, intopcode: &Vec<i64>) -> PuzzleResult<Answer> {
+        diagnostic_code(&get_intopcode_output(intopcode, 1)?)
+    }
+
+    // sensor boost mode, outputs the coordinates of the distress signal
+    fn part2(&self, intopcode: &Vec<i64>) -> PuzzleResult<Answer> {
+        diagnostic_code(&get_intopcode_output(intopcode, 2)?)
+    }
 }
 
 #[derive(Debug)]
@@ -768,10 +1365,9 @@ fn parse_asteroid_field(input: &str) -> Vec<Asteroid> {
     let mut pos = Vector2::new( 0, 0 );
     for l in input.lines() {
         for c in l.trim().chars() {
-            match c {
-                '#' => asteroids.push( Asteroid{ pos } ),
-                _ => ()
-            };
+            if c == '#' {
+                asteroids.push( Asteroid{ pos } );
+            }
             pos.x += 1;
         }
         pos.x = 0;
@@ -802,8 +1398,8 @@ fn compute_asteroid_los_hashmap<'a>(src: &'a Asteroid, field: &'a Vec<Asteroid>)
         }
         else {
             let mut insert_index = asteroid_vec.len();
-            for i in 0..asteroid_vec.len() {
-                let iv = asteroid_vec[i].pos - src.pos;
+            for (i, a) in asteroid_vec.iter().enumerate() {
+                let iv = a.pos - src.pos;
                 let idist = iv.x.abs() + iv.y.abs();
                 if idist < dist {
                     insert_index = i;
@@ -820,13 +1416,13 @@ fn compute_asteroid_los_hashmap<'a>(src: &'a Asteroid, field: &'a Vec<Asteroid>)
 fn compute_all_asteroid_los_count(field: &Vec<Asteroid>) -> Vec<u32> {
     let mut counts = Vec::new();
     for a in field {
-        counts.push( compute_asteroid_los_hashmap(&a, &field).keys().count().try_into().unwrap() );
+        counts.push( compute_asteroid_los_hashmap(a, field).keys().count().try_into().unwrap() );
     }
     counts
 }
 
 fn compute_max_asteroid_los_count(field: &Vec<Asteroid>) -> (&Asteroid, u32) {
-    let counts = compute_all_asteroid_los_count(&field);
+    let counts = compute_all_asteroid_los_count(field);
     let mut max_i = 0;
     for i in 1..counts.len() {
         if counts[max_i] < counts[i] {
@@ -842,12 +1438,12 @@ fn compute_asteroid_destruction_order<'a>(src: &'a Asteroid, field: &'a Vec<Aste
 
     let mut sorted_keys : Vec<Vector2<i32>> = los_hashmap.keys().copied().collect();
     sorted_keys.sort_by(|&a, &b| { 
-        let ay : f64 = (-a.y).try_into().unwrap();
-        let ax : f64 = a.x.try_into().unwrap();
+        let ay : f64 = (-a.y).into();
+        let ax : f64 = a.x.into();
         let mut atan = ax.atan2( ay );
         if atan < 0.0 { atan += 2.0 * std::f64::consts::PI; }
-        let by : f64 = (-b.y).try_into().unwrap();
-        let bx : f64 = b.x.try_into().unwrap();
+        let by : f64 = (-b.y).into();
+        let bx : f64 = b.x.into();
         let mut btan = bx.atan2( by );
         if btan < 0.0 { btan += 2.0 * std::f64::consts::PI; }
 
@@ -857,18 +1453,15 @@ fn compute_asteroid_destruction_order<'a>(src: &'a Asteroid, field: &'a Vec<Aste
         let mut found_asteroid = false;
 
         for k in sorted_keys.iter() {
-            match los_hashmap.get_mut(k) {
-                Some(asteroids) => {
-                    if !asteroids.is_empty() {
-                        asteroid_do.push( asteroids.pop().unwrap() );
-                        found_asteroid = true;
-                    }
-                },
-                None => ()
+            if let Some(asteroids) = los_hashmap.get_mut(k) {
+                if !asteroids.is_empty() {
+                    asteroid_do.push( asteroids.pop().unwrap() );
+                    found_asteroid = true;
+                }
             }
         }
 
-        if found_asteroid == false {
+        if !found_asteroid {
             break;
         }
     }
@@ -876,17 +1469,41 @@ fn compute_asteroid_destruction_order<'a>(src: &'a Asteroid, field: &'a Vec<Aste
     asteroid_do
 }
 
-fn aoc_dayten(input: &str) {
-    let asteroids = parse_asteroid_field(&input);
-    let (a, max) = compute_max_asteroid_los_count(&asteroids);
-    println!("Max Count {} from {:?}", max, a.pos);
-}
+struct AocDayTen;
+
+impl Solver for AocDayTen {
+    type Input = Vec<Asteroid>;
+
+    fn parse(&self, input: &str) -> PuzzleResult<Vec<Asteroid>> {
+        let asteroids = parse_asteroid_field(input);
+        if asteroids.is_empty() {
+            return Err("there are no asteroids".into());
+        }
+        Ok(asteroids)
+    }
+
+    fn part1(&self, asteroids: &Vec<Asteroid>) -> PuzzleResult<Answer> {
+        Ok(self.solve_part(asteroids, 1)?.0)
+    }
 
-fn aoc_dayten_parttwo(input: &str) {
-    let asteroids = parse_asteroid_field(&input);
-    let (a, _max) = compute_max_asteroid_los_count(&asteroids);
-    let destruction_order = compute_asteroid_destruction_order(&a, &asteroids);
-    println!("200th {:?} => {}", destruction_order[199], destruction_order[199].pos.x * 100 + destruction_order[199].pos.y);
+    fn part2(&self, asteroids: &Vec<Asteroid>) -> PuzzleResult<Answer> {
+        Ok(self.solve_part(asteroids, 2)?.0)
+    }
+
+    fn solve_part(&self, asteroids: &Vec<Asteroid>, part: u32) -> PuzzleResult<(Answer, Details)> {
+        let (a, max) = compute_max_asteroid_los_count(asteroids);
+        let station = ("station", format!("{},{}", a.pos.x, a.pos.y));
+        match part {
+            1 => Ok((max.into(), vec![station])),
+            2 => {
+                let destruction_order = compute_asteroid_destruction_order(a, asteroids);
+                let asteroid = destruction_order.get(199).ok_or("fewer than 200 asteroids get destroyed")?;
+                let details = vec![station, ("200th", format!("{},{}", asteroid.pos.x, asteroid.pos.y))];
+                Ok(((asteroid.pos.x * 100 + asteroid.pos.y).into(), details))
+            },
+            _ => Err(format!("there is no part {}", part).into()),
+        }
+    }
 }
 
 enum RobotDirection {
@@ -896,93 +1513,121 @@ enum RobotDirection {
     Right
 }
 
-fn paint_tiles(intopcode: &Vec<i64>, starting_tile: i64) -> HashMap<Vector2<i32>, i64> {
-    let mut input_values = VecDeque::new();
-    input_values.push_back(starting_tile);
+// The hull painting robot as seen by its program: it reports the color under it and
+// receives pairs of (color to paint, direction to turn) before moving forward
+struct PaintRobot {
+    dir: RobotDirection,
+    pos: Vector2<i32>,
+    painted_tiles: HashMap<Vector2<i32>, i64>,
+    outputs: Vec<i64>,
+}
 
-    let mut comp = IntOpCodeComp::new(&intopcode, &input_values);
-    let mut robot_dir = RobotDirection::Up;
-    let mut robot_pos : Vector2<i32> = Vector2::new(0, 0);
-    let mut painted_tiles : HashMap<Vector2<i32>, i64> = HashMap::new();
+impl InputDevice for PaintRobot {
+    fn read(&mut self) -> Option<i64> {
+        // provide the color at the current position
+        // if we have a painted_tile, use that value
+        // otherwise use black (0)
+        Some( *self.painted_tiles.get( &self.pos ).unwrap_or( &0 ) )
+    }
+}
 
-    while comp.complete == false
-    {
-        comp.tick();
-        
-        if comp.output_values.len() == 2
+impl OutputDevice for PaintRobot {
+    fn write(&mut self, value: i64) {
+        self.outputs.push(value);
+
+        if self.outputs.len() == 2
         {
             // paint_output: 0=black, 1=white
-            let paint_output = comp.output_values.pop_front().unwrap();
-            painted_tiles.insert( robot_pos, paint_output );
+            let paint_output = self.outputs[0];
+            self.painted_tiles.insert( self.pos, paint_output );
             
             // turn_output: 0=left 90, 1=right 90. Move forward 1 after turn.
-            let turn_output = comp.output_values.pop_front().unwrap();
-            match robot_dir {
+            let turn_output = self.outputs[1];
+            self.outputs.clear();
+            match self.dir {
                 RobotDirection::Up => {
                     if turn_output == 1 {
-                        robot_dir = RobotDirection::Right;
+                        self.dir = RobotDirection::Right;
                     }
                     else {
-                        robot_dir = RobotDirection::Left;
+                        self.dir = RobotDirection::Left;
                     }
                 },
                 RobotDirection::Down => {
                     if turn_output == 1 {
-                        robot_dir = RobotDirection::Left;
+                        self.dir = RobotDirection::Left;
                     }
                     else {
-                        robot_dir = RobotDirection::Right;
+                        self.dir = RobotDirection::Right;
                     }
                 },
                 RobotDirection::Right => {
                     if turn_output == 1 {
-                        robot_dir = RobotDirection::Down;
+                        self.dir = RobotDirection::Down;
                     }
                     else {
-                        robot_dir = RobotDirection::Up;
+                        self.dir = RobotDirection::Up;
                     }
                 },
                 RobotDirection::Left => {
                     if turn_output == 1 {
-                        robot_dir = RobotDirection::Up;
+                        self.dir = RobotDirection::Up;
                     }
                     else {
-                        robot_dir = RobotDirection::Down;
+                        self.dir = RobotDirection::Down;
                     }
                 },
             }
 
             // move forward one
-            match robot_dir {
-                RobotDirection::Up      => robot_pos.y += 1,
-                RobotDirection::Down    => robot_pos.y -= 1,
-                RobotDirection::Right   => robot_pos.x += 1,
-                RobotDirection::Left    => robot_pos.x -= 1,
-            }
-
-            // provide new input at current position
-            // if we have a painted_tile, use that value
-            // otherwise use black (0)
-            match painted_tiles.get( &robot_pos ) {
-                Some( &tile ) => comp.input_values.push_back( tile ),
-                None => comp.input_values.push_back( 0 )
+            match self.dir {
+                RobotDirection::Up      => self.pos.y += 1,
+                RobotDirection::Down    => self.pos.y -= 1,
+                RobotDirection::Right   => self.pos.x += 1,
+                RobotDirection::Left    => self.pos.x -= 1,
             }
         }
     }
+}
 
-    painted_tiles.clone()
+fn paint_tiles(intopcode: &[i64], starting_tile: i64) -> Result<HashMap<Vector2<i32>, i64>, IntOpCodeError> {
+    let mut input_values = VecDeque::new();
+    input_values.push_back(starting_tile);
+
+    let mut comp = IntOpCodeComp::new(intopcode, &input_values);
+    let mut robot = PaintRobot {
+        dir: RobotDirection::Up,
+        pos: Vector2::new(0, 0),
+        painted_tiles: HashMap::new(),
+        outputs: Vec::new(),
+    };
+    comp.run_with_device(&mut robot)?;
+
+    Ok(robot.painted_tiles)
 }
 
-fn aoc_dayeleven(input: &str) {
-    // create computer for the painting bot
-    let intopcode = compute_intopcodes_from_string(input);
-    let painted_tiles = paint_tiles(&intopcode, 0);
-    println!("Painted Tiles: {}", painted_tiles.len());
+struct AocDayEleven;
+
+impl Solver for AocDayEleven {
+    type Input = Vec<i64>;
+
+    fn parse(&self, input: &str) -> PuzzleResult<Vec<i64>> {
+        parse_intopcodes(input)
+    }
+
+    fn part1(&self, intopcode: &Vec<i64>) -> PuzzleResult<Answer> {
+        let painted_tiles = paint_tiles(intopcode, 0)?;
+        Ok(painted_tiles.len().into())
+    }
+
+    fn part2(&self, intopcode: &Vec<i64>) -> PuzzleResult<Answer> {
+        Ok(aoc_dayeleven_parttwo(intopcode)?.into())
+    }
 }
 
-fn aoc_dayeleven_parttwo(input: &str) {
-    let intopcode = compute_intopcodes_from_string(input);
-    let painted_tiles = paint_tiles(&intopcode, 1);
+// The registration identifier the robot paints when it starts on a white panel
+fn aoc_dayeleven_parttwo(intopcode: &[i64]) -> Result<String, IntOpCodeError> {
+    let painted_tiles = paint_tiles(intopcode, 1)?;
 
     // Visualize the hashmap. First find the extents
     let mut min : Vector2<i32> = Vector2::new(0, 0);
@@ -994,24 +1639,25 @@ fn aoc_dayeleven_parttwo(input: &str) {
         max.y = std::cmp::max(max.y, key.y);
     }
 
-    println!("Painted Tiles: {}, Min {:?}, Max {:?}", painted_tiles.len(), min, max);
-
+    let mut rows = Vec::new();
     for y in (min.y..=max.y).rev() { // reverse to properly flip the values, make sure last value is inclusive as well
+        let mut row = String::new();
         for x in min.x..=max.x {
             match painted_tiles.get(&Vector2::new(x, y)) {
                 Some(&tile) => {
                     if tile == 1 {
-                        print!("#");
+                        row.push('#');
                     }
                     else {
-                        print!(".");
+                        row.push('.');
                     }
                 },
-                None => { print!(".") }
+                None => { row.push('.') }
             }
         }
-        println!();
+        rows.push(row);
     }
+    Ok(rows.join("\n"))
 }
 
 #[derive(Debug)]
@@ -1025,14 +1671,22 @@ struct Moon {
 }
 
 impl Moon {
-    pub fn new(line: &str) -> Moon {
+    pub fn new(line: &str) -> PuzzleResult<Moon> {
         // Format should be like <x=12, y=0, z=-15>
-        let v: Vec<&str> = line.split(|c| c == ',' || c == '=').collect();
-        let moon = Moon { 
-            pos : Vector3::new( v[1].parse().unwrap(), v[3].parse().unwrap(), v[5][..(v[5].len()-1)].parse().unwrap() ),
+        let invalid = || format!("expected a moon like '<x=12, y=0, z=-15>', found '{}'", line.trim());
+        let coordinates = line.trim().strip_prefix('<').and_then(|l| l.strip_suffix('>')).ok_or_else(invalid)?;
+        let v: Vec<&str> = coordinates.split(',').collect();
+        if v.len() != 3 {
+            return Err(invalid().into());
+        }
+        let mut pos = Vector3::new( 0, 0, 0 );
+        for (i, (coordinate, axis)) in v.iter().zip(["x=", "y=", "z="]).enumerate() {
+            pos[i] = coordinate.trim().strip_prefix(axis).and_then(|value| value.parse().ok()).ok_or_else(invalid)?;
+        }
+        Ok(Moon { 
+            pos,
             vel : Vector3::new( 0, 0, 0 )
-        };
-        moon
+        })
     }
 
     pub fn potential_energy(&self) -> i32 {
@@ -1048,7 +1702,7 @@ impl Moon {
     }
 }
 
-fn sum_total_energy(moons: &Vec<Moon>) -> i32 {
+fn sum_total_energy(moons: &[Moon]) -> i32 {
     moons.iter().map( |m| m.total_energy() ).sum()
 }
 
@@ -1077,10 +1731,10 @@ fn step_moon_simulation(moons: &mut Vec<Moon>) {
     }
 }
 
-fn find_cycle_rate_for_axis(moon_base: &Vec<Moon>, axis_idx : usize) -> i64 {    
+fn find_cycle_rate_for_axis(moon_base: &[Moon], axis_idx : usize) -> i64 {    
     let mut state_hash = HashSet::new();
 
-    let mut moons = moon_base.clone();
+    let mut moons = moon_base.to_vec();
     let mut iteration : i64 = 0;
 
     loop {
@@ -1105,7 +1759,7 @@ fn find_cycle_rate_for_axis(moon_base: &Vec<Moon>, axis_idx : usize) -> i64 {
             moon.pos[axis_idx] += moon.vel[axis_idx];
         }
 
-        if state_hash.insert(moons.clone()) == false {
+        if !state_hash.insert(moons.clone()) {
             return iteration
         }
 
@@ -1113,68 +1767,64 @@ fn find_cycle_rate_for_axis(moon_base: &Vec<Moon>, axis_idx : usize) -> i64 {
     }
 }
 
-fn find_min_repeat_cycle(moon_base: &Vec<Moon>) -> i64 {
+fn find_min_repeat_cycle(moon_base: &[Moon]) -> i64 {
     // cycles on each axis are independent of one another
-    let x_cycle = find_cycle_rate_for_axis(&moon_base, 0);
-    let y_cycle = find_cycle_rate_for_axis(&moon_base, 1);
-    let z_cycle = find_cycle_rate_for_axis(&moon_base, 2);
+    let x_cycle = find_cycle_rate_for_axis(moon_base, 0);
+    let y_cycle = find_cycle_rate_for_axis(moon_base, 1);
+    let z_cycle = find_cycle_rate_for_axis(moon_base, 2);
 
     // min repeat cycle is just the lcm between these values
     x_cycle.lcm( &y_cycle ).lcm( &z_cycle )
 }
 
-fn aoc_daytwelve(input: &str) {
-    let mut moons : Vec<Moon> = Vec::new();
-    for line in input.lines() {
-        moons.push( Moon::new( &line ));
-    }
+struct AocDayTwelve;
+
+impl Solver for AocDayTwelve {
+    type Input = Vec<Moon>;
 
-    // simulate 1000 times
-    for _ in 0..1000 {
-        step_moon_simulation(&mut moons);
+    fn parse(&self, input: &str) -> PuzzleResult<Vec<Moon>> {
+        let mut moons : Vec<Moon> = Vec::new();
+        for line in input.lines().filter(|line| !line.trim().is_empty()) {
+            moons.push( Moon::new( line )? );
+        }
+        Ok(moons)
     }
 
-    let total_energy = sum_total_energy(&moons);
-    println!("Total Energy {}", total_energy);
-}
+    fn part1(&self, moons: &Vec<Moon>) -> PuzzleResult<Answer> {
+        let mut moons = moons.clone();
+
+        // simulate 1000 times
+        for _ in 0..1000 {
+            step_moon_simulation(&mut moons);
+        }
 
-fn aoc_daytwelve_part2(input: &str) {
-    let mut moons : Vec<Moon> = Vec::new();
-    for line in input.lines() {
-        moons.push( Moon::new( &line ));
+        Ok(sum_total_energy(&moons).into())
     }
 
-    let min_cycle = find_min_repeat_cycle( &moons );
-    // find the lcm between the cycles to find the 
-    println!("Min Cycle {}", min_cycle);
+    fn part2(&self, moons: &Vec<Moon>) -> PuzzleResult<Answer> {
+        // find the lcm between the cycles of each axis
+        Ok(find_min_repeat_cycle( moons ).into())
+    }
 }
 
-fn tick_block_game(comp: &mut IntOpCodeComp, score: &mut i64, screen_tiles: &mut HashMap<Vector2<i64>, i64>) {
-    
-    while comp.complete == false
-    {
-        comp.tick();
-        
-        if comp.output_values.len() >= 3
-        {
-            let pos = Vector2::new( comp.output_values.pop_front().unwrap(),
-                                    comp.output_values.pop_front().unwrap() );
-            if pos.x == -1 && pos.y == 0 {
-                *score = comp.output_values.pop_front().unwrap();
-            }
-            else {
-                let tile_id = comp.output_values.pop_front().unwrap();
-
-                // Add to the hash
-                screen_tiles.insert( pos, tile_id );
-            }
+fn tick_block_game(comp: &mut IntOpCodeComp, score: &mut i64, screen_tiles: &mut HashMap<Vector2<i64>, i64>) -> Result<(), IntOpCodeError> {
+    // Every draw is an (x, y, tile) triple
+    let mut screen = ChunkedOutput::new(3, |outputs| {
+        let pos = Vector2::new( outputs[0], outputs[1] );
+        if pos.x == -1 && pos.y == 0 {
+            *score = outputs[2];
         }
+        else {
+            let tile_id = outputs[2];
 
-        if comp.waiting_on_input == true {
-            break;
+            // Add to the hash
+            screen_tiles.insert( pos, tile_id );
         }
+    });
 
-    }
+    // Draw until the game wants a joystick input or ends
+    comp.run_with_devices(&mut VecDeque::new(), &mut screen)?;
+    Ok(())
 }
 
 fn count_blocks(screen_tiles: &HashMap<Vector2<i64>, i64>) -> usize {
@@ -1218,18 +1868,21 @@ fn get_tile_pos( screen_tiles: &HashMap<Vector2<i64>, i64>, tile: i64 ) -> Optio
     None
 }
 
-fn play_block_game(intopcode: &Vec<i64>, auto_play: bool) -> HashMap<Vector2<i64>, i64> {
+// Plays until the game ends, returning the final screen and score. Draws every frame if visualize is set.
+fn play_block_game(intopcode: &[i64], auto_play: bool, visualize: bool) -> Result<(HashMap<Vector2<i64>, i64>, i64), IntOpCodeError> {
     let input_values = VecDeque::new();
 
-    let mut comp = IntOpCodeComp::new(&intopcode, &input_values);
+    let mut comp = IntOpCodeComp::new(intopcode, &input_values);
     let mut screen_tiles : HashMap<Vector2<i64>, i64> = HashMap::new();
     let mut score = 0;
 
-    while comp.complete == false {
-        tick_block_game(&mut comp, &mut score, &mut screen_tiles);
-        visualize_game(&screen_tiles, score);
+    while comp.state != RunState::Halted {
+        tick_block_game(&mut comp, &mut score, &mut screen_tiles)?;
+        if visualize {
+            visualize_game(&screen_tiles, score);
+        }
 
-        if comp.waiting_on_input == true {
+        if comp.state == RunState::WaitingOnInput {
             if auto_play {
                 // Align the paddle with the ball
                 let paddle_pos = get_tile_pos( &screen_tiles, 3 ).unwrap();
@@ -1251,7 +1904,7 @@ fn play_block_game(intopcode: &Vec<i64>, auto_play: bool) -> HashMap<Vector2<i64
         }
     }
 
-    screen_tiles
+    Ok((screen_tiles, score))
 }
 
 fn visualize_game(screen_tiles: &HashMap<Vector2<i64>, i64>, score: i64) {
@@ -1286,19 +1939,29 @@ fn visualize_game(screen_tiles: &HashMap<Vector2<i64>, i64>, score: i64) {
     println!("Score {}", score);
 }
 
-fn aoc_daythirteen(input: &str) {
-    let intopcode = compute_intopcodes_from_string(input);
-    let screen_tiles = play_block_game(&intopcode, false);
+struct AocDayThirteen;
 
-    // Day thirteen only cares about the current state of the blocks
-    println!("Block count {}", count_blocks(&screen_tiles));
-}
+impl Solver for AocDayThirteen {
+    type Input = Vec<i64>;
+
+    fn parse(&self, input: &str) -> PuzzleResult<Vec<i64>> {
+        parse_intopcodes(input)
+    }
+
+    fn part1(&self, intopcode: &Vec<i64>) -> PuzzleResult<Answer> {
+        let (screen_tiles, _score) = play_block_game(intopcode, false, false)?;
+
+        // Day thirteen only cares about the current state of the blocks
+        Ok(count_blocks(&screen_tiles).into())
+    }
 
-fn aoc_daythirteen_parttwo(input: &str) {
-    let mut intopcode = compute_intopcodes_from_string(input);
-    intopcode[0] = 2;   // set to 2 to enter free play
+    fn part2(&self, intopcode: &Vec<i64>) -> PuzzleResult<Answer> {
+        let mut intopcode = intopcode.clone();
+        intopcode[0] = 2;   // set to 2 to enter free play
 
-    play_block_game(&intopcode, true);
+        let (_screen_tiles, score) = play_block_game(&intopcode, true, false)?;
+        Ok(score.into())
+    }
 }
 
 #[derive(Debug)]
@@ -1308,31 +1971,52 @@ struct Reaction {
     output: (i64, String),
 }
 
-fn parse_reaction(line: &str) -> Reaction {
+// Amount and name of a chemical, e.g. "7 A". Amounts are positive since reactions are divided by them.
+fn parse_chemical(text: &str) -> Option<(i64, String)> {
+    let (amount, name) = text.trim().split_once(' ')?;
+    let amount = amount.parse().ok().filter(|&amount| amount > 0)?;
+    let name = name.trim();
+    if name.is_empty() || name.contains(' ') {
+        return None;
+    }
+    Some((amount, String::from(name)))
+}
+
+fn parse_reaction(line: &str) -> PuzzleResult<Reaction> {
     // Example: 7 A, 1 B => 1 C
-    let mut split_line = line.split_terminator("=>");
-    let inputs_str = split_line.next().unwrap().trim();
-    let output_str = split_line.next().unwrap().trim();
+    let invalid = || format!("expected a reaction like '7 A, 1 B => 1 C', found '{}'", line.trim());
+    let (inputs_str, output_str) = line.split_once("=>").ok_or_else(invalid)?;
 
     let mut inputs = Vec::new();
     for i in inputs_str.split_terminator(",") {
-        let mut input_str = (*i).trim().split_terminator(" ");
-        inputs.push( ( input_str.next().unwrap().parse().unwrap(), String::from(input_str.next().unwrap()) ) );
+        inputs.push( parse_chemical(i).ok_or_else(invalid)? );
     }
 
-    let mut out = output_str.split_terminator(" ");
-    let output = (out.next().unwrap().parse().unwrap(), String::from(out.next().unwrap()));
+    let output = parse_chemical(output_str).ok_or_else(invalid)?;
+    if inputs.is_empty() {
+        return Err(invalid().into());
+    }
 
-    Reaction { inputs, output }
+    Ok(Reaction { inputs, output })
 }
 
-fn parse_dayfourteeninput(input: &str) -> HashMap<String, Reaction> {
+fn parse_dayfourteeninput(input: &str) -> PuzzleResult<HashMap<String, Reaction>> {
     let mut hashmap = HashMap::new();
-    for line in input.lines() {
-        let reaction = parse_reaction(line);
+    for line in input.lines().filter(|line| !line.trim().is_empty()) {
+        let reaction = parse_reaction(line)?;
         hashmap.insert( reaction.output.1.clone(), reaction );
     }
-    hashmap
+
+    // everything but ORE has to be made by something, and ORE is only ever made into one thing at a time
+    for reaction in hashmap.values() {
+        if reaction.inputs.len() > 1 && reaction.inputs.iter().any(|input| input.1 == "ORE") {
+            return Err(format!("the reaction making {} mixes ORE with other inputs", reaction.output.1).into());
+        }
+        if let Some(input) = reaction.inputs.iter().find(|input| input.1 != "ORE" && !hashmap.contains_key(&input.1)) {
+            return Err(format!("no reaction makes {}", input.1).into());
+        }
+    }
+    Ok(hashmap)
 }
 
 fn update_for_type(reactions: &HashMap<String, Reaction>, resource: &String, amount : i64, resources_have: &mut HashMap<String, i64>) {
@@ -1356,7 +2040,7 @@ fn update_for_type(reactions: &HashMap<String, Reaction>, resource: &String, amo
             let mut res_remaining = *res_count - res_amount;
             if res_remaining < 0 {
                 // Need to generate more of this resource
-                update_for_type(reactions, &res_name, -res_remaining, resources_have);
+                update_for_type(reactions, res_name, -res_remaining, resources_have);
                 let res_count = resources_have[res_name];
                 res_remaining = res_count - res_amount;
             }
@@ -1379,20 +2063,35 @@ fn ore_needed_for_fuel(reactions: &HashMap<String, Reaction>, fuel_amount: i64)
     resources_have["ORE"]
 }
 
-fn aoc_dayfourteen(input: &str) {
-    let reaction_hashmap = parse_dayfourteeninput( input );
-    println!("ORE NEEDED {}", ore_needed_for_fuel(&reaction_hashmap, 1));
+struct AocDayFourteen;
+
+impl Solver for AocDayFourteen {
+    type Input = HashMap<String, Reaction>;
+
+    fn parse(&self, input: &str) -> PuzzleResult<HashMap<String, Reaction>> {
+        let reaction_hashmap = parse_dayfourteeninput( input )?;
+        if !reaction_hashmap.contains_key("FUEL") {
+            return Err("no reaction makes FUEL".into());
+        }
+        Ok(reaction_hashmap)
+    }
+
+    fn part1(&self, reaction_hashmap: &HashMap<String, Reaction>) -> PuzzleResult<Answer> {
+        Ok(ore_needed_for_fuel(reaction_hashmap, 1).into())
+    }
+
+    fn part2(&self, reaction_hashmap: &HashMap<String, Reaction>) -> PuzzleResult<Answer> {
+        Ok(aoc_dayfourteen_parttwo(reaction_hashmap).into())
+    }
 }
 
-fn aoc_dayfourtteen_parttwo(input: &str) {
-    // binary search the amount of fuel 10000000000 ore can produce.
-    let reaction_hashmap = parse_dayfourteeninput( input );
+fn aoc_dayfourteen_parttwo(reaction_hashmap: &HashMap<String, Reaction>) -> i64 {
+    // binary search the amount of fuel 1000000000000 ore can produce.
     let mut min_fuel : i64 = 1;
     let mut max_fuel : i64 = 1000000000000;
     while min_fuel < max_fuel {
         let fuel_check = ( max_fuel + min_fuel ) / 2;
-        let ore_needed = ore_needed_for_fuel(&reaction_hashmap, fuel_check );
-        println!("fuel_check {}, ore_needed {}", fuel_check, ore_needed);
+        let ore_needed = ore_needed_for_fuel(reaction_hashmap, fuel_check );
 
         if ore_needed == 1000000000000 {
             max_fuel = fuel_check;
@@ -1406,6 +2105,14 @@ fn aoc_dayfourtteen_parttwo(input: &str) {
             min_fuel = fuel_check + 1;
         }
     }
+
+    // the search can stop one past the most fuel there is ore for
+    if ore_needed_for_fuel(reaction_hashmap, min_fuel) > 1000000000000 {
+        min_fuel - 1
+    }
+    else {
+        min_fuel
+    }
 }
 
 fn parse_daysixteen_input(input: &str) -> Vec<i32> {
@@ -1413,19 +2120,18 @@ fn parse_daysixteen_input(input: &str) -> Vec<i32> {
     ret
 }
 
-fn process_frequency(freq: &Vec<i32>, offset: usize) -> Vec<i32> {
-    let mut new_freq : Vec<i32> = Vec::new();
-    new_freq.resize(freq.len(), 0);
+fn process_frequency(freq: &[i32], offset: usize) -> Vec<i32> {
+    let mut new_freq : Vec<i32> = vec![0; freq.len()];
 
-    for round in offset..freq.len() {
+    for (round, new_val) in new_freq.iter_mut().enumerate().skip(offset) {
         let mut idx : usize = round;
         let mut mult = 1;
         let idx_min = offset - std::cmp::min(offset, round);
         while idx < freq.len() {
             if idx >= idx_min {
                 let max_idx = std::cmp::min(idx+round+1, freq.len());
-                for nidx in idx..max_idx {
-                    new_freq[round] += mult * freq[nidx];
+                for f in &freq[idx..max_idx] {
+                    *new_val += mult * f;
                 }
             }
 
@@ -1433,15 +2139,14 @@ fn process_frequency(freq: &Vec<i32>, offset: usize) -> Vec<i32> {
             mult *= -1;
         }
 
-        new_freq[round] = (new_freq[round] % 10).abs();
+        *new_val = (*new_val % 10).abs();
     }
 
     new_freq
 }
 
-fn process_frequency_rev(freq: &Vec<i32>, offset: usize) -> Vec<i32> {
-    let mut new_freq : Vec<i32> = Vec::new();
-    new_freq.resize(freq.len(), 0);
+fn process_frequency_rev(freq: &[i32], offset: usize) -> Vec<i32> {
+    let mut new_freq : Vec<i32> = vec![0; freq.len()];
     new_freq[freq.len()-1] = freq[freq.len() - 1];
     for round in (offset..freq.len()-1).rev() {
         new_freq[round] = new_freq[round + 1];
@@ -1451,8 +2156,8 @@ fn process_frequency_rev(freq: &Vec<i32>, offset: usize) -> Vec<i32> {
     new_freq
 }
 
-fn process_frequencies(freq: &Vec<i32>, count: usize, offset: usize, rev: bool) -> Vec<i32> {
-    let mut new_freq = freq.clone();
+fn process_frequencies(freq: &[i32], count: usize, offset: usize, rev: bool) -> Vec<i32> {
+    let mut new_freq = freq.to_vec();
     for _c in 0..count {
         if rev {
             new_freq = process_frequency_rev(&new_freq, offset);
@@ -1464,36 +2169,61 @@ fn process_frequencies(freq: &Vec<i32>, count: usize, offset: usize, rev: bool)
     new_freq
 }
 
-fn compute_offset(freq: &Vec<i32>) -> usize {
+fn compute_offset(freq: &[i32]) -> usize {
     let mut offset : usize = 0;
-    for idx in 0..7 {
+    for f in &freq[..7] {
         offset *= 10;
-        offset += usize::try_from( freq[idx] ).unwrap();
+        offset += usize::try_from( *f ).unwrap();
     }
     offset
 }
 
-fn aoc_daysixteen(input: &str) {
-    let vals = parse_daysixteen_input(input);
-    let freq = process_frequencies(&vals, 100, 0, false);
-    println!("First 8 Vals: {:?}", freq.get(..8));
+struct AocDaySixteen;
+
+impl Solver for AocDaySixteen {
+    type Input = Vec<i32>;
+
+    fn parse(&self, input: &str) -> PuzzleResult<Vec<i32>> {
+        let input = input.trim();
+        if input.len() < 8 || !input.chars().all(|c| c.is_ascii_digit()) {
+            return Err("expected a signal of at least 8 digits".into());
+        }
+        Ok(parse_daysixteen_input(input))
+    }
+
+    fn part1(&self, vals: &Vec<i32>) -> PuzzleResult<Answer> {
+        let freq = process_frequencies(vals, 100, 0, false);
+        Ok(digits_to_string(&freq[..8]).into())
+    }
+
+    fn part2(&self, vals: &Vec<i32>) -> PuzzleResult<Answer> {
+        aoc_daysixteen_parttwo(vals)
+    }
 }
 
-fn aoc_daysixteen_parttwo(input: &str) {
-    let vals = parse_daysixteen_input(input);
+fn digits_to_string(digits: &[i32]) -> String {
+    digits.iter().map(|d| d.to_string()).collect()
+}
+
+fn aoc_daysixteen_parttwo(vals: &[i32]) -> PuzzleResult<Answer> {
     let mut vals_repeat = Vec::with_capacity(vals.len() * 10000);
     for _ in 0..10000 {
-        vals_repeat.extend_from_slice(vals.as_slice());
+        vals_repeat.extend_from_slice(vals);
     }    
-    let offset = compute_offset(&vals);
+    let offset = compute_offset(vals);
+    // the shortcut only works for the back half of the signal
+    if offset < vals_repeat.len() / 2 || offset + 8 > vals_repeat.len() {
+        return Err(format!("message offset {} isn't in the back half of the signal", offset).into());
+    }
     let new_freq = process_frequencies(&vals_repeat, 100, offset, true);
 
-    println!("8 Vals: {:?}", new_freq.get(offset..(offset+8)));
+    Ok(digits_to_string(&new_freq[offset..offset + 8]).into())
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use aoc_registry::Puzzle;
 
     #[test]
     fn fuel_req_test() {
@@ -1506,72 +2236,93 @@ mod tests {
     }
 
     #[test]
-    fn simple_intopcode_test() {
-        assert_eq!( process_simple_intopcode(&vec![1,9,10,3,2,3,11,0,99,30,40,50]), vec![3500,9,10,70,2,3,11,0,99,30,40,50]);
-        assert_eq!( process_simple_intopcode(&vec![1,0,0,0,99]), vec![2,0,0,0,99]);
-        assert_eq!( process_simple_intopcode(&vec![2,3,0,3,99]), vec![2,3,0,6,99]);
-        assert_eq!( process_simple_intopcode(&vec![2,4,4,5,99,0]), vec![2,4,4,5,99,9801]);
-        assert_eq!( process_simple_intopcode(&vec![1,1,1,4,99,5,6,0,99]), vec![30,1,1,4,2,5,6,0,99]);
-        assert_eq!( process_simple_intopcode(&vec![1002,4,3,4,33]), vec![1002,4,3,4,99]);
-        assert_eq!( process_simple_intopcode(&vec![1101,100,-1,4,0]), vec![1101,100,-1,4,99]);
+    fn config_test() {
+        let args = |line: &str| -> Vec<String> { line.split_whitespace().map(String::from).collect() };
+        let config = Config::new(&args("aoc --day 9 --part 2 aocday9.txt")).unwrap();
+        assert!( matches!(config.command, Command::Puzzle(9, Some(2), OutputFormat::Text)) );
+        assert_eq!( config.input_filename, "aocday9.txt" );
+        assert!( matches!(Config::new(&args("aoc --day 7 --format json -")).unwrap().command, Command::Puzzle(7, None, OutputFormat::Json)) );
+        assert!( matches!(Config::new(&args("aoc --part 1 --day 2 x.txt")).unwrap().command, Command::Puzzle(2, Some(1), OutputFormat::Text)) );
+        let config = Config::new(&args("aoc aocday2.txt --day 2")).unwrap();
+        assert!( matches!(config.command, Command::Puzzle(2, None, OutputFormat::Text)) );
+        assert_eq!( config.input_filename, "aocday2.txt" );
+        let config = Config::new(&args("aoc all")).unwrap();
+        assert!( matches!(config.command, Command::AllPuzzles(OutputFormat::Text)) );
+        assert_eq!( config.input_filename, "." );
+        let config = Config::new(&args("aoc all --format csv inputs")).unwrap();
+        assert!( matches!(config.command, Command::AllPuzzles(OutputFormat::Csv)) );
+        assert_eq!( config.input_filename, "inputs" );
+        let config = Config::new(&args("aoc bench --iterations 3 --no-history 4 9")).unwrap();
+        assert!( matches!(config.command, Command::Benchmark(3, None, ref days) if days == &[4, 9]) );
+
+        let error = |line: &str| Config::new(&args(line)).err().unwrap();
+        assert_eq!( error("aoc bench --iterations 0"), "--iterations needs a positive number" );
+        assert_eq!( error("aoc bench 15"), "day 15 isn't solved" );
+        assert_eq!( error("aoc --day 15 x.txt"), "day 15 isn't solved, solved days are 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 16" );
+        assert_eq!( error("aoc --day x x.txt"), "--day needs a day number, not 'x'" );
+        assert_eq!( error("aoc --day 2 --part 3 x.txt"), "--part is 1 or 2, not '3'" );
+        assert_eq!( error("aoc --day 2"), "day 2 needs an input file, or - for stdin" );
+        assert_eq!( error("aoc --day 2 a.txt b.txt"), "unexpected argument 'b.txt'" );
+        assert_eq!( error("aoc all --format yaml"), "--format is json, csv or text, not 'yaml'" );
+        assert_eq!( error("aoc profile x.txt 1 two"), "profile input values must be integers" );
+        for seconds in ["-1", "inf", "NaN", "1e30", "x"] {
+            assert_eq!( error(&format!("aoc run x.txt --seconds {}", seconds)), "--seconds needs a number of seconds" );
+        }
+        assert!( matches!(Config::new(&args("aoc run x.txt --seconds 0.5")).unwrap().command, Command::Run(budget, None, _) if budget.max_duration == Some(std::time::Duration::from_millis(500))) );
+        assert!( error("aoc 9 aocday5.txt").starts_with("puzzles are picked with --day and --part now, not '9'") );
+        assert!( error("aoc nine aocday5.txt").starts_with("unknown command 'nine'") );
+        assert!( error("aoc").starts_with("not enough arguments") );
     }
 
     #[test]
-    fn leq_intopcode_test() {
-        {
-            let test = vec![3,9,8,9,10,9,4,9,99,-1,8]; // 1 if equal to 8, 0 otherwise
-            assert_eq!( get_intopcode_output(&test, 8), vec![1] );
-            assert_eq!( get_intopcode_output(&test, 7), vec![0] );
-            assert_eq!( get_intopcode_output(&test, 9), vec![0] );
-        }
-        
-        {
-            let test = vec![3,9,7,9,10,9,4,9,99,-1,8]; // 1 if less than 8, 0 otherwise
-            assert_eq!( get_intopcode_output(&test, 7), vec![1] );
-            assert_eq!( get_intopcode_output(&test, 8), vec![0] );
-            assert_eq!( get_intopcode_output(&test, 9), vec![0] );
-        }
-        
-        {
-            let test = vec![3,3,1108,-1,8,3,4,3,99]; // 1 if equal to 8, 0 otherwise (immediate)
-            assert_eq!( get_intopcode_output(&test, 8), vec![1] );
-            assert_eq!( get_intopcode_output(&test, 7), vec![0] );
-            assert_eq!( get_intopcode_output(&test, 9), vec![0] );
-        }
-        
-        {
-            let test = vec![3,3,1107,-1,8,3,4,3,99]; // 1 if less than 8, 0 otherwise (immediate)
-            assert_eq!( get_intopcode_output(&test, 7), vec![1] );
-            assert_eq!( get_intopcode_output(&test, 8), vec![0] );
-            assert_eq!( get_intopcode_output(&test, 9), vec![0] );
-        }
+    fn intopcode_error_test() {
+        assert_eq!( process_simple_intopcode(&[1,0,0,0,42]), Err(IntOpCodeError::UnknownOpcode { ip: 4, opcode: 42 }) );
+        assert_eq!( process_simple_intopcode(&[1,0,0,0,1]), Err(IntOpCodeError::UnknownOpcode { ip: 8, opcode: 0 }) ); // pointer runs off into empty memory
+        assert_eq!( process_simple_intopcode(&[3001,0,0,0,99]), Err(IntOpCodeError::UnknownParameterMode { ip: 0, opcode: 3001, parameter: 2, mode: 3 }) );
+        assert_eq!( process_simple_intopcode(&[1,-5,0,0,99]), Err(IntOpCodeError::NegativeAddress { ip: 0, opcode: 1, parameter: 1, address: -5 }) );
+        assert_eq!( process_simple_intopcode(&[109,-3,21101,1,1,0,99]), Err(IntOpCodeError::NegativeAddress { ip: 2, opcode: 21101, parameter: 3, address: -3 }) );
+        assert_eq!( process_simple_intopcode(&[1105,1,-1]), Err(IntOpCodeError::NegativeAddress { ip: 0, opcode: 1105, parameter: 2, address: -1 }) );
+        assert_eq!( process_simple_intopcode(&[1102,i64::MAX,2,0,99]), Err(IntOpCodeError::Overflow { ip: 0, opcode: 1102 }) );
+        assert_eq!( process_simple_intopcode(&[30099]), Ok(vec![30099]) ); // mode digits of parameters an opcode doesn't have are ignored
+
+        // harnesses pass the error along instead of aborting
+        assert_eq!( process_amp_intopcode_with_phases(&[3,0,3,0,42], &[0,1], false), Err(IntOpCodeError::UnknownOpcode { ip: 4, opcode: 42 }) );
+        assert_eq!( IntOpCodeError::UnknownOpcode { ip: 4, opcode: 42 }.to_string(), "unknown opcode 42 at ip 4" );
     }
 
     #[test]
-    fn jump_intopcode_test() {
-        // Here are some jump tests that take an input,
-        // then output 0 if the input was zero or 1 if the input was non-zero:
-        {
-            let test = vec![3,12,6,12,15,1,13,14,13,4,13,99,-1,0,1,9];
-            assert_eq!( get_intopcode_output(&test, 0), vec![0] );
-            assert_eq!( get_intopcode_output(&test, 10), vec![1] );
-        }
+    fn memory_backend_test() {
+        use intopcode_memory::{PagedMemory, SparseMemory, PAGE_SIZE};
 
-        {
-            let test = vec![3,3,1105,-1,9,1101,0,0,12,4,12,99,1];
-            assert_eq!( get_intopcode_output(&test, 0), vec![0] );
-            assert_eq!( get_intopcode_output(&test, 10), vec![1] );
+        // Reads 1, stores 41 at a far address, doubles it and outputs the value read back from there
+        let test = vec![3,13,1101,40,1,1000000000000,102,2,1000000000000,1000000000000,4,1000000000000,99,0];
+        for memory in [Box::new(PagedMemory::new(&test, Some(4 * PAGE_SIZE)).unwrap()) as Box<dyn IntOpCodeMemory>, Box::new(SparseMemory::new(&test, Some(64)).unwrap())] {
+            let mut comp = IntOpCodeComp::with_memory(memory, &VecDeque::from(vec![1]));
+            assert_eq!( comp.tick_to_completion().unwrap(), &VecDeque::from(vec![82]) );
+            assert_eq!( comp.memory().len(), 1000000000001 );
+            assert!( comp.memory().cells_used() <= 4 * PAGE_SIZE );
         }
+
+        // The default dense memory refuses instead of trying to allocate terabytes
+        assert_eq!( process_simple_intopcode(&test[2..]), Err(IntOpCodeError::MemoryLimitExceeded { ip: 0, address: 1000000000000, limit: DEFAULT_MEMORY_LIMIT }) );
     }
 
     #[test]
-    fn complex_jump_intopcode_test() {
-        let test = vec![3,21,1008,21,8,20,1005,20,22,107,8,21,20,1006,20,31,1106,0,36,98,0,0,1002,21,125,20,4,20,1105,1,46,104,999,1105,1,46,1101,1000,1,20,4,20,1105,1,46,98,99];
-        assert_eq!( get_intopcode_output(&test, 0), vec![999] );
-        assert_eq!( get_intopcode_output(&test, 7), vec![999] );
-        assert_eq!( get_intopcode_output(&test, 8), vec![1000] );
-        assert_eq!( get_intopcode_output(&test, 9), vec![1001] );
-        assert_eq!( get_intopcode_output(&test, 15), vec![1001] );
+    fn run_until_event_test() {
+        let test = vec![3,11,4,11,104,7,3,11,4,11,99,0];  // echo an input, output 7, echo another input
+        let mut comp = IntOpCodeComp::new(&test, &VecDeque::new());
+        assert_eq!( comp.run_until_event(), Ok(RunEvent::InputNeeded) );
+        assert_eq!( comp.state, RunState::WaitingOnInput );
+        assert_eq!( comp.run_until_event(), Ok(RunEvent::InputNeeded) ); // still nothing to read
+        comp.input_values.push_back(5);
+        assert_eq!( comp.run_until_event(), Ok(RunEvent::Output(5)) );
+        assert_eq!( comp.run_until_event(), Ok(RunEvent::Output(7)) );
+        assert!( comp.output_values.is_empty() );
+        comp.input_values.push_back(9);
+        assert_eq!( comp.run_until_event(), Ok(RunEvent::Output(9)) );
+        assert_eq!( comp.run_until_event(), Ok(RunEvent::Halted) );
+        assert_eq!( comp.state, RunState::Halted );
+        assert_eq!( comp.run_until_event(), Ok(RunEvent::Halted) );
     }
 
     #[test]
@@ -1584,7 +2335,8 @@ mod tests {
         U98,R91,D20,R16,D67,R40,U7,R15,U6,R7", 135, 410 )];
 
         for test in tests.iter() {
-            let dist = compute_min_dist(&test.0);
+            let (wire1, wire2) = parse_wires(test.0).unwrap();
+            let dist = compute_min_dist(&wire1, &wire2);
             assert_eq!( dist.0, test.1 );
             assert_eq!( dist.1, test.2 );
         }
@@ -1592,26 +2344,26 @@ mod tests {
 
     #[test]
     fn aoc_dayfour_valid_password_test() {
-        assert_eq!( vet_password(122345, false), true );
-        assert_eq!( vet_password(122345, true), true );
-        assert_eq!( vet_password(111123, false), true );
-        assert_eq!( vet_password(111123, true), false ); // fails part 2, no pair
-        assert_eq!( vet_password(135679, false), false ); // no dupe
-        assert_eq!( vet_password(135679, true), false ); // no dupe
-        assert_eq!( vet_password(111111, false), true ); // no dupe
-        assert_eq!( vet_password(111111, true), false ); // fails part 2, no pair
-        assert_eq!( vet_password(223450, false), false ); // not decreasing
-        assert_eq!( vet_password(223450, true), false ); // not decreasing
-        assert_eq!( vet_password(123789, false), false ); // no double
-        assert_eq!( vet_password(123789, true), false ); // no double
-        assert_eq!( vet_password(1000000, false), false ); // too large
-        assert_eq!( vet_password(1000000, true), false ); // too large
-        assert_eq!( vet_password(112233, false), true );
-        assert_eq!( vet_password(112233, true), true );
-        assert_eq!( vet_password(123444, false), true );
-        assert_eq!( vet_password(123444, true), false ); // fails part 2, no pair
-        assert_eq!( vet_password(111122, false), true );
-        assert_eq!( vet_password(111122, true), true );
+        assert!( vet_password(122345, false) );
+        assert!( vet_password(122345, true) );
+        assert!( vet_password(111123, false) );
+        assert!( !vet_password(111123, true) ); // fails part 2, no pair
+        assert!( !vet_password(135679, false) ); // no dupe
+        assert!( !vet_password(135679, true) ); // no dupe
+        assert!( vet_password(111111, false) ); // no dupe
+        assert!( !vet_password(111111, true) ); // fails part 2, no pair
+        assert!( !vet_password(223450, false) ); // not decreasing
+        assert!( !vet_password(223450, true) ); // not decreasing
+        assert!( !vet_password(123789, false) ); // no double
+        assert!( !vet_password(123789, true) ); // no double
+        assert!( !vet_password(1000000, false) ); // too large
+        assert!( !vet_password(1000000, true) ); // too large
+        assert!( vet_password(112233, false) );
+        assert!( vet_password(112233, true) );
+        assert!( vet_password(123444, false) );
+        assert!( !vet_password(123444, true) ); // fails part 2, no pair
+        assert!( vet_password(111122, false) );
+        assert!( vet_password(111122, true) );
     }
 
     #[test]
@@ -1628,7 +2380,7 @@ mod tests {
         J)K
         K)L";
 
-        let (orbits, planets) = parse_orbits_and_planets(input);
+        let (orbits, planets) = parse_orbits_and_planets(input).unwrap();
         assert_eq!( orbits, [("COM", "B"), ("B", "C"), ("C", "D"), ("D","E"), ("E","F"), ("B","G"), ("G","H"), ("D","I"), ("E","J"), ("J","K"), ("K","L")] );
         assert_eq!( compute_orbits( &orbits, "D" ), 3 );
         assert_eq!( compute_orbits( &orbits, "L" ), 7 );
@@ -1652,46 +2404,7 @@ mod tests {
         K)YOU
         I)SAN";
  
-        assert_eq!(orbital_distance(input, "YOU", "SAN"), 4);
-    }
-
-    #[test]
-    fn test_amp() {
-        {
-            let test = vec![3,15,3,16,1002,16,10,16,1,16,15,15,4,15,99,0,0];
-            let phases = vec![4,3,2,1,0];
-            assert_eq!(process_amp_intopcode_with_phases(&test, &phases, false), 43210);
-        }    
-
-        {
-            let test = vec![3,23,3,24,1002,24,10,24,1002,23,-1,23,101,5,23,23,1,24,23,23,4,23,99,0,0];
-            let phases = vec![0,1,2,3,4];
-            assert_eq!(process_amp_intopcode_with_phases(&test, &phases, false), 54321);
-        }
-
-        {
-            let test = vec![3,31,3,32,1002,32,10,32,1001,31,-2,31,1007,31,0,33,
-                1002,33,7,33,1,33,31,31,1,32,31,31,4,31,99,0,0,0];
-            let phases = vec![1,0,4,3,2];
-            assert_eq!(process_amp_intopcode_with_phases(&test, &phases, false), 65210);
-        }
-    }
-
-    #[test]
-    fn test_amp_feedback() {
-        {
-            let test = vec![3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5];
-            let phases = vec![9,8,7,6,5];
-            assert_eq!(process_amp_intopcode_with_phases(&test, &phases, true), 139629729);
-        }    
-
-        {
-            let test = vec![3,52,1001,52,-5,52,3,53,1,52,56,54,1007,54,5,55,1005,55,26,1001,54,
-                -5,54,1105,1,12,1,53,54,53,1008,54,0,55,1001,55,1,55,2,53,55,53,4,
-                53,1001,56,-1,56,1005,56,6,99,0,0,0,0,10];
-            let phases = vec![9,7,8,5,6];
-            assert_eq!(process_amp_intopcode_with_phases(&test, &phases, true), 18216);
-        }
+        assert_eq!(orbital_distance(input, "YOU", "SAN").unwrap(), 4);
     }
 
     #[test]
@@ -1699,18 +2412,18 @@ mod tests {
         let phases = [0,1,2,3,4];
         {
             let test = vec![3,15,3,16,1002,16,10,16,1,16,15,15,4,15,99,0,0];
-            assert_eq!(compute_max_amp_intopcode(&test, &phases, false), 43210);
+            assert_eq!(find_best_phases(&test, &phases, false), Ok(Some(PhaseSetting { phases: vec![4,3,2,1,0], output: 43210 })));
         }    
 
         {
             let test = vec![3,23,3,24,1002,24,10,24,1002,23,-1,23,101,5,23,23,1,24,23,23,4,23,99,0,0];
-            assert_eq!(compute_max_amp_intopcode(&test, &phases, false), 54321);
+            assert_eq!(find_best_phases(&test, &phases, false), Ok(Some(PhaseSetting { phases: vec![0,1,2,3,4], output: 54321 })));
         }
 
         {
             let test = vec![3,31,3,32,1002,32,10,32,1001,31,-2,31,1007,31,0,33,
                 1002,33,7,33,1,33,31,31,1,32,31,31,4,31,99,0,0,0];
-            assert_eq!(compute_max_amp_intopcode(&test, &phases, false), 65210);
+            assert_eq!(find_best_phases(&test, &phases, false), Ok(Some(PhaseSetting { phases: vec![1,0,4,3,2], output: 65210 })));
         }
     }
 
@@ -1719,16 +2432,34 @@ mod tests {
         let phases = [5,6,7,8,9];
         {
             let test = vec![3,26,1001,26,-4,26,3,27,1002,27,2,27,1,27,26,27,4,27,1001,28,-1,28,1005,28,6,99,0,0,5];
-            assert_eq!(compute_max_amp_intopcode(&test, &phases, true), 139629729);
+            assert_eq!(find_best_phases(&test, &phases, true), Ok(Some(PhaseSetting { phases: vec![9,8,7,6,5], output: 139629729 })));
         }    
         {
             let test = vec![3,52,1001,52,-5,52,3,53,1,52,56,54,1007,54,5,55,1005,55,26,1001,54,
                 -5,54,1105,1,12,1,53,54,53,1008,54,0,55,1001,55,1,55,2,53,55,53,4,
                 53,1001,56,-1,56,1005,56,6,99,0,0,0,0,10];
-            assert_eq!(compute_max_amp_intopcode(&test, &phases, true), 18216);
+            assert_eq!(find_best_phases(&test, &phases, true), Ok(Some(PhaseSetting { phases: vec![9,7,8,5,6], output: 18216 })));
         }    
     }
 
+    #[test]
+    fn test_best_phases_any_length() {
+        assert_eq!(nth_phase_permutation(&[1,2,3], 0), vec![1,2,3]);
+        assert_eq!(nth_phase_permutation(&[1,2,3], 3), vec![2,3,1]);
+        assert_eq!(nth_phase_permutation(&[1,2,3], 5), vec![3,2,1]);
+
+        // Each amplifier outputs input * 10 + phase, so the phases end up as the digits of the output
+        let test = &[3,15,3,16,1002,16,10,16,1,16,15,15,4,15,99,0,0];
+        assert_eq!(find_best_phases(test, &[3,1,2], false), Ok(Some(PhaseSetting { phases: vec![3,2,1], output: 321 })));
+        assert_eq!(find_best_phases(test, &[0,1,2,3,4,5,6,7], false), Ok(Some(PhaseSetting { phases: vec![7,6,5,4,3,2,1,0], output: 76543210 })));
+        assert_eq!(find_best_phases(test, &[], false), Ok(None));
+        assert_eq!(find_best_phases(&[3,0,3,0,42], &[0,1], false), Err(PhaseSearchError::Program(IntOpCodeError::UnknownOpcode { ip: 4, opcode: 42 })));
+        let phases : Vec<i64> = (0..21).collect();
+        assert_eq!(find_best_phases(test, &phases, false), Err(PhaseSearchError::TooManyPhases(21)));
+        let phases : Vec<i64> = (0..22).collect();  // 21! orderings start with each phase, more than a usize holds
+        assert_eq!(nth_phase_permutation(&phases, usize::MAX)[..2], [0, 8]);
+    }
+
     #[test]
     fn test_parse_elf_images() {
         let input = "123456789012";
@@ -1754,22 +2485,6 @@ mod tests {
         assert_eq!(elf_image.decode_image(), vec![0,1,1,0] );
     }
 
-    #[test]
-    fn aoc_day_nine_tests() {
-        {
-            let test = vec![109,1,204,-1,1001,100,1,100,1008,100,16,101,1006,101,0,99];
-            assert_eq!(get_intopcode_output(&test, 0), test);
-        }
-        {
-            let test = vec![1102,34915192,34915192,7,4,7,99,0];
-            assert_eq!(get_intopcode_output(&test, 0), vec![1219070632396864]);
-        }
-        {
-            let test = vec![104,1125899906842624,99];
-            assert_eq!(get_intopcode_output(&test, 0), vec![1125899906842624]);
-        }
-    }
-
     #[test]
     fn parse_asteroid_field_test() {
         let input = ".#..#
@@ -1778,7 +2493,7 @@ mod tests {
         ....#
         ...##";
 
-        let asteroids = parse_asteroid_field(&input);
+        let asteroids = parse_asteroid_field(input);
         assert_eq!(asteroids.len(), 10);
         assert_eq!(asteroids[0].pos, Vector2::new(1, 0));
         assert_eq!(asteroids[9].pos, Vector2::new(4, 4));
@@ -1792,7 +2507,7 @@ mod tests {
         ....#
         ...##";
 
-        let asteroids = parse_asteroid_field(&input);
+        let asteroids = parse_asteroid_field(input);
         assert_eq!(compute_asteroid_los_hashmap(&asteroids[0], &asteroids).keys().count(), 7);
         assert_eq!(compute_all_asteroid_los_count(&asteroids), vec![7,7,6,7,7,7,5,7,8,7]);
         assert_eq!(compute_max_asteroid_los_count(&asteroids).1, 8);
@@ -1812,7 +2527,7 @@ mod tests {
             ##...#..#.
             .#....####";
 
-            let asteroids = parse_asteroid_field(&input);
+            let asteroids = parse_asteroid_field(input);
             let a = Asteroid{ pos: Vector2::new(5, 8)};
             assert_eq!(compute_asteroid_los_hashmap(&a, &asteroids).keys().count(), 33);
             let (a, count) = compute_max_asteroid_los_count(&asteroids);
@@ -1831,7 +2546,7 @@ mod tests {
             ..##....##
             ......#...
             .####.###.";
-            let asteroids = parse_asteroid_field(&input);
+            let asteroids = parse_asteroid_field(input);
             let a = Asteroid{ pos: Vector2::new(1, 2)};
             assert_eq!(compute_asteroid_los_hashmap(&a, &asteroids).keys().count(), 35);
             assert_eq!(compute_max_asteroid_los_count(&asteroids).1, 35);
@@ -1848,7 +2563,7 @@ mod tests {
             #..#.#.###
             .##...##.#
             .....#.#..";
-            let asteroids = parse_asteroid_field(&input);
+            let asteroids = parse_asteroid_field(input);
             let a = Asteroid{ pos: Vector2::new(6, 3)};
             assert_eq!(compute_asteroid_los_hashmap(&a, &asteroids).keys().count(), 41);
         }
@@ -1877,7 +2592,7 @@ mod tests {
         #.#.#.#####.####.###
         ###.##.####.##.#..##";
 
-        let asteroids = parse_asteroid_field(&input);
+        let asteroids = parse_asteroid_field(input);
         let a = Asteroid{ pos: Vector2::new(11, 13)};
         assert_eq!(compute_asteroid_los_hashmap(&a, &asteroids).keys().count(), 210);
         let asteroid_do = compute_asteroid_destruction_order(&a, &asteroids);
@@ -1892,22 +2607,26 @@ mod tests {
         assert_eq!(asteroid_do[199].pos, Vector2::new(8,2));
         assert_eq!(asteroid_do[200].pos, Vector2::new(10,9));
         assert_eq!(asteroid_do[298].pos, Vector2::new(11,1));
+
+        let results = AocDayTen.solve_detailed(input, &[2], true).unwrap();
+        assert_eq!(results[0].answer, Answer::Number(802));
+        assert_eq!(results[0].details, vec![("station", String::from("11,13")), ("200th", String::from("8,2"))]);
     }
 
     #[test]
     fn moon_simulation_test()
     {
         {
-            let moon0 = Moon::new("<x=-1, y=0, z=2>");
+            let moon0 = Moon::new("<x=-1, y=0, z=2>").unwrap();
             assert_eq!(moon0.pos, Vector3::new(-1, 0, 2));
             assert_eq!(moon0.vel, Vector3::new(0, 0, 0));
         }
 
         {
-            let mut moons = vec![Moon::new("<x=-1, y=0, z=2>"),
-                                 Moon::new("<x=2, y=-10, z=-7>"),
-                                 Moon::new("<x=4, y=-8, z=8>"),
-                                 Moon::new("<x=3, y=5, z=-1>")];
+            let mut moons = vec![Moon::new("<x=-1, y=0, z=2>").unwrap(),
+                                 Moon::new("<x=2, y=-10, z=-7>").unwrap(),
+                                 Moon::new("<x=4, y=-8, z=8>").unwrap(),
+                                 Moon::new("<x=3, y=5, z=-1>").unwrap()];
             step_moon_simulation(&mut moons);
             assert_eq!(moons[0].vel, Vector3::new(3, -1, -1));
             assert_eq!(moons[0].pos, Vector3::new(2, -1, 1));
@@ -1952,7 +2671,7 @@ mod tests {
 
     #[test]
     fn reaction_input_test() {
-        let reaction = parse_reaction("59 CQGW, 15 MSNG, 6 XGKRF, 10 LJRQ, 1 HRKGV, 15 RKVC => 1 FUEL");
+        let reaction = parse_reaction("59 CQGW, 15 MSNG, 6 XGKRF, 10 LJRQ, 1 HRKGV, 15 RKVC => 1 FUEL").unwrap();
         assert_eq!(reaction, Reaction { inputs: vec![(59, String::from("CQGW")), 
                                                      (15, String::from("MSNG")),
                                                      (6, String::from("XGKRF")),
@@ -1970,15 +2689,54 @@ mod tests {
         7 A, 1 C => 1 D
         7 A, 1 D => 1 E
         7 A, 1 E => 1 FUEL";
-        let hashmap = parse_dayfourteeninput(input);
+        let hashmap = parse_dayfourteeninput(input).unwrap();
         assert_eq!(ore_needed_for_fuel(&hashmap, 1), 31);
     }
 
+    #[test]
+    fn reaction_max_fuel_test() {
+        let input = "157 ORE => 5 NZVS
+        165 ORE => 6 DCFZ
+        44 XJWVT, 5 KHKGT, 1 QDVJ, 29 NZVS, 9 GPVTF, 48 HKGWZ => 1 FUEL
+        12 HKGWZ, 1 GPVTF, 8 PSHF => 9 QDVJ
+        179 ORE => 7 PSHF
+        177 ORE => 5 HKGWZ
+        7 DCFZ, 7 PSHF => 2 XJWVT
+        165 ORE => 2 GPVTF
+        3 DCFZ, 7 NZVS, 5 HKGWZ, 10 PSHF => 8 KHKGT";
+        let answers = AocDayFourteen.solve(input, &[1, 2]).unwrap();
+        assert_eq!( answers, vec![Answer::Number(13312), Answer::Number(82892753)] );
+    }
+
+    #[test]
+    fn short_program_test() {
+        let error = |day: u32, input: &str| Registry::all_days().solve(day, input, &[1, 2]).unwrap_err().to_string();
+        assert_eq!( error(2, "1,0"), "the program is too short to have a noun and verb at addresses 1 and 2" );
+        assert_eq!( error(13, ""), "the input has no intopcodes" );
+        assert_eq!( error(9, " \n"), "the input has no intopcodes" );
+    }
+
+    #[test]
+    fn invalid_input_test() {
+        let error = |day: u32, input: &str| Registry::all_days().solve(day, input, &[1, 2]).unwrap_err().to_string();
+        assert_eq!( error(3, "R8,U5"), "expected two wires, one per line, found 1" );
+        assert_eq!( error(3, "R8,U5\nX7,R6"), "invalid wire step 'X7', expected a direction R, L, U or D and a distance" );
+        assert_eq!( error(3, "R8,U\nU7,R6"), "invalid wire step 'U', expected a direction R, L, U or D and a distance" );
+        assert_eq!( error(6, "COM)B\nB"), "expected an orbit like 'COM)B', found 'B'" );
+        assert_eq!( error(6, "COM)B\n)C"), "expected an orbit like 'COM)B', found ')C'" );
+        assert_eq!( error(12, "<x=1, y=2>"), "expected a moon like '<x=12, y=0, z=-15>', found '<x=1, y=2>'" );
+        assert_eq!( error(12, "<x=1, y=2, z=three>"), "expected a moon like '<x=12, y=0, z=-15>', found '<x=1, y=2, z=three>'" );
+        assert_eq!( error(14, "10 ORE => 10 A\n7 A, 1 B => 1 FUEL"), "no reaction makes B" );
+        assert_eq!( error(14, "10 ORE => 0 FUEL"), "expected a reaction like '7 A, 1 B => 1 C', found '10 ORE => 0 FUEL'" );
+        assert_eq!( error(14, " => 1 FUEL"), "expected a reaction like '7 A, 1 B => 1 C', found '=> 1 FUEL'" );
+        assert_eq!( error(14, "10 ORE, 1 A => 1 FUEL\n1 ORE => 1 A"), "the reaction making FUEL mixes ORE with other inputs" );
+    }
+
     #[test]
     fn test_day_sixteen_input() {
         let freq_0 = parse_daysixteen_input("12345678");
         assert_eq!( freq_0, vec![1, 2, 3, 4, 5, 6, 7, 8]);
-        let freq_1 = process_frequencies(&freq_0, 1, 0); 
+        let freq_1 = process_frequencies(&freq_0, 1, 0, false);
         assert_eq!( freq_1, vec![4,8,2,2,6,1,5,8]);
         // let freq_2 = process_frequencies(&freq_1, 1, 0);
         // assert_eq!( freq_2, vec![3,4,0,4,0,4,3,8]);
@@ -1992,7 +2750,7 @@ mod tests {
     #[test]
     fn test_day_sixteen_additional() {
         let freq_0 = parse_daysixteen_input("80871224585914546619083218645595");
-        let freq_100 = process_frequencies(&freq_0, 100, 0);
+        let freq_100 = process_frequencies(&freq_0, 100, 0, false);
         assert_eq!( freq_100.split_at(8).0, [2,4,1,7,6,1,7,6]);
     }
 
@@ -2005,8 +2763,8 @@ mod tests {
             freq_repeat.extend_from_slice(freq_0.as_slice());
         }
 
-        let freq_100 = process_frequencies(&freq_repeat, 1, freq_offset);
-        assert_eq!( freq_100.get(freq_offset..(freq_offset+1)).unwrap(), [8, 4, 4, 6, 2, 0, 2, 6] );
+        let freq_100 = process_frequencies(&freq_repeat, 100, freq_offset, true);
+        assert_eq!( freq_100.get(freq_offset..(freq_offset+8)).unwrap(), [8, 4, 4, 6, 2, 0, 2, 6] );
     
         //    02935109699940807407585447034323 becomes 78725270.
         //03081770884921959731165446850517 becomes 53553731.